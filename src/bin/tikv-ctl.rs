@@ -578,7 +578,7 @@ mod tests {
             })
             .collect();
         let lock_value: Vec<_> = test_data_lock.iter()
-            .map(|data| Lock::new(data.1, data.2.to_vec(), data.3, 0, None).to_bytes())
+            .map(|data| Lock::new(data.1, data.2.to_vec(), data.3, 0, None, 0).to_bytes())
             .collect();
         let kvs = keys.iter().zip(lock_value.iter());
         let lock_cf = db.cf_handle(CF_LOCK).unwrap();
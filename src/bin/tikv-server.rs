@@ -59,7 +59,9 @@ use rocksdb::{DBOptions, ColumnFamilyOptions, BlockBasedOptions};
 use fs2::FileExt;
 use sys_info::{cpu_num, mem_info};
 
-use tikv::storage::{TEMP_DIR, CF_DEFAULT, CF_LOCK, CF_WRITE, CF_RAFT};
+use tikv::storage::{TEMP_DIR, CF_DEFAULT, CF_LOCK, CF_WRITE, CF_RAFT, CF_RAW};
+use tikv::storage::gc_filter::GcContext;
+use tikv::storage::max_read_ts::MaxReadTsSyncer;
 use tikv::util::{self, panic_hook, rocksdb as rocksdb_util};
 use tikv::util::collections::HashMap;
 use tikv::util::logger::{self, StderrLogger};
@@ -70,6 +72,9 @@ use tikv::server::{DEFAULT_LISTENING_ADDR, DEFAULT_CLUSTER_ID, Server, Node, Con
                    create_raft_storage};
 use tikv::server::transport::ServerRaftStoreRouter;
 use tikv::server::resolve;
+use tikv::server::gc_worker::GcWorker;
+use tikv::cdc::Endpoint as CdcEndpoint;
+use tikv::raftstore::coprocessor::CoprocessorHost;
 use tikv::raftstore::store::{self, SnapManager};
 use tikv::pd::{RpcClient, PdClient};
 use tikv::raftstore::store::keys::region_raft_prefix_len;
@@ -82,8 +87,8 @@ const RAFTCF_MIN_MEM: u64 = 256 * MB;
 const RAFTCF_MAX_MEM: u64 = 2 * GB;
 const LOCKCF_MIN_MEM: u64 = 256 * MB;
 const LOCKCF_MAX_MEM: u64 = GB;
-// [default cf, write cf, raft cf, lock cf]
-const DEFAULT_BLOCK_CACHE_RATIO: &'static [f64] = &[0.25, 0.15, 0.02, 0.02];
+// [default cf, write cf, raft cf, lock cf, raw cf]
+const DEFAULT_BLOCK_CACHE_RATIO: &'static [f64] = &[0.25, 0.15, 0.02, 0.02, 0.05];
 const SEC_TO_MS: i64 = 1000;
 
 fn sanitize_memory_usage() -> bool {
@@ -650,6 +655,20 @@ fn get_rocksdb_lock_cf_option(config: &toml::Value, total_mem: u64) -> ColumnFam
     cf_opts
 }
 
+fn get_rocksdb_raw_cf_option(config: &toml::Value, total_mem: u64) -> ColumnFamilyOptions {
+    let mut default_values = CfOptValues::default();
+    default_values.block_cache_size =
+        align_to_mb((total_mem as f64 * DEFAULT_BLOCK_CACHE_RATIO[4]) as u64) as i64;
+    default_values.use_bloom_filter = true;
+    default_values.whole_key_filtering = true;
+    default_values.compaction_pri = 3;
+
+    let mut cf_opts = get_rocksdb_cf_option(config, "rawcf", default_values);
+    let f = Box::new(SizePropertiesCollectorFactory::default());
+    cf_opts.add_table_properties_collector_factory("tikv.size-properties-collector", f);
+    cf_opts
+}
+
 fn adjust_block_cache_size(cache_size: u64, min_limit: u64, max_limit: u64) -> u64 {
     if cache_size < min_limit {
         return min_limit;
@@ -701,6 +720,9 @@ fn build_cfg(matches: &ArgMatches,
     cfg_usize(&mut cfg.messages_per_tick,
               config,
               "server.messages-per-tick");
+    cfg_u64(&mut cfg.cdc_resolved_ts_interval,
+            config,
+            "server.cdc-resolved-ts-interval");
     let capacity = get_flag_int(matches, "capacity")
         .or_else(|| get_toml_int_opt(config, "server.capacity"));
     if let Some(cap) = capacity {
@@ -745,6 +767,25 @@ fn build_cfg(matches: &ArgMatches,
     cfg_u64(&mut cfg.raft_store.region_check_size_diff,
             config,
             "raftstore.region-split-check-diff");
+    cfg.raft_store.split_region_check_approximate =
+        get_toml_boolean(config, "raftstore.split-region-check-approximate", Some(false));
+    cfg_u64(&mut cfg.raft_store.region_split_keys,
+            config,
+            "raftstore.region-split-keys");
+    cfg_u64(&mut cfg.raft_store.region_max_keys,
+            config,
+            "raftstore.region-max-keys");
+    cfg.raft_store.split_region_on_table =
+        get_toml_boolean(config, "raftstore.split-region-on-table", Some(false));
+    cfg_u64(&mut cfg.raft_store.load_split_check_tick_interval,
+            config,
+            "raftstore.load-split-check-tick-interval");
+    cfg_u64(&mut cfg.raft_store.region_split_qps_threshold,
+            config,
+            "raftstore.region-split-qps-threshold");
+    cfg_usize(&mut cfg.raft_store.load_split_detect_times,
+              config,
+              "raftstore.load-split-detect-times");
     cfg_u64(&mut cfg.raft_store.raft_log_gc_tick_interval,
             config,
             "raftstore.raft-log-gc-tick-interval");
@@ -804,6 +845,31 @@ fn build_cfg(matches: &ArgMatches,
     cfg_usize(&mut cfg.storage.scheduler_too_busy_threshold,
               config,
               "storage.scheduler-too-busy-threshold");
+    cfg.storage.enable_ttl = get_toml_boolean(config, "storage.enable-ttl", Some(false));
+    cfg.storage.enable_compaction_filter_gc =
+        get_toml_boolean(config, "storage.enable-compaction-filter-gc", Some(false));
+    // Replicas collect garbage and drop expired values in compaction at different times, so
+    // their data differs.
+    if cfg.storage.enable_compaction_filter_gc &&
+       cfg.raft_store.consistency_check_tick_interval > 0 {
+        exit_with_err("storage.enable-compaction-filter-gc and \
+                       raftstore.consistency-check-interval can't be enabled together"
+            .to_owned());
+    }
+    if cfg.storage.enable_ttl && cfg.raft_store.consistency_check_tick_interval > 0 {
+        exit_with_err("storage.enable-ttl and raftstore.consistency-check-interval can't be \
+                       enabled together"
+            .to_owned());
+    }
+    cfg_u64(&mut cfg.storage.gc_safe_point_poll_interval,
+            config,
+            "storage.gc-safe-point-poll-interval");
+    cfg_usize(&mut cfg.storage.gc_batch_keys, config, "storage.gc-batch-keys");
+    cfg_usize(&mut cfg.storage.gc_max_keys_per_sec,
+              config,
+              "storage.gc-max-keys-per-sec");
+    cfg.storage.enable_follower_read =
+        get_toml_boolean(config, "storage.enable-follower-read", Some(false));
 
     cfg
 }
@@ -887,14 +953,24 @@ fn run_raft_server(pd_client: RpcClient,
 
     // Create engine, storage.
     let opts = get_rocksdb_db_option(config);
+    let raw_ttl = cfg.storage.enable_ttl;
+    let gc_context = if cfg.storage.enable_compaction_filter_gc {
+        Some(GcContext::new())
+    } else {
+        None
+    };
     let cfs_opts =
         vec![rocksdb_util::CFOptions::new(CF_DEFAULT,
-                                          get_rocksdb_default_cf_option(config, total_mem)),
+                                          get_rocksdb_default_cf_option(config, total_mem))
+                 .with_gc(gc_context.clone()),
              rocksdb_util::CFOptions::new(CF_LOCK, get_rocksdb_lock_cf_option(config, total_mem)),
              rocksdb_util::CFOptions::new(CF_WRITE,
-                                          get_rocksdb_write_cf_option(config, total_mem)),
+                                          get_rocksdb_write_cf_option(config, total_mem))
+                 .with_gc(gc_context.clone()),
              rocksdb_util::CFOptions::new(CF_RAFT,
-                                          get_rocksdb_raftlog_cf_option(config, total_mem))];
+                                          get_rocksdb_raftlog_cf_option(config, total_mem)),
+             rocksdb_util::CFOptions::new(CF_RAW, get_rocksdb_raw_cf_option(config, total_mem))
+                 .with_raw_ttl(raw_ttl)];
     let engine = Arc::new(rocksdb_util::new_engine_opt(db_path.to_str()
                                                            .unwrap(),
                                                        opts,
@@ -902,9 +978,18 @@ fn run_raft_server(pd_client: RpcClient,
         .unwrap_or_else(|err| exit_with_err(format!("{:?}", err))));
     let mut storage = create_raft_storage(raft_router.clone(), engine.clone(), &cfg)
         .unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
+    if let Some(ref gc_context) = gc_context {
+        gc_context.set_db(&engine);
+        storage.set_gc_context(gc_context.clone());
+    }
 
-    // Create pd client, snapshot manager, server.
+    // Create pd client, node, snapshot manager, server.
     let pd_client = Arc::new(pd_client);
+    let mut node = Node::new(&mut event_loop, &cfg, pd_client.clone());
+    // The storage records the load of the regions for the node to split the hot ones.
+    if cfg.raft_store.load_split_check_tick_interval > 0 {
+        storage.set_load_recorder(node.load_recorder());
+    }
     let (mut worker, resolver) = resolve::new_resolver(pd_client.clone())
         .unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
     let snap_mgr = SnapManager::new(snap_path.as_path().to_str().unwrap().to_owned(),
@@ -919,13 +1004,27 @@ fn run_raft_server(pd_client: RpcClient,
         .unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
     let trans = server.transport();
 
-    // Create node.
-    let mut node = Node::new(&mut event_loop, &cfg, pd_client);
+    // Create cdc endpoint, it observes the applied writes through the coprocessor host.
+    let mut cdc_endpoint = CdcEndpoint::new(engine.clone(),
+                                            pd_client.clone(),
+                                            cfg.cdc_resolved_ts_interval,
+                                            storage.get_max_read_ts());
+    // Create max read ts syncer, one-phase commit is disabled in the regions which just become
+    // led by the local store until their max read ts is synced with pd.
+    let mut max_read_ts_syncer = MaxReadTsSyncer::new(pd_client.clone(),
+                                                      storage.get_max_read_ts());
+    max_read_ts_syncer.start().unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
+    let mut coprocessor_host = CoprocessorHost::new();
+    coprocessor_host.registry.register_observer(200, Box::new(cdc_endpoint.observer()));
+    coprocessor_host.registry.register_observer(300, Box::new(max_read_ts_syncer.observer()));
+
+    // Start node.
     node.start(event_loop,
                engine.clone(),
                trans,
                snap_mgr,
-               snap_status_receiver)
+               snap_status_receiver,
+               coprocessor_host)
         .unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
     initial_metric(config, Some(node.id()));
 
@@ -935,11 +1034,35 @@ fn run_raft_server(pd_client: RpcClient,
         panic!("failed to start storage, error = {:?}", e);
     }
 
+    // Start gc worker.
+    let mut gc_worker = GcWorker::new(node.id(),
+                                      storage.get_engine(),
+                                      engine.clone(),
+                                      pd_client,
+                                      gc_context,
+                                      &cfg.storage);
+    if cfg.storage.gc_safe_point_poll_interval > 0 {
+        info!("start gc worker");
+        gc_worker.start().unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
+    }
+
+    info!("start cdc endpoint");
+    cdc_endpoint.start().unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
+
     // Run server.
     server.start(&cfg).unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
     signal_handler::handle_signal(engine, backup_path);
 
     // Stop.
+    if let Some(Err(e)) = gc_worker.stop().map(|h| h.join()) {
+        info!("ignore failure when stopping gc worker: {:?}", e);
+    }
+    if let Some(Err(e)) = cdc_endpoint.stop().map(|h| h.join()) {
+        info!("ignore failure when stopping cdc endpoint: {:?}", e);
+    }
+    if let Some(Err(e)) = max_read_ts_syncer.stop().map(|h| h.join()) {
+        info!("ignore failure when stopping max read ts syncer: {:?}", e);
+    }
     server.stop().unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
     node.stop().unwrap_or_else(|err| exit_with_err(format!("{:?}", err)));
     if let Some(Err(e)) = worker.stop().map(|h| h.join()) {
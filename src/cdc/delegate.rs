@@ -0,0 +1,449 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{cmp, mem};
+use std::collections::BTreeMap;
+
+use futures::sync::mpsc::UnboundedSender;
+use kvproto::metapb::Region;
+use kvproto::raft_cmdpb::{CmdType, Request};
+use kvproto::raft_serverpb::{RegionLocalState, PeerState};
+use rocksdb::DB;
+
+use raftstore::Result;
+use raftstore::store::keys;
+use raftstore::store::engine::{Iterable, Peekable, Snapshot};
+use storage::{Key, Value, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use storage::mvcc::{Lock, Write, WriteType};
+use util::collections::HashMap;
+
+// The max number of rows sent in one event.
+const MAX_BATCH_ROWS: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowOp {
+    Put,
+    Delete,
+}
+
+/// A committed write of a key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub key: Vec<u8>,
+    // None if the op is `Delete`.
+    pub value: Option<Value>,
+    pub op: RowOp,
+    pub start_ts: u64,
+    pub commit_ts: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Rows { region_id: u64, rows: Vec<Row> },
+    // No more rows with a commit ts not larger than `ts` will be sent.
+    ResolvedTs { region_id: u64, ts: u64 },
+    // The subscription is stopped.
+    Error { region_id: u64, msg: String },
+}
+
+/// `Resolver` tracks the start ts of the locks in a region, the resolved ts can't pass the
+/// smallest one, since the transaction may commit after it.
+#[derive(Default)]
+pub struct Resolver {
+    locks: HashMap<Vec<u8>, u64>,
+    // start ts -> the number of locks.
+    lock_ts: BTreeMap<u64, usize>,
+    resolved_ts: u64,
+}
+
+impl Resolver {
+    pub fn track_lock(&mut self, key: Vec<u8>, start_ts: u64) {
+        if let Some(ts) = self.locks.insert(key, start_ts) {
+            self.untrack_ts(ts);
+        }
+        *self.lock_ts.entry(start_ts).or_insert(0) += 1;
+    }
+
+    pub fn untrack_lock(&mut self, key: &[u8]) {
+        if let Some(ts) = self.locks.remove(key) {
+            self.untrack_ts(ts);
+        }
+    }
+
+    fn untrack_ts(&mut self, ts: u64) {
+        let cnt = {
+            let cnt = self.lock_ts.get_mut(&ts).unwrap();
+            *cnt -= 1;
+            *cnt
+        };
+        if cnt == 0 {
+            self.lock_ts.remove(&ts);
+        }
+    }
+
+    /// Tries to advance the resolved ts to `ts`, returns the new resolved ts if it advances.
+    pub fn resolve(&mut self, ts: u64) -> Option<u64> {
+        let ts = match self.lock_ts.keys().next() {
+            Some(&min_lock_ts) => cmp::min(ts, min_lock_ts),
+            None => ts,
+        };
+        if ts <= self.resolved_ts {
+            return None;
+        }
+        self.resolved_ts = ts;
+        Some(ts)
+    }
+}
+
+/// Decodes a record of the write cf into a row, returns None if it's neither a put nor a delete.
+fn decode_write<P: Peekable>(engine: &P,
+                             pending_values: &mut HashMap<Vec<u8>, Value>,
+                             key: Vec<u8>,
+                             value: &[u8])
+                             -> Result<Option<Row>> {
+    let write = box_try!(Write::parse(value));
+    let op = match write.write_type {
+        WriteType::Put => RowOp::Put,
+        WriteType::Delete => RowOp::Delete,
+        WriteType::Lock | WriteType::Rollback => return Ok(None),
+    };
+    let key = Key::from_encoded(key);
+    let commit_ts = box_try!(key.decode_ts());
+    let key = box_try!(key.truncate_ts());
+    let value = match (op, write.short_value) {
+        (RowOp::Delete, _) => None,
+        (RowOp::Put, Some(v)) => Some(v),
+        (RowOp::Put, None) => {
+            let default_key = key.append_ts(write.start_ts);
+            match pending_values.remove(default_key.encoded()) {
+                Some(v) => Some(v),
+                None => {
+                    let data_key = keys::data_key(default_key.encoded());
+                    match try!(engine.get_value_cf(CF_DEFAULT, &data_key)) {
+                        Some(v) => Some(v.to_vec()),
+                        None => {
+                            return Err(box_err!("value of {} is missing", default_key))
+                        }
+                    }
+                }
+            }
+        }
+    };
+    Ok(Some(Row {
+        key: box_try!(key.raw()),
+        value: value,
+        op: op,
+        start_ts: write.start_ts,
+        commit_ts: commit_ts,
+    }))
+}
+
+/// `Delegate` turns the write requests applied in a subscribed region into events.
+pub struct Delegate {
+    region: Region,
+    // Tells the subscribers of the region apart.
+    downstream_id: u64,
+    downstream: UnboundedSender<Event>,
+    resolver: Resolver,
+    // The values written by prewrites, which are needed once the transactions commit. Keyed by
+    // the key in the default cf.
+    pending_values: HashMap<Vec<u8>, Value>,
+    // The rows not sent yet, keyed by their commit ts.
+    rows: BTreeMap<u64, Vec<Row>>,
+    // The applied index of the snapshot the delegate is initialized with, the change logs before
+    // it are already in the snapshot.
+    applied_index: u64,
+    stopped: bool,
+}
+
+impl Delegate {
+    pub fn new(region: Region, downstream_id: u64, downstream: UnboundedSender<Event>) -> Delegate {
+        Delegate {
+            region: region,
+            downstream_id: downstream_id,
+            downstream: downstream,
+            resolver: Resolver::default(),
+            pending_values: HashMap::default(),
+            rows: BTreeMap::new(),
+            applied_index: 0,
+            stopped: false,
+        }
+    }
+
+    pub fn region_id(&self) -> u64 {
+        self.region.get_id()
+    }
+
+    pub fn downstream_id(&self) -> u64 {
+        self.downstream_id
+    }
+
+    /// Whether the subscription is stopped, a stopped delegate should be removed.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    fn send(&mut self, event: Event) {
+        if self.stopped {
+            return;
+        }
+        if self.downstream.send(event).is_err() {
+            info!("[region {}] cdc subscriber {} is gone",
+                  self.region_id(),
+                  self.downstream_id);
+            self.stopped = true;
+        }
+    }
+
+    pub fn fail(&mut self, msg: String) {
+        warn!("[region {}] cdc subscription of {} failed: {}",
+              self.region_id(),
+              self.downstream_id,
+              msg);
+        let region_id = self.region_id();
+        self.send(Event::Error {
+            region_id: region_id,
+            msg: msg,
+        });
+        self.stopped = true;
+    }
+
+    fn hold_rows(&mut self, rows: Vec<Row>) {
+        for row in rows {
+            self.rows.entry(row.commit_ts).or_insert_with(Vec::new).push(row);
+        }
+    }
+
+    // Sends the rows committed not after `resolved_ts` in the order of their commit ts.
+    fn send_rows(&mut self, resolved_ts: u64) {
+        let later = self.rows.split_off(&(resolved_ts + 1));
+        let resolved = mem::replace(&mut self.rows, later);
+        let region_id = self.region_id();
+        let mut rows = vec![];
+        for (_, mut rs) in resolved {
+            rows.append(&mut rs);
+            if rows.len() >= MAX_BATCH_ROWS {
+                self.send(Event::Rows {
+                    region_id: region_id,
+                    rows: rows.split_off(0),
+                });
+            }
+        }
+        if !rows.is_empty() {
+            self.send(Event::Rows {
+                region_id: region_id,
+                rows: rows,
+            });
+        }
+    }
+
+    /// Loads the locks of the region and the rows committed after `checkpoint_ts` in the
+    /// snapshot.
+    pub fn initialize(&mut self,
+                      snap: &Snapshot,
+                      applied_index: u64,
+                      checkpoint_ts: u64)
+                      -> Result<()> {
+        self.applied_index = applied_index;
+        let start_key = keys::enc_start_key(&self.region);
+        let end_key = keys::enc_end_key(&self.region);
+        {
+            let resolver = &mut self.resolver;
+            try!(snap.scan_cf(CF_LOCK, &start_key, &end_key, false, &mut |key, value| {
+                let lock = box_try!(Lock::parse(value));
+                resolver.track_lock(keys::origin_key(key).to_vec(), lock.ts);
+                Ok(true)
+            }));
+        }
+
+        let mut rows = vec![];
+        let mut pending_values = HashMap::default();
+        try!(snap.scan_cf(CF_WRITE, &start_key, &end_key, false, &mut |key, value| {
+            let key = keys::origin_key(key).to_vec();
+            let commit_ts = box_try!(Key::from_encoded(key.clone()).decode_ts());
+            if commit_ts <= checkpoint_ts {
+                return Ok(true);
+            }
+            if let Some(row) = try!(decode_write(snap, &mut pending_values, key, value)) {
+                rows.push(row);
+            }
+            Ok(true)
+        }));
+        self.hold_rows(rows);
+        Ok(())
+    }
+
+    /// Handles the write requests applied at `index`, `region` is the region they were applied
+    /// in.
+    pub fn on_change_log(&mut self,
+                         db: &DB,
+                         region: &Region,
+                         index: u64,
+                         requests: Vec<Request>)
+                         -> Result<()> {
+        if index <= self.applied_index {
+            return Ok(());
+        }
+        if region.get_region_epoch().get_version() !=
+           self.region.get_region_epoch().get_version() {
+            return Err(box_err!("region epoch changed from {:?} to {:?}",
+                                self.region.get_region_epoch(),
+                                region.get_region_epoch()));
+        }
+
+        let mut rows = vec![];
+        for mut req in requests {
+            match req.get_cmd_type() {
+                CmdType::Put => {
+                    let mut put = req.take_put();
+                    match put.get_cf() {
+                        CF_WRITE => {
+                            let row = try!(decode_write(db,
+                                                        &mut self.pending_values,
+                                                        put.take_key(),
+                                                        put.get_value()));
+                            if let Some(row) = row {
+                                rows.push(row);
+                            }
+                        }
+                        CF_LOCK => {
+                            let lock = box_try!(Lock::parse(put.get_value()));
+                            self.resolver.track_lock(put.take_key(), lock.ts);
+                        }
+                        "" | CF_DEFAULT => {
+                            self.pending_values.insert(put.take_key(), put.take_value());
+                        }
+                        _ => {}
+                    }
+                }
+                CmdType::Delete => {
+                    let delete = req.get_delete();
+                    match delete.get_cf() {
+                        CF_LOCK => self.resolver.untrack_lock(delete.get_key()),
+                        "" | CF_DEFAULT => {
+                            self.pending_values.remove(delete.get_key());
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.hold_rows(rows);
+        Ok(())
+    }
+
+    /// Advances the resolved ts to `ts` if the locks allow, the rows committed not after the
+    /// resolved ts are sent before it.
+    pub fn on_resolved_ts(&mut self, db: &DB, ts: u64) -> Result<()> {
+        // Splits and merges aren't observed, check the range of the region is unchanged before
+        // the resolved ts moves on.
+        let state_key = keys::region_state_key(self.region_id());
+        let state: Option<RegionLocalState> = try!(db.get_msg(&state_key));
+        match state {
+            Some(ref state) if state.get_state() == PeerState::Normal &&
+                               state.get_region().get_region_epoch().get_version() ==
+                               self.region.get_region_epoch().get_version() => {}
+            _ => return Err(box_err!("region is removed or its range is changed")),
+        }
+
+        if let Some(ts) = self.resolver.resolve(ts) {
+            self.send_rows(ts);
+            let region_id = self.region_id();
+            self.send(Event::ResolvedTs {
+                region_id: region_id,
+                ts: ts,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+    use futures::sync::mpsc;
+
+    use super::*;
+
+    fn new_row(key: &[u8], commit_ts: u64) -> Row {
+        Row {
+            key: key.to_vec(),
+            value: Some(b"v".to_vec()),
+            op: RowOp::Put,
+            start_ts: commit_ts - 1,
+            commit_ts: commit_ts,
+        }
+    }
+
+    #[test]
+    fn test_resolver() {
+        let mut resolver = Resolver::default();
+        assert_eq!(resolver.resolve(10), Some(10));
+        // The resolved ts never goes back.
+        assert_eq!(resolver.resolve(5), None);
+
+        resolver.track_lock(b"k1".to_vec(), 12);
+        resolver.track_lock(b"k2".to_vec(), 15);
+        // Tracking the same lock again is fine.
+        resolver.track_lock(b"k2".to_vec(), 15);
+        assert_eq!(resolver.resolve(20), Some(12));
+        assert_eq!(resolver.resolve(20), None);
+
+        resolver.untrack_lock(b"k1");
+        assert_eq!(resolver.resolve(20), Some(15));
+        // Untracking an unknown lock is ignored.
+        resolver.untrack_lock(b"k3");
+        resolver.untrack_lock(b"k2");
+        assert_eq!(resolver.resolve(20), Some(20));
+        assert_eq!(resolver.resolve(30), Some(30));
+    }
+
+    #[test]
+    fn test_send_rows_in_order() {
+        let mut region = Region::new();
+        region.set_id(1);
+        let (tx, rx) = mpsc::unbounded();
+        let mut delegate = Delegate::new(region, 1, tx);
+        delegate.hold_rows(vec![new_row(b"k1", 20), new_row(b"k2", 10)]);
+        delegate.hold_rows(vec![new_row(b"k3", 15), new_row(b"k4", 30)]);
+
+        delegate.send_rows(20);
+        delegate.send_rows(25);
+        delegate.hold_rows((0..MAX_BATCH_ROWS as u64).map(|i| new_row(b"k5", 40 + i)).collect());
+        delegate.send_rows(40 + MAX_BATCH_ROWS as u64);
+        drop(delegate);
+
+        let events: Vec<_> = rx.collect().wait().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0],
+                   Event::Rows {
+                       region_id: 1,
+                       rows: vec![new_row(b"k2", 10), new_row(b"k3", 15), new_row(b"k1", 20)],
+                   });
+        // The rows are sent in batches.
+        match events[1] {
+            Event::Rows { ref rows, .. } => {
+                assert_eq!(rows.len(), MAX_BATCH_ROWS);
+                assert_eq!(rows[0], new_row(b"k4", 30));
+            }
+            ref e => panic!("unexpected event {:?}", e),
+        }
+        match events[2] {
+            Event::Rows { ref rows, .. } => {
+                assert_eq!(rows, &vec![new_row(b"k5", 40 + MAX_BATCH_ROWS as u64 - 1)]);
+            }
+            ref e => panic!("unexpected event {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use futures::Future;
+use futures::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use kvproto::metapb::Region;
+use kvproto::raft_cmdpb::Request;
+use kvproto::raft_serverpb::{RaftApplyState, RegionLocalState, PeerState};
+use rocksdb::DB;
+use tokio_core::reactor::Handle;
+use tokio_timer::Timer;
+
+use pd::PdClient;
+use raftstore::{Result, Error};
+use raftstore::store::keys;
+use raftstore::store::engine::{Peekable, Snapshot};
+use storage::{MaxReadTs, CF_RAFT};
+use util::collections::HashMap;
+use util::worker::{FutureRunnable, FutureScheduler, FutureWorker, Stopped};
+
+use super::delegate::{Delegate, Event};
+use super::observer::CdcObserver;
+
+// In milliseconds.
+pub const DEFAULT_RESOLVED_TS_INTERVAL: u64 = 1000;
+
+pub enum Task {
+    Register {
+        region_id: u64,
+        downstream_id: u64,
+        checkpoint_ts: u64,
+        downstream: UnboundedSender<Event>,
+    },
+    Deregister { region_id: u64, downstream_id: u64 },
+    // The local peer of the region is no longer the leader.
+    NotLeader { region_id: u64 },
+    ChangeLog {
+        region: Region,
+        index: u64,
+        requests: Vec<Request>,
+    },
+    // Asks pd for a timestamp to resolve the regions with.
+    ResolveTs,
+    ResolvedTs { ts: u64 },
+}
+
+impl Display for Task {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Task::Register { region_id, downstream_id, checkpoint_ts, .. } => {
+                write!(f,
+                       "register {} to region {} from checkpoint ts {}",
+                       downstream_id,
+                       region_id,
+                       checkpoint_ts)
+            }
+            Task::Deregister { region_id, downstream_id } => {
+                write!(f, "deregister {} from region {}", downstream_id, region_id)
+            }
+            Task::NotLeader { region_id } => write!(f, "region {} is not leader", region_id),
+            Task::ChangeLog { ref region, index, ref requests } => {
+                write!(f,
+                       "change log of region {} at index {} with {} requests",
+                       region.get_id(),
+                       index,
+                       requests.len())
+            }
+            Task::ResolveTs => write!(f, "resolve ts"),
+            Task::ResolvedTs { ts } => write!(f, "resolved ts {}", ts),
+        }
+    }
+}
+
+struct Runner<C: PdClient> {
+    db: Arc<DB>,
+    pd_client: Arc<C>,
+    observer: CdcObserver,
+    scheduler: FutureScheduler<Task>,
+    timer: Timer,
+    resolved_ts_interval: Duration,
+    // The delegates of the subscribers of every region.
+    delegates: HashMap<u64, Vec<Delegate>>,
+    max_read_ts: MaxReadTs,
+}
+
+impl<C: PdClient> Runner<C> {
+    fn register(&mut self,
+                region_id: u64,
+                downstream_id: u64,
+                checkpoint_ts: u64,
+                downstream: UnboundedSender<Event>) {
+        // Observe the region before taking the snapshot, so the change logs after the snapshot
+        // are not missed.
+        self.observer.observe_region(region_id);
+        let snap = Snapshot::new(self.db.clone());
+        let res = if self.observer.is_leader(region_id) {
+            load_region(&snap, region_id)
+        } else {
+            Err(Error::NotLeader(region_id, None))
+        };
+        let res = res.and_then(|(region, applied_index)| {
+            let mut delegate = Delegate::new(region, downstream_id, downstream.clone());
+            try!(delegate.initialize(&snap, applied_index, checkpoint_ts));
+            Ok(delegate)
+        });
+        match res {
+            Ok(delegate) => {
+                self.delegates.entry(region_id).or_insert_with(Vec::new).push(delegate);
+            }
+            Err(e) => {
+                let mut delegate = Delegate::new(Region::new(), downstream_id, downstream);
+                delegate.fail(format!("failed to subscribe region {}: {:?}", region_id, e));
+            }
+        }
+        self.remove_stopped(region_id);
+    }
+
+    fn deregister(&mut self, region_id: u64, downstream_id: u64) {
+        if let Some(delegates) = self.delegates.get_mut(&region_id) {
+            delegates.retain(|d| d.downstream_id() != downstream_id);
+        }
+        self.remove_stopped(region_id);
+    }
+
+    fn on_not_leader(&mut self, region_id: u64) {
+        if let Some(delegates) = self.delegates.get_mut(&region_id) {
+            for delegate in delegates {
+                delegate.fail("the local peer is not leader anymore".to_owned());
+            }
+        }
+        self.remove_stopped(region_id);
+    }
+
+    fn on_change_log(&mut self, region: Region, index: u64, requests: Vec<Request>) {
+        let region_id = region.get_id();
+        if let Some(delegates) = self.delegates.get_mut(&region_id) {
+            for delegate in delegates {
+                let res = delegate.on_change_log(&self.db, &region, index, requests.clone());
+                check_delegate(delegate, res);
+            }
+        }
+        self.remove_stopped(region_id);
+    }
+
+    fn on_resolve_ts(&mut self, handle: &Handle) {
+        if !self.delegates.is_empty() {
+            let scheduler = self.scheduler.clone();
+            let f = self.pd_client.get_timestamp().then(move |res| {
+                match res {
+                    // The change logs scheduled before are handled before the resolved ts.
+                    Ok(ts) => {
+                        if let Err(Stopped(t)) = scheduler.schedule(Task::ResolvedTs { ts: ts }) {
+                            warn!("cdc worker is stopped, drop {}", t);
+                        }
+                    }
+                    Err(e) => warn!("cdc failed to get timestamp: {:?}", e),
+                }
+                Ok(())
+            });
+            handle.spawn(f);
+        }
+
+        let scheduler = self.scheduler.clone();
+        let f = self.timer.sleep(self.resolved_ts_interval).then(move |_| {
+            if let Err(Stopped(t)) = scheduler.schedule(Task::ResolveTs) {
+                info!("cdc worker is stopped, drop {}", t);
+            }
+            Ok(())
+        });
+        handle.spawn(f);
+    }
+
+    fn on_resolved_ts(&mut self, ts: u64) {
+        // The transactions committed in one phase don't lock their keys, so they must be
+        // committed after the ts, except the ones being applied, which the ts is lowered below.
+        let ts = self.max_read_ts.resolve(ts);
+        let region_ids: Vec<_> = self.delegates.keys().cloned().collect();
+        for region_id in region_ids {
+            for delegate in self.delegates.get_mut(&region_id).unwrap() {
+                let res = delegate.on_resolved_ts(&self.db, ts);
+                check_delegate(delegate, res);
+            }
+            self.remove_stopped(region_id);
+        }
+    }
+
+    // Removes the stopped delegates of the region, the region is not observed anymore once it
+    // has none.
+    fn remove_stopped(&mut self, region_id: u64) {
+        let empty = self.delegates.get_mut(&region_id).map_or(true, |delegates| {
+            delegates.retain(|d| !d.is_stopped());
+            delegates.is_empty()
+        });
+        if empty {
+            self.delegates.remove(&region_id);
+            self.observer.unobserve_region(region_id);
+        }
+    }
+}
+
+// Fails the delegate if `res` is an error.
+fn check_delegate(delegate: &mut Delegate, res: Result<()>) {
+    if let Err(e) = res {
+        delegate.fail(format!("{:?}", e));
+    }
+}
+
+// Loads the region and its applied index in the snapshot.
+fn load_region(snap: &Snapshot, region_id: u64) -> Result<(Region, u64)> {
+    let state_key = keys::region_state_key(region_id);
+    let mut state: RegionLocalState = match try!(snap.get_msg(&state_key)) {
+        Some(state) => state,
+        None => return Err(box_err!("region {} is not found", region_id)),
+    };
+    if state.get_state() != PeerState::Normal {
+        return Err(box_err!("region {} is {:?}", region_id, state.get_state()));
+    }
+    let apply_state_key = keys::apply_state_key(region_id);
+    let apply_state: RaftApplyState = match try!(snap.get_msg_cf(CF_RAFT, &apply_state_key)) {
+        Some(state) => state,
+        None => return Err(box_err!("apply state of region {} is not found", region_id)),
+    };
+    Ok((state.take_region(), apply_state.get_applied_index()))
+}
+
+impl<C: PdClient> FutureRunnable<Task> for Runner<C> {
+    fn run(&mut self, task: Task, handle: &Handle) {
+        match task {
+            Task::Register { region_id, downstream_id, checkpoint_ts, downstream } => {
+                self.register(region_id, downstream_id, checkpoint_ts, downstream)
+            }
+            Task::Deregister { region_id, downstream_id } => {
+                self.deregister(region_id, downstream_id)
+            }
+            Task::NotLeader { region_id } => self.on_not_leader(region_id),
+            Task::ChangeLog { region, index, requests } => {
+                self.on_change_log(region, index, requests)
+            }
+            Task::ResolveTs => self.on_resolve_ts(handle),
+            Task::ResolvedTs { ts } => self.on_resolved_ts(ts),
+        }
+    }
+}
+
+/// `Endpoint` serves the cdc subscriptions of the local store, see the module document.
+pub struct Endpoint<C: PdClient + 'static> {
+    worker: FutureWorker<Task>,
+    observer: CdcObserver,
+    runner: Option<Runner<C>>,
+    downstream_id_alloc: AtomicUsize,
+}
+
+impl<C: PdClient + 'static> Endpoint<C> {
+    /// Creates an endpoint, which sends a resolved ts every `resolved_ts_interval` milliseconds.
+    /// The resolved ts are pushed to `max_read_ts` before they are sent.
+    pub fn new(db: Arc<DB>,
+               pd_client: Arc<C>,
+               resolved_ts_interval: u64,
+               max_read_ts: MaxReadTs)
+               -> Endpoint<C> {
+        let worker = FutureWorker::new("cdc worker");
+        let observer = CdcObserver::new(worker.scheduler());
+        let runner = Runner {
+            db: db,
+            pd_client: pd_client,
+            observer: observer.clone(),
+            scheduler: worker.scheduler(),
+            timer: Timer::default(),
+            resolved_ts_interval: Duration::from_millis(resolved_ts_interval),
+            delegates: HashMap::default(),
+            max_read_ts: max_read_ts,
+        };
+        Endpoint {
+            worker: worker,
+            observer: observer,
+            runner: Some(runner),
+            downstream_id_alloc: AtomicUsize::new(1),
+        }
+    }
+
+    /// The observer to register in the coprocessor host of the raftstore.
+    pub fn observer(&self) -> CdcObserver {
+        self.observer.clone()
+    }
+
+    pub fn start(&mut self) -> io::Result<()> {
+        let runner = match self.runner.take() {
+            Some(runner) => runner,
+            None => return Err(io::Error::new(io::ErrorKind::Other, "cdc is already started")),
+        };
+        try!(self.worker.start(runner));
+        if let Err(e) = self.worker.schedule(Task::ResolveTs) {
+            warn!("failed to schedule cdc resolve ts: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Subscribes the changes of the region committed after `checkpoint_ts`, returns the id of
+    /// the subscriber, which deregisters it, and the events.
+    pub fn register(&self,
+                    region_id: u64,
+                    checkpoint_ts: u64)
+                    -> ::std::result::Result<(u64, UnboundedReceiver<Event>), Stopped<Task>> {
+        let downstream_id = self.downstream_id_alloc.fetch_add(1, Ordering::SeqCst) as u64;
+        let (tx, rx) = mpsc::unbounded();
+        try!(self.worker.schedule(Task::Register {
+            region_id: region_id,
+            downstream_id: downstream_id,
+            checkpoint_ts: checkpoint_ts,
+            downstream: tx,
+        }));
+        Ok((downstream_id, rx))
+    }
+
+    /// Stops the subscription `downstream_id` of the region, the other subscribers of the region
+    /// are not affected.
+    pub fn deregister(&self,
+                      region_id: u64,
+                      downstream_id: u64)
+                      -> ::std::result::Result<(), Stopped<Task>> {
+        self.worker.schedule(Task::Deregister {
+            region_id: region_id,
+            downstream_id: downstream_id,
+        })
+    }
+
+    pub fn stop(&mut self) -> Option<JoinHandle<()>> {
+        self.worker.stop()
+    }
+}
@@ -0,0 +1,42 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Change data capture streams the committed MVCC writes of a region to its subscribers.
+//!
+//! A `CdcObserver` registered in the coprocessor host of the raftstore forwards the write
+//! requests applied in the observed regions to the cdc worker. For every subscribed region, the
+//! worker keeps a `Delegate` which
+//!
+//! - decodes the commits in the write cf into rows, with the values taken from the short values
+//!   or the default cf.
+//! - tracks the locks of the region, so that it can tell a resolved ts: no more rows with a
+//!   commit ts not larger than it will be sent.
+//!
+//! A region can have several subscribers, each with its own delegate. Once registered, a
+//! subscriber gets the rows committed after its checkpoint ts, first the ones in the region,
+//! then the new commits as they are applied. The rows are held until a resolved
+//! ts passes their commit ts, then they are sent ordered by their commit ts, followed by the
+//! resolved ts. Rows may be sent more than once. The subscription fails with an `Event::Error`
+//! when the range of the region changes, the subscriber is expected to subscribe again from its
+//! last resolved ts.
+//!
+//! The resolved ts depends on the order the leader applies logs in, so the region can only be
+//! subscribed on its leader, and the subscription fails once the local peer is not the leader.
+
+mod delegate;
+mod endpoint;
+mod observer;
+
+pub use self::delegate::{Event, Row, RowOp};
+pub use self::endpoint::{Endpoint, Task, DEFAULT_RESOLVED_TS_INTERVAL};
+pub use self::observer::CdcObserver;
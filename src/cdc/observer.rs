@@ -0,0 +1,118 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Mutex, RwLock};
+
+use kvproto::raft_cmdpb::Request;
+use protobuf::RepeatedField;
+
+use raft::StateRole;
+use raftstore::coprocessor::{Coprocessor, RegionObserver, ObserverContext, RegionChangeEvent};
+use util::HandyRwLock;
+use util::collections::HashSet;
+use util::worker::FutureScheduler;
+
+use super::endpoint::Task;
+
+/// `CdcObserver` forwards the write requests applied in the subscribed regions to the cdc
+/// worker, and tells it when the local peers of the subscribed regions are no longer leaders.
+#[derive(Clone)]
+pub struct CdcObserver {
+    regions: Arc<RwLock<HashSet<u64>>>,
+    // The regions led by the local store.
+    leaders: Arc<RwLock<HashSet<u64>>>,
+    // The observer is shared by the apply worker, so the scheduler has to be `Sync`.
+    scheduler: Arc<Mutex<FutureScheduler<Task>>>,
+}
+
+impl CdcObserver {
+    pub fn new(scheduler: FutureScheduler<Task>) -> CdcObserver {
+        CdcObserver {
+            regions: Arc::new(RwLock::new(HashSet::default())),
+            leaders: Arc::new(RwLock::new(HashSet::default())),
+            scheduler: Arc::new(Mutex::new(scheduler)),
+        }
+    }
+
+    pub fn observe_region(&self, region_id: u64) {
+        self.regions.wl().insert(region_id);
+    }
+
+    pub fn unobserve_region(&self, region_id: u64) {
+        self.regions.wl().remove(&region_id);
+    }
+
+    fn is_observed(&self, region_id: u64) -> bool {
+        self.regions.rl().contains(&region_id)
+    }
+
+    /// Whether the local peer of the region is the leader. The region should be observed first,
+    /// so that losing the leadership after the check is not missed.
+    pub fn is_leader(&self, region_id: u64) -> bool {
+        self.leaders.rl().contains(&region_id)
+    }
+
+    fn update_role(&self, region_id: u64, role: Option<StateRole>) {
+        if role == Some(StateRole::Leader) {
+            self.leaders.wl().insert(region_id);
+            return;
+        }
+        self.leaders.wl().remove(&region_id);
+        if !self.is_observed(region_id) {
+            return;
+        }
+        let task = Task::NotLeader { region_id: region_id };
+        if let Err(e) = self.scheduler.lock().unwrap().schedule(task) {
+            warn!("[region {}] failed to schedule not leader: {:?}", region_id, e);
+        }
+    }
+}
+
+impl Coprocessor for CdcObserver {}
+
+impl RegionObserver for CdcObserver {
+    fn post_apply_query(&self,
+                        ctx: &mut ObserverContext,
+                        index: u64,
+                        requests: &RepeatedField<Request>) {
+        let region = ctx.region();
+        if !self.is_observed(region.get_id()) {
+            return;
+        }
+        let task = Task::ChangeLog {
+            region: region.clone(),
+            index: index,
+            requests: requests.to_vec(),
+        };
+        if let Err(e) = self.scheduler.lock().unwrap().schedule(task) {
+            warn!("[region {}] failed to schedule change log: {:?}",
+                  region.get_id(),
+                  e);
+        }
+    }
+
+    fn on_role_change(&self, ctx: &mut ObserverContext, role: StateRole) {
+        self.update_role(ctx.region().get_id(), Some(role));
+    }
+
+    fn on_region_changed(&self,
+                         ctx: &mut ObserverContext,
+                         event: RegionChangeEvent,
+                         role: StateRole) {
+        let role = match event {
+            RegionChangeEvent::Create | RegionChangeEvent::Update => Some(role),
+            RegionChangeEvent::Destroy => None,
+        };
+        self.update_role(ctx.region().get_id(), role);
+    }
+}
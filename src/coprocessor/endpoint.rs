@@ -27,7 +27,7 @@ use util::worker::{BatchRunnable, Scheduler};
 use util::collections::HashMap;
 use util::threadpool::{ThreadPool, FifoQueue};
 use server::OnResponse;
-use storage::{self, Engine, SnapshotStore, engine, Snapshot, Statistics};
+use storage::{self, Engine, SnapshotStore, engine, Snapshot, Statistics, MaxReadTs, Key};
 
 use super::codec::mysql;
 use super::codec::datum::Datum;
@@ -62,6 +62,7 @@ const ENDPOINT_IS_BUSY: &'static str = "endpoint is busy";
 
 pub struct Host {
     engine: Box<Engine>,
+    max_read_ts: MaxReadTs,
     sched: Scheduler<Task>,
     reqs: HashMap<u64, Vec<RequestTask>>,
     last_req_id: u64,
@@ -72,9 +73,14 @@ pub struct Host {
 }
 
 impl Host {
-    pub fn new(engine: Box<Engine>, scheduler: Scheduler<Task>, concurrency: usize) -> Host {
+    pub fn new(engine: Box<Engine>,
+               max_read_ts: MaxReadTs,
+               scheduler: Scheduler<Task>,
+               concurrency: usize)
+               -> Host {
         Host {
             engine: engine,
+            max_read_ts: max_read_ts,
             sched: scheduler,
             reqs: HashMap::default(),
             last_req_id: 0,
@@ -246,6 +252,20 @@ impl BatchRunnable<Task> for Host {
                         on_error(e, req);
                         continue;
                     }
+                    // It may wait for the one-phase commits in the ranges being applied.
+                    if let Some(start_ts) = req.start_ts {
+                        for range in req.req.get_ranges() {
+                            let start = Key::from_raw(range.get_start());
+                            // An empty end key means unbounded.
+                            let end = if range.get_end().is_empty() {
+                                None
+                            } else {
+                                Some(Key::from_raw(range.get_end()))
+                            };
+                            let end = end.as_ref().map(|k| k.encoded().as_slice());
+                            self.max_read_ts.read_range(start_ts, start.encoded(), end);
+                        }
+                    }
                     let key = {
                         let ctx = req.req.get_context();
                         (ctx.get_region_id(),
@@ -525,6 +545,7 @@ mod tests {
     use super::*;
     use util::worker::Worker;
     use storage::engine::{self, TEMP_DIR};
+    use storage::MaxReadTs;
 
     use kvproto::coprocessor::Request;
 
@@ -544,7 +565,7 @@ mod tests {
     fn test_req_outdated() {
         let mut worker = Worker::new("test-endpoint");
         let engine = engine::new_local_engine(TEMP_DIR, &[]).unwrap();
-        let end_point = Host::new(engine, worker.scheduler(), 1);
+        let end_point = Host::new(engine, MaxReadTs::default(), worker.scheduler(), 1);
         worker.start_batch(end_point, 30).unwrap();
         let (tx, rx) = mpsc::channel();
         let mut task = RequestTask::new(Request::new(),
@@ -562,7 +583,7 @@ mod tests {
     fn test_too_many_reqs() {
         let mut worker = Worker::new("test-endpoint");
         let engine = engine::new_local_engine(TEMP_DIR, &[]).unwrap();
-        let mut end_point = Host::new(engine, worker.scheduler(), 1);
+        let mut end_point = Host::new(engine, MaxReadTs::default(), worker.scheduler(), 1);
         end_point.max_running_task_count = 3;
         worker.start_batch(end_point, 30).unwrap();
         let (tx, rx) = mpsc::channel();
@@ -298,4 +298,16 @@ impl PdClient for RpcClient {
             .request(req, executor, LEADER_CHANGE_RETRY)
             .execute()
     }
+
+    fn get_gc_safe_point(&self) -> PdFuture<u64> {
+        // TODO: send GetGCSafePoint to pd once kvproto defines it.
+        let e: Error = box_err!("getting gc safe point is not supported by pd yet");
+        future::err(e).boxed()
+    }
+
+    fn get_timestamp(&self) -> PdFuture<u64> {
+        // TODO: get timestamps through the Tso stream once the client supports it.
+        let e: Error = box_err!("getting timestamp is not supported by pd client yet");
+        future::err(e).boxed()
+    }
 }
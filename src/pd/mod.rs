@@ -132,4 +132,10 @@ pub trait PdClient: Send + Sync {
 
     // Report pd the split region.
     fn report_split(&self, left: metapb::Region, right: metapb::Region) -> PdFuture<()>;
+
+    // Get the cluster GC safe point, versions older than it can be collected.
+    fn get_gc_safe_point(&self) -> PdFuture<u64>;
+
+    // Get a timestamp from the timestamp oracle of pd.
+    fn get_timestamp(&self) -> PdFuture<u64>;
 }
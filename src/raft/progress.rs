@@ -79,6 +79,10 @@ pub struct Progress {
     // When a leader receives a reply, the previous inflights should
     // be freed by calling inflights.freeTo.
     pub ins: Inflights,
+
+    // is_learner is true if the peer is a learner. A learner receives the log like a
+    // follower, but it doesn't vote, and it isn't counted in the quorum.
+    pub is_learner: bool,
 }
 
 
@@ -38,7 +38,7 @@ use raft::errors::{Result, Error, StorageError};
 use raft::raft_log::{self, RaftLog};
 use raft::read_only::{ReadOnlyOption, ReadState, ReadOnly};
 
-use super::FlatMap;
+use super::{FlatMap, HashSet};
 
 // CAMPAIGN_PRE_ELECTION represents the first phase of a normal election when
 // Config.pre_vote is true.
@@ -82,6 +82,21 @@ pub struct Config {
     /// peer is private and only used for testing right now.
     pub peers: Vec<u64>,
 
+    /// learners contains the IDs of the learner nodes (including self if it's a
+    /// learner) in the raft cluster. Learners receive the log from the leader, but
+    /// they don't vote in elections and aren't counted in the quorum.
+    /// ConfState doesn't record learners, so they are set here on every start.
+    ///
+    /// Learners are only used in tests for now. No conf change type adds a learner, and
+    /// neither ConfState nor metapb::Peer can persist one, so the raftstore never sets this.
+    pub learners: Vec<u64>,
+
+    /// joint is the joint consensus the node was in when it stopped. ConfState can't describe a
+    /// joint consensus, it only records the voters of both configurations, so the application
+    /// persists the one entered by `begin_membership_change` and sets it here on restart.
+    /// The voters of the incoming and outgoing configurations must be the restored ones.
+    pub joint: Option<JointConfig>,
+
     /// ElectionTick is the number of node.tick invocations that must pass between
     /// elections. That is, if a follower does not receive any message from the
     /// leader of current term before ElectionTick has elapsed, it will become
@@ -164,6 +179,14 @@ pub struct SoftState {
     pub raft_state: StateRole,
 }
 
+/// `JointConfig` is the configurations of a joint consensus (C_old,new), during which the
+/// decisions need the majorities of both of them.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct JointConfig {
+    pub incoming: Vec<u64>,
+    pub outgoing: Vec<u64>,
+}
+
 #[derive(Default)]
 pub struct Raft<T: Storage> {
     pub term: u64,
@@ -196,6 +219,10 @@ pub struct Raft<T: Storage> {
     /// New configuration is ignored if there exists unapplied configuration.
     pub pending_conf: bool,
 
+    /// The voters are being replaced if it's not None. The voters of both configurations are
+    /// in `prs`.
+    pub joint: Option<JointConfig>,
+
     pub read_only: ReadOnly,
 
     /// number of ticks since it reached last electionTimeout when it is leader
@@ -260,6 +287,36 @@ pub fn quorum(total: usize) -> usize {
     total / 2 + 1
 }
 
+// Returns true if the voters which `f` returns true for are the majority of `voters`.
+fn is_majority<I, F>(voters: I, f: &F) -> bool
+    where I: Iterator<Item = u64>,
+          F: Fn(u64) -> bool
+{
+    let (mut total, mut count) = (0, 0);
+    for id in voters {
+        total += 1;
+        if f(id) {
+            count += 1;
+        }
+    }
+    count >= quorum(total)
+}
+
+// Returns the largest index which is replicated on the majority of `voters`.
+fn committed_index<I: Iterator<Item = u64>>(prs: &FlatMap<u64, Progress>, voters: I) -> u64 {
+    let mut mis: Vec<_> = voters.map(|id| prs[&id].matched).collect();
+    // reverse sort
+    mis.sort_by(|a, b| b.cmp(a));
+    mis[quorum(mis.len()) - 1]
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum VoteResult {
+    Won,
+    Lost,
+    Pending,
+}
+
 impl<T: Storage> Raft<T> {
     pub fn new(c: &Config, store: T) -> Raft<T> {
         c.validate().expect("configuration is invalid");
@@ -296,6 +353,7 @@ impl<T: Storage> Raft<T> {
             term: Default::default(),
             election_elapsed: Default::default(),
             pending_conf: Default::default(),
+            joint: None,
             before_step_state: None,
             vote: Default::default(),
             heartbeat_elapsed: Default::default(),
@@ -306,6 +364,27 @@ impl<T: Storage> Raft<T> {
         for p in peers {
             r.prs.insert(*p, new_progress(1, r.max_inflight));
         }
+        for l in &c.learners {
+            if r.prs.contains_key(l) {
+                panic!("{} node {} is in both learners and peers", c.tag, l);
+            }
+            let mut p = new_progress(1, r.max_inflight);
+            p.is_learner = true;
+            r.prs.insert(*l, p);
+        }
+        if let Some(ref joint) = c.joint {
+            let mut voters = joint.incoming.clone();
+            voters.extend_from_slice(&joint.outgoing);
+            voters.sort();
+            voters.dedup();
+            if voters != r.nodes() {
+                panic!("{} joint consensus {:?} doesn't match the voters {:?}",
+                       c.tag,
+                       joint,
+                       r.nodes());
+            }
+            r.joint = Some(joint.clone());
+        }
         if rs.hard_state != HardState::new() {
             r.load_state(rs.hard_state);
         }
@@ -314,10 +393,11 @@ impl<T: Storage> Raft<T> {
         }
         let term = r.term;
         r.become_follower(term, INVALID_ID);
-        info!("{} newRaft [peers: {:?}, term: {:?}, commit: {}, applied: {}, last_index: {}, \
-               last_term: {}]",
+        info!("{} newRaft [peers: {:?}, learners: {:?}, term: {:?}, commit: {}, applied: {}, \
+               last_index: {}, last_term: {}]",
               r.tag,
               r.nodes(),
+              r.learner_nodes(),
               r.term,
               r.raft_log.committed,
               r.raft_log.get_applied(),
@@ -370,8 +450,43 @@ impl<T: Storage> Raft<T> {
         self.state == StateRole::Leader && self.check_quorum
     }
 
-    fn quorum(&self) -> usize {
-        quorum(self.prs.len())
+    // Returns true if the voters which `f` returns true for are the quorum, which needs the
+    // majorities of both configurations in a joint consensus.
+    fn has_quorum<F: Fn(u64) -> bool>(&self, f: F) -> bool {
+        match self.joint {
+            Some(ref joint) => {
+                is_majority(joint.incoming.iter().cloned(), &f) &&
+                is_majority(joint.outgoing.iter().cloned(), &f)
+            }
+            None => {
+                is_majority(self.prs.iter().filter(|&(_, p)| !p.is_learner).map(|(id, _)| *id),
+                            &f)
+            }
+        }
+    }
+
+    fn vote_result(&self) -> VoteResult {
+        let granted = |id: u64| self.votes.get(&id) == Some(&true);
+        if self.has_quorum(granted) {
+            return VoteResult::Won;
+        }
+        // The election is lost once the majority of either configuration rejects.
+        let rejected = |id: u64| self.votes.get(&id) == Some(&false);
+        let lost = match self.joint {
+            Some(ref joint) => {
+                is_majority(joint.incoming.iter().cloned(), &rejected) ||
+                is_majority(joint.outgoing.iter().cloned(), &rejected)
+            }
+            None => {
+                is_majority(self.prs.iter().filter(|&(_, p)| !p.is_learner).map(|(id, _)| *id),
+                            &rejected)
+            }
+        };
+        if lost {
+            VoteResult::Lost
+        } else {
+            VoteResult::Pending
+        }
     }
 
     // for testing leader lease
@@ -391,13 +506,27 @@ impl<T: Storage> Raft<T> {
         self.randomized_election_timeout
     }
 
+    // nodes returns the voters in the cluster, learners are excluded. The voters of both
+    // configurations are returned in a joint consensus.
     pub fn nodes(&self) -> Vec<u64> {
-        let mut nodes = Vec::with_capacity(self.prs.len());
-        nodes.extend(self.prs.keys());
+        let mut nodes: Vec<_> =
+            self.prs.iter().filter(|&(_, p)| !p.is_learner).map(|(id, _)| *id).collect();
         nodes.sort();
         nodes
     }
 
+    pub fn learner_nodes(&self) -> Vec<u64> {
+        let mut nodes: Vec<_> =
+            self.prs.iter().filter(|&(_, p)| p.is_learner).map(|(id, _)| *id).collect();
+        nodes.sort();
+        nodes
+    }
+
+    /// Returns true if `id` is a learner in the cluster.
+    pub fn is_learner(&self, id: u64) -> bool {
+        self.prs.get(&id).map_or(false, |p| p.is_learner)
+    }
+
     // send persists state to stable storage and then sends to its mailbox.
     fn send(&mut self, mut m: Message) {
         m.set_from(self.id);
@@ -575,13 +704,16 @@ impl<T: Storage> Raft<T> {
     // r.bcast_append).
     pub fn maybe_commit(&mut self) -> bool {
         // TODO: optimize
-        let mut mis = Vec::with_capacity(self.prs.len());
-        for p in self.prs.values() {
-            mis.push(p.matched);
-        }
-        // reverse sort
-        mis.sort_by(|a, b| b.cmp(a));
-        let mci = mis[self.quorum() - 1];
+        let mci = match self.joint {
+            Some(ref joint) => {
+                cmp::min(committed_index(&self.prs, joint.incoming.iter().cloned()),
+                         committed_index(&self.prs, joint.outgoing.iter().cloned()))
+            }
+            None => {
+                committed_index(&self.prs,
+                                self.prs.iter().filter(|&(_, p)| !p.is_learner).map(|(id, _)| *id))
+            }
+        };
         let term = self.term;
         self.raft_log.maybe_commit(mci, term)
     }
@@ -602,7 +734,9 @@ impl<T: Storage> Raft<T> {
         let (last_index, max_inflight) = (self.raft_log.last_index(), self.max_inflight);
         let self_id = self.id;
         for (id, p) in &mut self.prs {
+            let is_learner = p.is_learner;
             *p = new_progress(last_index + 1, max_inflight);
+            p.is_learner = is_learner;
             if id == &self_id {
                 p.matched = last_index;
             }
@@ -750,7 +884,8 @@ impl<T: Storage> Raft<T> {
             (MessageType::MsgRequestVote, self.term)
         };
         let id = self.id;
-        if self.quorum() == self.poll(id, vote_resp_msg_type(vote_msg), true) {
+        self.poll(id, vote_resp_msg_type(vote_msg), true);
+        if self.vote_result() == VoteResult::Won {
             // We won the election after voting for ourselves (which must mean that
             // this is a single-node cluster). Advance to the next state.
             if campaign_type == CAMPAIGN_PRE_ELECTION {
@@ -760,7 +895,8 @@ impl<T: Storage> Raft<T> {
             }
             return;
         }
-        let ids: Vec<_> = self.prs.keys().cloned().collect();
+        // Learners don't vote.
+        let ids = self.nodes();
         for id in ids {
             if id == self.id {
                 continue;
@@ -797,7 +933,10 @@ impl<T: Storage> Raft<T> {
                   id,
                   self.term)
         }
-        self.votes.entry(id).or_insert(v);
+        // Learners don't vote.
+        if !self.is_learner(id) {
+            self.votes.entry(id).or_insert(v);
+        }
         self.votes.values().filter(|x| **x).count()
     }
 
@@ -897,7 +1036,10 @@ impl<T: Storage> Raft<T> {
 
         match m.get_msg_type() {
             MessageType::MsgHup => {
-                if self.state != StateRole::Leader {
+                if !self.promotable() {
+                    warn!("{} is not promotable and can't campaign, ignoring MsgHup",
+                          self.tag);
+                } else if self.state != StateRole::Leader {
                     let ents = self.raft_log
                         .slice(self.raft_log.applied + 1,
                                self.raft_log.committed + 1,
@@ -1077,6 +1219,12 @@ impl<T: Storage> Raft<T> {
                    self.tag);
             return;
         }
+        if self.is_learner(lead_transferee) {
+            debug!("{} ignored transferring leadership to learner {}",
+                   self.tag,
+                   lead_transferee);
+            return;
+        }
         // Transfer leadership to third party.
         info!("{} [term {}] starts to transfer leadership to {}",
               self.tag,
@@ -1152,9 +1300,14 @@ impl<T: Storage> Raft<T> {
                 if self.read_only.option != ReadOnlyOption::Safe || m.get_context().is_empty() {
                     return;
                 }
-
-                let ack_count = self.read_only.recv_ack(m);
-                if ack_count < self.quorum() {
+                self.read_only.recv_ack(m);
+                let self_id = self.id;
+                let acked = match self.read_only.acks(m.get_context()) {
+                    // The acks of learners aren't counted in the quorum.
+                    Some(acks) => self.has_quorum(|id| id == self_id || acks.contains(&id)),
+                    None => false,
+                };
+                if !acked {
                     return;
                 }
 
@@ -1261,7 +1414,8 @@ impl<T: Storage> Raft<T> {
                     return;
                 }
 
-                if self.quorum() > 1 {
+                let self_id = self.id;
+                if !self.has_quorum(|id| id == self_id) {
                     // thinking: use an interally defined context instead of the user given context.
                     // We can express this in terms of the term and index instead of
                     // a user-supplied value.
@@ -1369,21 +1523,23 @@ impl<T: Storage> Raft<T> {
                 }
 
                 let gr = self.poll(m.get_from(), m.get_msg_type(), !m.get_reject());
-                info!("{} [quorum:{}] has received {} {:?} votes and {} vote rejections",
+                info!("{} [joint: {}] has received {} {:?} votes and {} vote rejections",
                       self.tag,
-                      self.quorum(),
+                      self.joint.is_some(),
                       gr,
                       m.get_msg_type(),
                       self.votes.len() - gr);
-                if self.quorum() == gr {
-                    if self.state == StateRole::PreCandidate {
-                        self.campaign(CAMPAIGN_ELECTION);
-                    } else {
-                        self.become_leader();
-                        self.bcast_append();
+                match self.vote_result() {
+                    VoteResult::Won => {
+                        if self.state == StateRole::PreCandidate {
+                            self.campaign(CAMPAIGN_ELECTION);
+                        } else {
+                            self.become_leader();
+                            self.bcast_append();
+                        }
                     }
-                } else if self.quorum() == self.votes.len() - gr {
-                    self.become_follower(term, INVALID_ID);
+                    VoteResult::Lost => self.become_follower(term, INVALID_ID),
+                    VoteResult::Pending => {}
                 }
             }
             MessageType::MsgTimeoutNow => {
@@ -1578,7 +1734,21 @@ impl<T: Storage> Raft<T> {
               self.raft_log.last_term(),
               meta.get_index(),
               meta.get_term());
+        // ConfState doesn't record learners, keep the known ones which aren't voters in the
+        // snapshot. Neither does it record the joint consensus, the voters in the snapshot are
+        // used.
+        self.joint = None;
+        let learners: Vec<_> = self.learner_nodes()
+            .into_iter()
+            .filter(|id| !meta.get_conf_state().get_nodes().contains(id))
+            .collect();
         self.prs = FlatMap::with_capacity(meta.get_conf_state().get_nodes().len());
+        for id in learners {
+            let next_idx = self.raft_log.last_index() + 1;
+            let matched = if id == self.id { next_idx - 1 } else { 0 };
+            self.set_progress(id, matched, next_idx);
+            self.prs.get_mut(&id).unwrap().is_learner = true;
+        }
         for &n in meta.get_conf_state().get_nodes() {
             let next_idx = self.raft_log.last_index() + 1;
             let matched = if n == self.id { next_idx - 1 } else { 0 };
@@ -1605,14 +1775,24 @@ impl<T: Storage> Raft<T> {
     }
 
     // promotable indicates whether state machine can be promoted to leader,
-    // which is true when its own id is in progress list.
+    // which is true when its own id is in progress list and it's not a learner.
     pub fn promotable(&self) -> bool {
-        self.prs.contains_key(&self.id)
+        self.prs.get(&self.id).map_or(false, |p| !p.is_learner)
     }
 
+    // add_node adds a voter, or promotes the learner to a voter.
     pub fn add_node(&mut self, id: u64) {
         self.pending_conf = false;
-        if self.prs.contains_key(&id) {
+        if self.joint.is_some() {
+            warn!("{} ignored adding node {} in a joint consensus", self.tag, id);
+            return;
+        }
+        if let Some(pr) = self.prs.get_mut(&id) {
+            // The progress of the learner is kept, it has been receiving the log.
+            if pr.is_learner {
+                info!("{} promotes learner {} to voter", self.tag, id);
+                pr.is_learner = false;
+            }
             // Ignore any redundant addNode calls (which can happen because the
             // initial bootstrapping entries are applied twice).
             return;
@@ -1621,9 +1801,29 @@ impl<T: Storage> Raft<T> {
         self.set_progress(id, 0, last_index + 1);
     }
 
+    // add_learner adds a learner. It's ignored if the node is already a voter, since a voter
+    // can't be demoted. It's only used in tests for now, see Config::learners.
+    pub fn add_learner(&mut self, id: u64) {
+        self.pending_conf = false;
+        if self.joint.is_some() {
+            warn!("{} ignored adding learner {} in a joint consensus", self.tag, id);
+            return;
+        }
+        if self.prs.contains_key(&id) {
+            return;
+        }
+        let last_index = self.raft_log.last_index();
+        self.set_progress(id, 0, last_index + 1);
+        self.prs.get_mut(&id).unwrap().is_learner = true;
+    }
+
     pub fn remove_node(&mut self, id: u64) {
-        self.del_progress(id);
         self.pending_conf = false;
+        if self.joint.is_some() {
+            warn!("{} ignored removing node {} in a joint consensus", self.tag, id);
+            return;
+        }
+        self.del_progress(id);
 
         // do not try to commit or abort transferring if there is no nodes in the cluster.
         if self.prs.is_empty() {
@@ -1641,6 +1841,73 @@ impl<T: Storage> Raft<T> {
         }
     }
 
+    /// Enters the joint consensus which replaces the voters with `voters`. Until it's finalized,
+    /// the log is committed and the leader is elected by the majorities of both the current
+    /// voters and `voters`. The learners in `voters` are promoted.
+    pub fn begin_membership_change(&mut self, voters: &[u64]) {
+        self.pending_conf = false;
+        if self.joint.is_some() {
+            warn!("{} ignored membership change to {:?} in a joint consensus",
+                  self.tag,
+                  voters);
+            return;
+        }
+        if voters.is_empty() {
+            warn!("{} ignored membership change to no voters", self.tag);
+            return;
+        }
+        let mut incoming = voters.to_vec();
+        incoming.sort();
+        incoming.dedup();
+        let outgoing = self.nodes();
+        info!("{} begins membership change from {:?} to {:?}",
+              self.tag,
+              outgoing,
+              incoming);
+        let last_index = self.raft_log.last_index();
+        for &id in &incoming {
+            if let Some(pr) = self.prs.get_mut(&id) {
+                pr.is_learner = false;
+                continue;
+            }
+            self.set_progress(id, 0, last_index + 1);
+        }
+        self.joint = Some(JointConfig {
+            incoming: incoming,
+            outgoing: outgoing,
+        });
+    }
+
+    /// Leaves the joint consensus, the voters which are only in the outgoing configuration are
+    /// removed.
+    pub fn finalize_membership_change(&mut self) {
+        self.pending_conf = false;
+        let joint = match self.joint.take() {
+            Some(joint) => joint,
+            None => return,
+        };
+        info!("{} finalizes membership change from {:?} to {:?}",
+              self.tag,
+              joint.outgoing,
+              joint.incoming);
+        for id in joint.outgoing {
+            if joint.incoming.contains(&id) {
+                continue;
+            }
+            self.del_progress(id);
+            // If the removed node is the lead_transferee, then abort the leadership transferring.
+            if self.state == StateRole::Leader && self.lead_transferee == Some(id) {
+                self.abort_leader_transfer();
+            }
+        }
+
+        // The quorum only needs the incoming configuration now, see if any pending entries can
+        // be committed.
+        if self.maybe_commit() && !self.skip_bcast_commit {
+            self.bcast_append();
+        }
+    }
+
     pub fn reset_pending_conf(&mut self) {
         self.pending_conf = false;
     }
@@ -1694,22 +1961,23 @@ impl<T: Storage> Raft<T> {
     // false.
     // check_quorum_active also resets all recent_active to false.
     fn check_quorum_active(&mut self) -> bool {
-        let mut act = 0;
+        let mut active = HashSet::default();
         let self_id = self.id;
         for (id, p) in &mut self.prs {
             if id == &self_id {
                 // self is always active
-                act += 1;
+                active.insert(*id);
                 continue;
             }
 
             if p.recent_active {
-                act += 1;
+                active.insert(*id);
             }
 
             p.recent_active = false;
         }
-        act >= self.quorum()
+        // Learners aren't counted in the quorum.
+        self.has_quorum(|id| active.contains(&id))
     }
 
     pub fn send_timeout_now(&mut self, to: u64) {
@@ -281,6 +281,29 @@ impl<T: Storage> RawNode<T> {
         cs
     }
 
+    /// Replaces the voters with `voters` atomically through a joint consensus. The change should
+    /// be proposed by `propose_conf_change`, so that only one configuration change is pending,
+    /// and applied by this instead of `apply_conf_change`. Once it's applied, the application
+    /// proposes another change which calls `finalize_membership_change` when it's applied.
+    ///
+    /// ConfState can't describe a joint consensus, the returned one contains the voters of
+    /// both configurations. The application has to persist the joint consensus by itself and
+    /// set it to `Config::joint` on restart, until `finalize_membership_change` is applied.
+    pub fn begin_membership_change(&mut self, voters: &[u64]) -> ConfState {
+        self.raft.begin_membership_change(voters);
+        let mut cs = ConfState::new();
+        cs.set_nodes(self.raft.nodes());
+        cs
+    }
+
+    /// Leaves the joint consensus entered by `begin_membership_change`.
+    pub fn finalize_membership_change(&mut self) -> ConfState {
+        self.raft.finalize_membership_change();
+        let mut cs = ConfState::new();
+        cs.set_nodes(self.raft.nodes());
+        cs
+    }
+
     // Step advances the state machine using the given message.
     pub fn step(&mut self, m: Message) -> Result<()> {
         // ignore unexpected local messages receiving over network
@@ -105,17 +105,18 @@ impl ReadOnly {
     /// rev_ack notifies the ReadOnly struct that the raft state machine received
     /// an acknowledgment of the heartbeat that attached with the read only request
     /// context.
-    pub fn recv_ack(&mut self, m: &Message) -> usize {
-        match self.pending_read_index.get_mut(m.get_context()) {
-            None => 0,
-            Some(rs) => {
-                rs.acks.insert(m.get_from());
-                // add one to include an ack from local node
-                rs.acks.len() + 1
-            }
+    pub fn recv_ack(&mut self, m: &Message) {
+        if let Some(rs) = self.pending_read_index.get_mut(m.get_context()) {
+            rs.acks.insert(m.get_from());
         }
     }
 
+    /// Returns the nodes which have acknowledged the read only request with the context,
+    /// the local node is excluded.
+    pub fn acks(&self, ctx: &[u8]) -> Option<&HashSet<u64>> {
+        self.pending_read_index.get(ctx).map(|rs| &rs.acks)
+    }
+
     /// advance advances the read only request queue kept by the ReadOnly struct.
     /// It dequeues the requests until it finds the read only request that has
     /// the same context as the given `m`.
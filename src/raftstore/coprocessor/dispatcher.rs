@@ -11,10 +11,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{RegionObserver, ObserverContext, Result};
+use super::{RegionObserver, ObserverContext, RegionChangeEvent, Result};
 
-use kvproto::raft_cmdpb::RaftCmdRequest;
+use kvproto::raft_cmdpb::{RaftCmdRequest, RaftCmdResponse};
 use kvproto::metapb::Region;
+use raft::StateRole;
 
 struct ObserverEntry {
     priority: u32,
@@ -94,6 +95,53 @@ impl CoprocessorHost {
         }
     }
 
+    /// Call all post apply hook until bypass is set to true.
+    pub fn post_apply(&self,
+                      region: &Region,
+                      index: u64,
+                      req: &RaftCmdRequest,
+                      resp: &RaftCmdResponse) {
+        if resp.get_header().has_error() {
+            return;
+        }
+        let mut ctx = ObserverContext::new(region);
+        for entry in &self.registry.observers {
+            if req.has_admin_request() {
+                entry.observer.post_apply_admin(&mut ctx,
+                                                index,
+                                                req.get_admin_request(),
+                                                resp.get_admin_response());
+            } else {
+                entry.observer.post_apply_query(&mut ctx, index, req.get_requests());
+            }
+            if ctx.bypass {
+                break;
+            }
+        }
+    }
+
+    /// Call all role change hook until bypass is set to true.
+    pub fn on_role_change(&self, region: &Region, role: StateRole) {
+        let mut ctx = ObserverContext::new(region);
+        for entry in &self.registry.observers {
+            entry.observer.on_role_change(&mut ctx, role);
+            if ctx.bypass {
+                break;
+            }
+        }
+    }
+
+    /// Call all region changed hook until bypass is set to true.
+    pub fn on_region_changed(&self, region: &Region, event: RegionChangeEvent, role: StateRole) {
+        let mut ctx = ObserverContext::new(region);
+        for entry in &self.registry.observers {
+            entry.observer.on_region_changed(&mut ctx, event, role);
+            if ctx.bypass {
+                break;
+            }
+        }
+    }
+
     pub fn shutdown(&self) {
         for entry in &self.registry.observers {
             entry.observer.stop();
@@ -109,7 +157,9 @@ mod test {
     use protobuf::RepeatedField;
 
     use kvproto::metapb::Region;
-    use kvproto::raft_cmdpb::{AdminRequest, Request, RaftCmdRequest};
+    use kvproto::raft_cmdpb::{AdminRequest, AdminResponse, Request, RaftCmdRequest,
+                              RaftCmdResponse};
+    use raft::StateRole;
 
     struct TestCoprocessor {
         bypass: Arc<AtomicBool>,
@@ -159,6 +209,36 @@ mod test {
             self.called.fetch_add(3, Ordering::SeqCst);
             ctx.bypass = self.bypass.load(Ordering::SeqCst);
         }
+
+        fn post_apply_query(&self,
+                            ctx: &mut ObserverContext,
+                            _: u64,
+                            _: &RepeatedField<Request>) {
+            self.called.fetch_add(4, Ordering::SeqCst);
+            ctx.bypass = self.bypass.load(Ordering::SeqCst);
+        }
+
+        fn post_apply_admin(&self,
+                            ctx: &mut ObserverContext,
+                            _: u64,
+                            _: &AdminRequest,
+                            _: &AdminResponse) {
+            self.called.fetch_add(5, Ordering::SeqCst);
+            ctx.bypass = self.bypass.load(Ordering::SeqCst);
+        }
+
+        fn on_role_change(&self, ctx: &mut ObserverContext, _: StateRole) {
+            self.called.fetch_add(6, Ordering::SeqCst);
+            ctx.bypass = self.bypass.load(Ordering::SeqCst);
+        }
+
+        fn on_region_changed(&self,
+                             ctx: &mut ObserverContext,
+                             _: RegionChangeEvent,
+                             _: StateRole) {
+            self.called.fetch_add(7, Ordering::SeqCst);
+            ctx.bypass = self.bypass.load(Ordering::SeqCst);
+        }
     }
 
     fn share_bool() -> Arc<AtomicBool> {
@@ -223,6 +303,24 @@ mod test {
         host.pre_apply(&region, &mut query_req);
         assert_all!(&[&called1, &called2], &[0, 5]);
 
+        host.post_apply(&region, 1, &query_req, &RaftCmdResponse::new());
+        assert_all!(&[&called1, &called2], &[0, 9]);
+
+        host.post_apply(&region, 1, &admin_req, &RaftCmdResponse::new());
+        assert_all!(&[&called1, &called2], &[0, 14]);
+
+        // post_apply is ignored when handling failed request.
+        let mut err_resp = RaftCmdResponse::new();
+        err_resp.mut_header().mut_error().set_message("error".to_owned());
+        host.post_apply(&region, 1, &query_req, &err_resp);
+        assert_all!(&[&called1, &called2], &[0, 14]);
+
+        host.on_role_change(&region, StateRole::Leader);
+        assert_all!(&[&called1, &called2], &[0, 20]);
+
+        host.on_region_changed(&region, RegionChangeEvent::Update, StateRole::Leader);
+        assert_all!(&[&called1, &called2], &[0, 27]);
+
         set_all!(&[&bypass2], false);
         set_all!(&[&called2], 0);
 
@@ -19,10 +19,12 @@ mod error;
 pub use self::region_snapshot::{RegionSnapshot, RegionIterator};
 pub use self::dispatcher::{CoprocessorHost, Registry};
 
-use kvproto::raft_cmdpb::{AdminRequest, Request};
+use kvproto::raft_cmdpb::{AdminRequest, AdminResponse, Request};
 use kvproto::metapb::Region;
 use protobuf::RepeatedField;
 
+use raft::StateRole;
+
 pub use self::error::{Error, Result};
 
 
@@ -53,6 +55,17 @@ impl<'a> ObserverContext<'a> {
     }
 }
 
+/// The change of the meta of a region in the local store.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionChangeEvent {
+    /// A peer of the region is created, by restarting, splitting or applying a snapshot.
+    Create,
+    /// The range or the peers of the region are changed.
+    Update,
+    /// The peer of the region is destroyed.
+    Destroy,
+}
+
 /// Observer hook of region level.
 pub trait RegionObserver: Coprocessor {
     /// Hook to call before execute admin request.
@@ -69,4 +82,26 @@ pub trait RegionObserver: Coprocessor {
     ///
     /// Please note that improper implementation can lead to data inconsistency.
     fn pre_apply_query(&self, _: &mut ObserverContext, _: &mut RepeatedField<Request>) {}
+
+    /// Hook to call after read/write request is applied successfully at the log index.
+    ///
+    /// The data written by the requests may not be visible in the engine yet.
+    fn post_apply_query(&self, _: &mut ObserverContext, _: u64, _: &RepeatedField<Request>) {}
+
+    /// Hook to call after admin request is applied successfully at the log index.
+    ///
+    /// The region in the context has taken the effect of the request already.
+    fn post_apply_admin(&self,
+                        _: &mut ObserverContext,
+                        _: u64,
+                        _: &AdminRequest,
+                        _: &AdminResponse) {
+    }
+
+    /// Hook to call when the raft state of the local peer is changed.
+    fn on_role_change(&self, _: &mut ObserverContext, _: StateRole) {}
+
+    /// Hook to call when the region is created, updated or destroyed in the local store, the
+    /// role is the raft state of the local peer.
+    fn on_region_changed(&self, _: &mut ObserverContext, _: RegionChangeEvent, _: StateRole) {}
 }
@@ -30,10 +30,15 @@ const RAFT_LOG_GC_THRESHOLD: u64 = 50;
 const RAFT_LOG_GC_COUNT_LIMIT: u64 = REGION_SPLIT_SIZE * 3 / 4 / 1024;
 const RAFT_LOG_GC_SIZE_LIMIT: u64 = REGION_SPLIT_SIZE * 3 / 4;
 const SPLIT_REGION_CHECK_TICK_INTERVAL: u64 = 10000;
+const LOAD_SPLIT_CHECK_TICK_INTERVAL: u64 = 1000;
+const REGION_SPLIT_QPS_THRESHOLD: u64 = 3000;
+const LOAD_SPLIT_DETECT_TIMES: usize = 10;
 
 pub const REGION_SPLIT_SIZE: u64 = 256 * 1024 * 1024;
 pub const REGION_MAX_SIZE: u64 = REGION_SPLIT_SIZE / 2 * 3;
 pub const REGION_CHECK_DIFF: u64 = REGION_SPLIT_SIZE / 8;
+pub const REGION_SPLIT_KEYS: u64 = 960000;
+pub const REGION_MAX_KEYS: u64 = REGION_SPLIT_KEYS / 2 * 3;
 
 const REGION_COMPACT_CHECK_TICK_INTERVAL: u64 = 0; // disable manual compaction by default.
 const REGION_COMPACT_DELETE_KEYS_COUNT: u64 = 1_000_000;
@@ -100,6 +105,26 @@ pub struct Config {
     /// When size change of region exceed the diff since last check, it
     /// will be checked again whether it should be split.
     pub region_check_size_diff: u64,
+    /// Whether to take the size and the split key of a region from the size properties of the
+    /// sst files instead of scanning the region. The region is still scanned if the properties
+    /// are missing.
+    pub split_region_check_approximate: bool,
+    /// When the number of keys of region [a, b) meets region_max_keys, it will be split
+    /// into [a, c), [c, b), and [a, c) will have region_split_keys keys. The keys are the
+    /// keys in the write cf, the versions of a key are counted once. 0 means regions are never
+    /// split by the number of keys.
+    pub region_max_keys: u64,
+    pub region_split_keys: u64,
+    /// Whether to split a region at the table boundaries in it, so that every region holds
+    /// the data of one table at most.
+    pub split_region_on_table: bool,
+    /// Interval (ms) to check whether a region should be split by its load, 0 means regions
+    /// are never split by load.
+    pub load_split_check_tick_interval: u64,
+    /// When the qps of a region stays above the threshold for `load_split_detect_times`
+    /// checks in a row, it will be split into two regions taking about the same load.
+    pub region_split_qps_threshold: u64,
+    pub load_split_detect_times: usize,
     /// Interval (ms) to check whether start compaction for a region.
     pub region_compact_check_interval: u64,
     /// When delete keys of a region exceeds the size, a compaction will
@@ -161,6 +186,13 @@ impl Default for Config {
             region_max_size: REGION_MAX_SIZE,
             region_split_size: REGION_SPLIT_SIZE,
             region_check_size_diff: REGION_CHECK_DIFF,
+            split_region_check_approximate: false,
+            region_max_keys: REGION_MAX_KEYS,
+            region_split_keys: REGION_SPLIT_KEYS,
+            split_region_on_table: false,
+            load_split_check_tick_interval: LOAD_SPLIT_CHECK_TICK_INTERVAL,
+            region_split_qps_threshold: REGION_SPLIT_QPS_THRESHOLD,
+            load_split_detect_times: LOAD_SPLIT_DETECT_TIMES,
             region_compact_check_interval: REGION_COMPACT_CHECK_TICK_INTERVAL,
             region_compact_delete_keys_count: REGION_COMPACT_DELETE_KEYS_COUNT,
             pd_heartbeat_tick_interval: PD_HEARTBEAT_TICK_INTERVAL,
@@ -213,6 +245,16 @@ impl Config {
                                 self.region_split_size));
         }
 
+        if self.region_max_keys > 0 && self.region_max_keys < self.region_split_keys {
+            return Err(box_err!("region max keys {} must >= split keys {}",
+                                self.region_max_keys,
+                                self.region_split_keys));
+        }
+
+        if self.load_split_detect_times == 0 {
+            return Err(box_err!("load split detect times must be greater than 0"));
+        }
+
         let election_timeout = self.raft_base_tick_interval *
                                self.raft_election_timeout_ticks as u64;
         let lease = self.raft_store_max_leader_lease.num_milliseconds() as u64;
@@ -260,6 +302,18 @@ mod tests {
         cfg.region_split_size = 20;
         assert!(cfg.validate().is_err());
 
+        cfg = Config::new();
+        cfg.region_max_keys = 10;
+        cfg.region_split_keys = 20;
+        assert!(cfg.validate().is_err());
+
+        cfg.region_max_keys = 0;
+        assert!(cfg.validate().is_ok());
+
+        cfg = Config::new();
+        cfg.load_split_detect_times = 0;
+        assert!(cfg.validate().is_err());
+
         cfg = Config::new();
         cfg.raft_base_tick_interval = 1000;
         cfg.raft_election_timeout_ticks = 10;
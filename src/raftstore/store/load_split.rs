@@ -0,0 +1,305 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{cmp, mem};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::Instant;
+
+use rand::{self, Rng};
+
+use util::collections::HashMap;
+use util::time::duration_to_sec;
+
+// The max number of keys sampled in a region during a check interval.
+const SAMPLE_NUM: usize = 32;
+// The number of the shards of the recorder, every recording thread uses one of them.
+const SHARD_NUM: usize = 16;
+
+static SHARD_ALLOC: AtomicUsize = ATOMIC_USIZE_INIT;
+
+thread_local!(static SHARD: usize = SHARD_ALLOC.fetch_add(1, Ordering::Relaxed) % SHARD_NUM);
+
+/// The keys accessed in a region during a check interval.
+#[derive(Default, Debug)]
+pub struct RegionLoad {
+    // The number of the accessed keys.
+    pub count: u64,
+    // At most `SAMPLE_NUM` keys sampled uniformly from the accessed keys.
+    pub samples: Vec<Vec<u8>>,
+}
+
+impl RegionLoad {
+    fn record(&mut self, key: &[u8]) {
+        self.count += 1;
+        if self.samples.len() < SAMPLE_NUM {
+            self.samples.push(key.to_vec());
+            return;
+        }
+        // Reservoir sampling.
+        let i = rand::thread_rng().gen_range(0, self.count) as usize;
+        if i < SAMPLE_NUM {
+            self.samples[i] = key.to_vec();
+        }
+    }
+
+    // Merges the load recorded in another shard. Every sample is taken from either of them in
+    // proportion to their counts, so the samples stay uniform over all the accessed keys.
+    fn merge(&mut self, other: RegionLoad) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = other;
+            return;
+        }
+        let count = self.count + other.count;
+        let num = cmp::min(SAMPLE_NUM, self.samples.len() + other.samples.len());
+        let mut rng = rand::thread_rng();
+        let mut samples = Vec::with_capacity(num);
+        for _ in 0..num {
+            let from = if rng.gen_range(0, count) < self.count {
+                &self.samples
+            } else {
+                &other.samples
+            };
+            let i = rng.gen_range(0, from.len());
+            samples.push(from[i].clone());
+        }
+        self.count = count;
+        self.samples = samples;
+    }
+}
+
+/// `LoadRecorder` records the keys accessed by the read and write requests of every region. It's
+/// shared by the storage, which records the keys, and the raftstore, which splits the hot regions.
+/// Every recording thread records in its own shard, so the requests of a hot region don't contend
+/// on a single lock.
+#[derive(Clone)]
+pub struct LoadRecorder {
+    shards: Arc<Vec<Mutex<HashMap<u64, RegionLoad>>>>,
+}
+
+impl LoadRecorder {
+    pub fn new() -> LoadRecorder {
+        LoadRecorder {
+            shards: Arc::new((0..SHARD_NUM).map(|_| Mutex::new(HashMap::default())).collect()),
+        }
+    }
+
+    fn local_shard(&self) -> &Mutex<HashMap<u64, RegionLoad>> {
+        &self.shards[SHARD.with(|s| *s)]
+    }
+
+    pub fn record(&self, region_id: u64, key: &[u8]) {
+        let mut regions = self.local_shard().lock().unwrap();
+        regions.entry(region_id).or_insert_with(RegionLoad::default).record(key);
+    }
+
+    pub fn record_keys<'a, I>(&self, region_id: u64, keys: I)
+        where I: IntoIterator<Item = &'a [u8]>
+    {
+        let mut regions = self.local_shard().lock().unwrap();
+        let load = regions.entry(region_id).or_insert_with(RegionLoad::default);
+        for key in keys {
+            load.record(key);
+        }
+    }
+
+    /// Takes the loads recorded since the last call.
+    pub fn take(&self) -> HashMap<u64, RegionLoad> {
+        let mut res: HashMap<u64, RegionLoad> = HashMap::default();
+        for shard in self.shards.iter() {
+            let regions = mem::replace(&mut *shard.lock().unwrap(), HashMap::default());
+            for (region_id, load) in regions {
+                res.entry(region_id).or_insert_with(RegionLoad::default).merge(load);
+            }
+        }
+        res
+    }
+}
+
+impl Default for LoadRecorder {
+    fn default() -> LoadRecorder {
+        LoadRecorder::new()
+    }
+}
+
+// Returns the key which splits the samples evenly, or None if the samples can't be split.
+fn find_split_key(mut samples: Vec<Vec<u8>>) -> Option<Vec<u8>> {
+    if samples.len() < 2 {
+        return None;
+    }
+    samples.sort();
+    let mid = samples.len() / 2;
+    // All the samples before the middle one are the same key, so splitting there takes nothing
+    // off the region.
+    if samples[0] == samples[mid] {
+        return None;
+    }
+    Some(samples.swap_remove(mid))
+}
+
+struct HotRegion {
+    // The number of the check intervals in a row the qps is above the threshold.
+    times: usize,
+    samples: Vec<Vec<u8>>,
+}
+
+/// `LoadSplitChecker` finds the split keys of the regions whose qps stays above the threshold.
+pub struct LoadSplitChecker {
+    recorder: LoadRecorder,
+    qps_threshold: u64,
+    detect_times: usize,
+    hot_regions: HashMap<u64, HotRegion>,
+    last_check: Instant,
+}
+
+impl LoadSplitChecker {
+    pub fn new(recorder: LoadRecorder,
+               qps_threshold: u64,
+               detect_times: usize)
+               -> LoadSplitChecker {
+        LoadSplitChecker {
+            recorder: recorder,
+            qps_threshold: qps_threshold,
+            detect_times: detect_times,
+            hot_regions: HashMap::default(),
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Checks the loads recorded since the last check. Returns the regions which have been hot
+    /// for `detect_times` checks in a row, with the keys to split them at.
+    pub fn check(&mut self) -> Vec<(u64, Vec<u8>)> {
+        let now = Instant::now();
+        let elapsed = duration_to_sec(now.duration_since(self.last_check));
+        self.last_check = now;
+
+        let mut hot_regions = HashMap::default();
+        let mut res = vec![];
+        for (region_id, load) in self.recorder.take() {
+            if elapsed <= 0.0 || (load.count as f64) / elapsed < self.qps_threshold as f64 {
+                continue;
+            }
+            let mut hot = self.hot_regions.remove(&region_id).unwrap_or_else(|| {
+                HotRegion {
+                    times: 0,
+                    samples: vec![],
+                }
+            });
+            hot.times += 1;
+            hot.samples.extend(load.samples);
+            if hot.times < self.detect_times {
+                hot_regions.insert(region_id, hot);
+                continue;
+            }
+            if let Some(key) = find_split_key(hot.samples) {
+                res.push((region_id, key));
+            }
+        }
+        // The regions not hot in this interval start over.
+        self.hot_regions = hot_regions;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_region_load() {
+        let mut load = RegionLoad::default();
+        for i in 0..SAMPLE_NUM * 4 {
+            load.record(format!("k{:04}", i).as_bytes());
+        }
+        assert_eq!(load.count, SAMPLE_NUM as u64 * 4);
+        assert_eq!(load.samples.len(), SAMPLE_NUM);
+    }
+
+    #[test]
+    fn test_load_recorder_shards() {
+        let recorder = LoadRecorder::new();
+        let handles: Vec<_> = (0..SHARD_NUM * 2)
+            .map(|i| {
+                let recorder = recorder.clone();
+                thread::spawn(move || {
+                    for j in 0..SAMPLE_NUM {
+                        recorder.record(1, format!("k{:02}{:02}", i, j).as_bytes());
+                    }
+                    recorder.record(2, b"k");
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let loads = recorder.take();
+        assert_eq!(loads[&1].count, (SHARD_NUM * 2 * SAMPLE_NUM) as u64);
+        assert_eq!(loads[&1].samples.len(), SAMPLE_NUM);
+        assert_eq!(loads[&2].count, SHARD_NUM as u64 * 2);
+        assert_eq!(loads[&2].samples, vec![b"k".to_vec(); SHARD_NUM * 2]);
+        assert!(recorder.take().is_empty());
+    }
+
+    fn new_keys(keys: &[&str]) -> Vec<Vec<u8>> {
+        keys.iter().map(|k| k.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_find_split_key() {
+        assert_eq!(find_split_key(vec![]), None);
+        assert_eq!(find_split_key(new_keys(&["k1"])), None);
+        assert_eq!(find_split_key(new_keys(&["k3", "k1", "k2"])),
+                   Some(b"k2".to_vec()));
+        assert_eq!(find_split_key(new_keys(&["k1", "k2", "k1", "k2"])),
+                   Some(b"k2".to_vec()));
+        // A single hot key can't be split.
+        assert_eq!(find_split_key(new_keys(&["k1", "k1", "k1", "k2"])), None);
+    }
+
+    #[test]
+    fn test_load_split_checker() {
+        let recorder = LoadRecorder::new();
+        let mut checker = LoadSplitChecker::new(recorder.clone(), 1, 2);
+
+        let record = |region_id: u64| {
+            let keys: Vec<_> = (0..10).map(|i| format!("k{}", i).into_bytes()).collect();
+            recorder.record_keys(region_id, keys.iter().map(|k| k.as_slice()));
+        };
+        thread::sleep(Duration::from_millis(10));
+        record(1);
+        record(2);
+        assert!(checker.check().is_empty());
+
+        // Region 2 isn't hot any more, and starts over.
+        thread::sleep(Duration::from_millis(10));
+        record(1);
+        assert_eq!(checker.check(), vec![(1, b"k5".to_vec())]);
+
+        thread::sleep(Duration::from_millis(10));
+        record(2);
+        assert!(checker.check().is_empty());
+
+        // The qps is below the threshold.
+        let mut checker = LoadSplitChecker::new(recorder.clone(), 1_000_000, 1);
+        thread::sleep(Duration::from_millis(10));
+        recorder.record(1, b"k1");
+        recorder.record(1, b"k2");
+        assert!(checker.check().is_empty());
+    }
+}
@@ -28,10 +28,12 @@ mod worker;
 mod metrics;
 mod engine_metrics;
 mod local_metrics;
+mod load_split;
 
-pub use self::msg::{Msg, Callback, BatchCallback, Tick, SnapshotStatusMsg};
+pub use self::msg::{Msg, Callback, BatchCallback, SplitRegionCallback, Tick, SnapshotStatusMsg};
 pub use self::store::{StoreChannel, Store, create_event_loop};
 pub use self::config::Config;
+pub use self::load_split::LoadRecorder;
 pub use self::transport::Transport;
 pub use self::peer::Peer;
 pub use self::bootstrap::{bootstrap_store, prepare_bootstrap, write_prepare_bootstrap,
@@ -17,13 +17,15 @@ use std::fmt;
 
 use kvproto::raft_serverpb::RaftMessage;
 use kvproto::raft_cmdpb::{RaftCmdRequest, RaftCmdResponse};
-use kvproto::metapb::RegionEpoch;
+use kvproto::metapb::{Region, RegionEpoch};
+use kvproto::errorpb;
 use raft::SnapshotStatus;
 
 use util::escape;
 
 pub type Callback = Box<FnBox(RaftCmdResponse) + Send>;
 pub type BatchCallback = Box<FnBox(Vec<Option<RaftCmdResponse>>) + Send>;
+pub type SplitRegionCallback = Box<FnBox(Result<Vec<Region>, errorpb::Error>) + Send>;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Tick {
@@ -37,6 +39,7 @@ pub enum Tick {
     CompactLockCf,
     ConsistencyCheck,
     ReportRegionFlow,
+    LoadSplitCheck,
 }
 
 pub struct SnapshotStatusMsg {
@@ -57,6 +60,14 @@ pub enum Msg {
         callback: Callback,
     },
 
+    // A read-only command which may be served by a follower once it has applied up to the
+    // read index of the leader.
+    FollowerReadCmd {
+        send_time: Instant,
+        request: RaftCmdRequest,
+        callback: Callback,
+    },
+
     BatchRaftSnapCmds {
         send_time: Instant,
         batch: Vec<RaftCmdRequest>,
@@ -70,6 +81,27 @@ pub enum Msg {
         split_key: Vec<u8>,
     },
 
+    // Asks the leader to split the region at the key through pd. The callback gets the response
+    // of the split command, which holds the resulting regions.
+    SplitRegion {
+        region_id: u64,
+        region_epoch: RegionEpoch,
+        // The key to split the region at, not a data key.
+        split_key: Vec<u8>,
+        callback: Option<Callback>,
+    },
+
+    // Asks the leader to split the region at all the keys, one split after another. The callback
+    // gets all the resulting regions sorted by their start keys, or the error of the first
+    // failed split, in which case the splits before it are kept.
+    BatchSplitRegion {
+        region_id: u64,
+        region_epoch: RegionEpoch,
+        // The keys to split the region at, not data keys.
+        split_keys: Vec<Vec<u8>>,
+        callback: SplitRegionCallback,
+    },
+
     ReportUnreachable { region_id: u64, to_peer_id: u64 },
 
     // For snapshot stats.
@@ -89,8 +121,15 @@ impl fmt::Debug for Msg {
             Msg::Quit => write!(fmt, "Quit"),
             Msg::RaftMessage(_) => write!(fmt, "Raft Message"),
             Msg::RaftCmd { .. } => write!(fmt, "Raft Command"),
+            Msg::FollowerReadCmd { .. } => write!(fmt, "Follower Read Command"),
             Msg::BatchRaftSnapCmds { .. } => write!(fmt, "Batch Raft Commands"),
             Msg::SplitCheckResult { .. } => write!(fmt, "Split Check Result"),
+            Msg::SplitRegion { region_id, ref split_key, .. } => {
+                write!(fmt, "Split region {} at key {}", region_id, escape(split_key))
+            }
+            Msg::BatchSplitRegion { region_id, ref split_keys, .. } => {
+                write!(fmt, "Split region {} at {} keys", region_id, split_keys.len())
+            }
             Msg::ReportUnreachable { ref region_id, ref to_peer_id } => {
                 write!(fmt,
                        "peer {} for region {} is unreachable",
@@ -118,6 +157,14 @@ impl Msg {
         }
     }
 
+    pub fn new_follower_read_cmd(request: RaftCmdRequest, callback: Callback) -> Msg {
+        Msg::FollowerReadCmd {
+            send_time: Instant::now(),
+            request: request,
+            callback: callback,
+        }
+    }
+
     pub fn new_batch_raft_snapshot_cmd(batch: Vec<RaftCmdRequest>,
                                        on_finished: BatchCallback)
                                        -> Msg {
@@ -18,7 +18,7 @@ use std::collections::VecDeque;
 use std::{cmp, mem, slice};
 use std::time::{Instant, Duration};
 
-use time::Timespec;
+use time::{Timespec, Duration as TimeDuration};
 use rocksdb::{DB, WriteBatch};
 use protobuf::{self, Message, MessageStatic};
 use kvproto::metapb;
@@ -60,6 +60,8 @@ struct ReadIndexRequest {
     id: u64,
     cmds: Vec<(RaftCmdRequest, Callback)>,
     renew_lease_time: Timespec,
+    // The read index answered by raft, `None` if it's not answered yet.
+    read_index: Option<u64>,
 }
 
 impl ReadIndexRequest {
@@ -433,6 +435,11 @@ impl Peer {
         self.raft_group.raft.state == StateRole::Leader
     }
 
+    #[inline]
+    pub fn get_role(&self) -> StateRole {
+        self.raft_group.raft.state
+    }
+
     #[inline]
     pub fn get_store(&self) -> &PeerStorage {
         self.raft_group.get_store()
@@ -600,6 +607,7 @@ impl Peer {
     fn on_role_changed(&mut self, ready: &Ready, worker: &FutureWorker<PdTask>) {
         // Update leader lease when the Raft state changes.
         if let Some(ref ss) = ready.ss {
+            self.coprocessor_host.on_role_change(self.region(), ss.raft_state);
             match ss.raft_state {
                 StateRole::Leader => {
                     // The local read can only be performed after a new leader has applied
@@ -784,7 +792,9 @@ impl Peer {
 
     fn apply_reads(&mut self, ready: &Ready) {
         let mut propose_time = None;
-        if self.ready_to_handle_read() {
+        if !self.is_leader() {
+            self.apply_follower_reads(ready);
+        } else if self.ready_to_handle_read() {
             for state in &ready.read_states {
                 let mut read = self.pending_reads.reads.pop_front().unwrap();
                 assert_eq!(state.request_ctx.as_slice(), read.binary_id());
@@ -798,8 +808,9 @@ impl Peer {
             }
         } else {
             for state in &ready.read_states {
-                let read = &self.pending_reads.reads[self.pending_reads.ready_cnt];
+                let read = &mut self.pending_reads.reads[self.pending_reads.ready_cnt];
                 assert_eq!(state.request_ctx.as_slice(), read.binary_id());
+                read.read_index = Some(state.index);
                 self.pending_reads.ready_cnt += 1;
                 propose_time = Some(read.renew_lease_time);
             }
@@ -822,6 +833,78 @@ impl Peer {
         }
     }
 
+    fn apply_follower_reads(&mut self, ready: &Ready) {
+        let term = self.term();
+        for state in &ready.read_states {
+            let ready_cnt = self.pending_reads.ready_cnt;
+            let pos = self.pending_reads
+                .reads
+                .iter()
+                .skip(ready_cnt)
+                .position(|read| read.binary_id() == state.request_ctx.as_slice());
+            let pos = match pos {
+                Some(pos) => ready_cnt + pos,
+                // The request has been cleared as stale.
+                None => continue,
+            };
+            // The leader drops read index requests silently, for example when it hasn't
+            // committed an entry in its term yet, so the requests sent before an answered one
+            // will never be answered.
+            for mut read in self.pending_reads.reads.drain(ready_cnt..pos) {
+                for (_, cb) in read.cmds.drain(..) {
+                    apply::notify_stale_req(term, cb);
+                }
+            }
+            self.pending_reads.reads[ready_cnt].read_index = Some(state.index);
+            self.pending_reads.ready_cnt += 1;
+        }
+        self.handle_follower_reads();
+    }
+
+    /// Fails the reads of a follower not answered within an election timeout. The read index
+    /// request may be lost on the way or dropped by the leader, and then the read would wait
+    /// forever.
+    pub fn check_follower_reads(&mut self) {
+        if self.is_leader() {
+            return;
+        }
+        let election_timeout = self.cfg.raft_base_tick_interval *
+                               self.cfg.raft_election_timeout_ticks as u64;
+        let timeout = TimeDuration::milliseconds(election_timeout as i64);
+        let now = monotonic_raw_now();
+        let term = self.term();
+        let ready_cnt = self.pending_reads.ready_cnt;
+        // The unanswered reads are in the order they are sent.
+        while self.pending_reads.reads.len() > ready_cnt {
+            if self.pending_reads.reads[ready_cnt].renew_lease_time + timeout > now {
+                break;
+            }
+            let mut read = self.pending_reads.reads.remove(ready_cnt).unwrap();
+            for (_, cb) in read.cmds.drain(..) {
+                apply::notify_stale_req(term, cb);
+            }
+        }
+    }
+
+    /// Serves the answered reads of a follower whose read indexes have been applied.
+    fn handle_follower_reads(&mut self) {
+        if self.is_applying_snapshot() {
+            // The applied index is set before the data of the snapshot is written.
+            return;
+        }
+        let applied_index = self.get_store().applied_index();
+        while self.pending_reads.ready_cnt > 0 {
+            if self.pending_reads.reads[0].read_index.unwrap() > applied_index {
+                break;
+            }
+            let mut read = self.pending_reads.reads.pop_front().unwrap();
+            self.pending_reads.ready_cnt -= 1;
+            for (req, cb) in read.cmds.drain(..) {
+                cb(self.handle_read(req));
+            }
+        }
+    }
+
     pub fn post_apply(&mut self, res: &ApplyRes, groups: &mut HashSet<u64>) {
         if self.is_applying_snapshot() {
             panic!("{} should not applying snapshot.", self.tag);
@@ -853,7 +936,9 @@ impl Peer {
             self.mark_to_be_checked(groups);
         }
 
-        if self.pending_reads.ready_cnt > 0 && self.ready_to_handle_read() {
+        if self.pending_reads.ready_cnt > 0 && !self.is_leader() {
+            self.handle_follower_reads();
+        } else if self.pending_reads.ready_cnt > 0 && self.ready_to_handle_read() {
             for _ in 0..self.pending_reads.ready_cnt {
                 let mut read = self.pending_reads.reads.pop_front().unwrap();
                 for (req, cb) in read.cmds.drain(..) {
@@ -993,6 +1078,35 @@ impl Peer {
         }
     }
 
+    /// Propose a read-only request which may be served by a follower. The leader handles it
+    /// like `propose`, while a follower asks the leader for a read index and serves the request
+    /// once it has applied up to the read index.
+    ///
+    /// Return true means the request has been proposed successfully.
+    pub fn propose_follower_read(&mut self,
+                                 cb: Callback,
+                                 req: RaftCmdRequest,
+                                 mut err_resp: RaftCmdResponse,
+                                 metrics: &mut RaftProposeMetrics)
+                                 -> bool {
+        if self.is_leader() {
+            return self.propose(cb, req, err_resp, metrics);
+        }
+        if self.pending_remove {
+            return false;
+        }
+
+        metrics.all += 1;
+
+        if let Err(e) = self.check_follower_read(&req) {
+            cmd_resp::bind_error(&mut err_resp, e);
+            cb(err_resp);
+            return false;
+        }
+        self.follower_read_index(req, cb, metrics);
+        true
+    }
+
     /// Propose a snapshot request. Note that the `None` response means
     /// it requires the peer to perform a read-index. The request never
     /// be actual proposed to other nodes.
@@ -1248,6 +1362,7 @@ impl Peer {
             id: id,
             cmds: vec![(req, cb)],
             renew_lease_time: renew_lease_time,
+            read_index: None,
         });
 
         match self.leader_lease_expired_time {
@@ -1271,6 +1386,45 @@ impl Peer {
         true
     }
 
+    fn check_follower_read(&self, req: &RaftCmdRequest) -> Result<()> {
+        if req.has_admin_request() {
+            return Err(box_err!("admin request can't be served by a follower"));
+        }
+        for r in req.get_requests() {
+            match r.get_cmd_type() {
+                CmdType::Get | CmdType::Snap => {}
+                cmd_type => {
+                    return Err(box_err!("{:?} request can't be served by a follower", cmd_type));
+                }
+            }
+        }
+        // The read index request is forwarded to the leader, or dropped if there is no leader.
+        if self.leader_id() == raft::INVALID_ID {
+            return Err(Error::NotLeader(self.region_id, None));
+        }
+        Ok(())
+    }
+
+    fn follower_read_index(&mut self,
+                           req: RaftCmdRequest,
+                           cb: Callback,
+                           metrics: &mut RaftProposeMetrics) {
+        metrics.read_index += 1;
+
+        // Unlike the leader, a follower can't share a pending read index between requests, as
+        // the leader may have captured it before the later requests arrived.
+        let id = self.pending_reads.next_id();
+        let ctx: [u8; 8] = unsafe { mem::transmute(id) };
+        self.raft_group.read_index(ctx.to_vec());
+
+        self.pending_reads.reads.push_back(ReadIndexRequest {
+            id: id,
+            cmds: vec![(req, cb)],
+            renew_lease_time: monotonic_raw_now(),
+            read_index: None,
+        });
+    }
+
     fn propose_normal(&mut self,
                       mut req: RaftCmdRequest,
                       metrics: &mut RaftProposeMetrics)
@@ -36,17 +36,17 @@ use util::escape;
 use util::time::{SlowTimer, duration_to_sec};
 use pd::PdClient;
 use kvproto::raft_cmdpb::{AdminCmdType, AdminRequest, StatusCmdType, StatusResponse,
-                          RaftCmdRequest, RaftCmdResponse};
+                          RaftCmdRequest, RaftCmdResponse, CmdType};
 use protobuf::Message;
 use raft::{self, SnapshotStatus, INVALID_INDEX};
 use raftstore::{Result, Error};
 use kvproto::metapb;
-use util::worker::{Worker, Scheduler, FutureWorker};
-use util::transport::SendCh;
+use util::worker::{Worker, Scheduler, FutureWorker, Stopped};
+use util::transport::{NotifyError, SendCh};
 use util::{rocksdb, RingQueue};
 use util::collections::{HashMap, HashSet};
-use storage::{CF_DEFAULT, CF_LOCK, CF_WRITE, CF_RAFT};
-use raftstore::coprocessor::CoprocessorHost;
+use storage::{CF_DEFAULT, CF_LOCK, CF_WRITE, CF_RAFT, CF_RAW};
+use raftstore::coprocessor::{CoprocessorHost, RegionChangeEvent};
 use raftstore::coprocessor::split_observer::SplitObserver;
 use super::worker::{SplitCheckRunner, SplitCheckTask, RegionTask, RegionRunner, CompactTask,
                     CompactRunner, RaftlogGcTask, RaftlogGcRunner, PdRunner, PdTask,
@@ -59,12 +59,13 @@ use super::engine::{Iterable, Peekable, Snapshot as EngineSnapshot};
 use super::config::Config;
 use super::peer::{self, Peer, StaleState, ConsistencyState, ReadyContext};
 use super::peer_storage::{self, ApplySnapResult, CacheQueryStats};
-use super::msg::{Callback, BatchCallback};
+use super::msg::{Callback, BatchCallback, SplitRegionCallback};
 use super::cmd_resp::{bind_term, new_error};
 use super::transport::Transport;
 use super::metrics::*;
 use super::engine_metrics::*;
 use super::local_metrics::RaftMetrics;
+use super::load_split::{LoadRecorder, LoadSplitChecker};
 use prometheus::local::LocalHistogram;
 
 type Key = Vec<u8>;
@@ -120,6 +121,7 @@ pub struct Store<T, C: 'static> {
     compact_worker: Worker<CompactTask>,
     pd_worker: FutureWorker<PdTask>,
     consistency_check_worker: Worker<ConsistencyCheckTask>,
+    load_split_checker: LoadSplitChecker,
 
     pub apply_worker: Worker<ApplyTask>,
     apply_res_receiver: Option<StdReceiver<ApplyTaskRes>>,
@@ -177,7 +179,9 @@ impl<T, C> Store<T, C> {
                engine: Arc<DB>,
                trans: T,
                pd_client: Arc<C>,
-               mgr: SnapManager)
+               mgr: SnapManager,
+               mut coprocessor_host: CoprocessorHost,
+               load_recorder: LoadRecorder)
                -> Result<Store<T, C>> {
         // TODO: we can get cluster meta regularly too later.
         try!(cfg.validate());
@@ -185,10 +189,12 @@ impl<T, C> Store<T, C> {
         let sendch = SendCh::new(ch.sender, "raftstore");
         let tag = format!("[store {}]", meta.get_id());
 
-        let mut coprocessor_host = CoprocessorHost::new();
         // TODO load coprocessors from configuration
         coprocessor_host.registry.register_observer(100, box SplitObserver);
 
+        let load_split_checker = LoadSplitChecker::new(load_recorder,
+                                                       cfg.region_split_qps_threshold,
+                                                       cfg.load_split_detect_times);
         let mut s = Store {
             cfg: Rc::new(cfg),
             store: meta,
@@ -204,6 +210,7 @@ impl<T, C> Store<T, C> {
             compact_worker: Worker::new("compact worker"),
             pd_worker: FutureWorker::new("pd worker"),
             consistency_check_worker: Worker::new("consistency check worker"),
+            load_split_checker: load_split_checker,
             apply_worker: Worker::new("apply worker"),
             apply_res_receiver: None,
             region_ranges: BTreeMap::new(),
@@ -270,6 +277,8 @@ impl<T, C> Store<T, C> {
             }
 
             self.region_ranges.insert(enc_end_key(region), region_id);
+            self.coprocessor_host
+                .on_region_changed(region, RegionChangeEvent::Create, peer.get_role());
             // No need to check duplicated here, because we use region id as the key
             // in DB.
             self.region_peers.insert(region_id, peer);
@@ -423,11 +432,11 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         self.register_compact_lock_cf_tick(event_loop);
         self.register_consistency_check_tick(event_loop);
         self.register_report_region_flow_tick(event_loop);
+        self.register_load_split_check_tick(event_loop);
 
         let split_check_runner = SplitCheckRunner::new(self.engine.clone(),
                                                        self.sendch.clone(),
-                                                       self.cfg.region_max_size,
-                                                       self.cfg.region_split_size);
+                                                       &self.cfg);
         box_try!(self.split_check_worker.start(split_check_runner));
 
         let runner = RegionRunner::new(self.engine.clone(),
@@ -511,6 +520,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             if peer.raft_group.tick() {
                 peer.mark_to_be_checked(&mut self.pending_raft_groups);
             }
+            peer.check_follower_reads();
 
             // If this peer detects the leader is missing for a long long time,
             // it should consider itself as a stale peer which is removed from
@@ -1022,6 +1032,10 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                    self.store_id());
 
         }
+        if is_initialized {
+            self.coprocessor_host
+                .on_region_changed(p.region(), RegionChangeEvent::Destroy, p.get_role());
+        }
     }
 
     fn on_ready_change_peer(&mut self, region_id: u64, cp: ChangePeer) {
@@ -1034,6 +1048,8 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                 return;
             }
             p.mut_store().region = cp.region;
+            self.coprocessor_host
+                .on_region_changed(p.region(), RegionChangeEvent::Update, p.get_role());
             if p.is_leader() {
                 // Notify pd immediately.
                 info!("{} notify pd with change peer region {:?}",
@@ -1108,7 +1124,13 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             (left.clone(), right.clone())
         };
 
-        self.region_peers.get_mut(&region_id).unwrap().mut_store().region = origin_region.clone();
+        {
+            let origin_peer = self.region_peers.get_mut(&region_id).unwrap();
+            origin_peer.mut_store().region = origin_region.clone();
+            self.coprocessor_host.on_region_changed(&origin_region,
+                                                    RegionChangeEvent::Update,
+                                                    origin_peer.get_role());
+        }
         let new_region_id = new_region.get_id();
         if let Some(peer) = self.region_peers.get(&new_region_id) {
             // If the store received a raft msg with the new region raft group
@@ -1173,6 +1195,9 @@ impl<T: Transport, C: PdClient> Store<T, C> {
                     new_peer.size_diff_hint = self.cfg.region_check_size_diff;
                 }
                 self.apply_worker.schedule(ApplyTask::register(&new_peer)).unwrap();
+                self.coprocessor_host.on_region_changed(&new_region,
+                                                        RegionChangeEvent::Create,
+                                                        new_peer.get_role());
                 self.region_peers.insert(new_region_id, new_peer);
             }
         }
@@ -1230,6 +1255,15 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         }
 
         self.region_ranges.insert(enc_end_key(&region), region.get_id());
+
+        let event = if prev_region.get_peers().is_empty() {
+            RegionChangeEvent::Create
+        } else {
+            RegionChangeEvent::Update
+        };
+        if let Some(peer) = self.region_peers.get(&region_id) {
+            self.coprocessor_host.on_region_changed(&region, event, peer.get_role());
+        }
     }
 
     fn on_ready_result(&mut self, region_id: u64, exec_results: Vec<ExecResult>) {
@@ -1257,7 +1291,8 @@ impl<T: Transport, C: PdClient> Store<T, C> {
     }
 
     fn pre_propose_raft_command(&mut self,
-                                msg: &RaftCmdRequest)
+                                msg: &RaftCmdRequest,
+                                allow_follower: bool)
                                 -> Result<Option<RaftCmdResponse>> {
         try!(self.validate_store_id(msg));
         if msg.has_status_request() {
@@ -1265,12 +1300,12 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             let resp = try!(self.execute_status_command(msg));
             return Ok(Some(resp));
         }
-        try!(self.validate_region(msg));
+        try!(self.validate_region(msg, allow_follower));
         Ok(None)
     }
 
     fn propose_raft_command(&mut self, msg: RaftCmdRequest, cb: Callback) {
-        match self.pre_propose_raft_command(&msg) {
+        match self.pre_propose_raft_command(&msg, false) {
             Ok(Some(resp)) => {
                 cb.call_box((resp,));
                 return;
@@ -1300,6 +1335,28 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         // we will call the callback with timeout error.
     }
 
+    fn propose_follower_read_command(&mut self, msg: RaftCmdRequest, cb: Callback) {
+        match self.pre_propose_raft_command(&msg, true) {
+            Ok(Some(resp)) => {
+                cb.call_box((resp,));
+                return;
+            }
+            Err(e) => {
+                cb.call_box((new_error(e),));
+                return;
+            }
+            _ => (),
+        }
+
+        let mut resp = RaftCmdResponse::new();
+        let region_id = msg.get_header().get_region_id();
+        let mut peer = self.region_peers.get_mut(&region_id).unwrap();
+        bind_term(&mut resp, peer.term());
+        if peer.propose_follower_read(cb, msg, resp, &mut self.raft_metrics.propose) {
+            peer.mark_to_be_checked(&mut self.pending_raft_groups);
+        }
+    }
+
     fn propose_batch_raft_snapshot_command(&mut self,
                                            batch: Vec<RaftCmdRequest>,
                                            on_finished: BatchCallback) {
@@ -1307,7 +1364,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         BATCH_SNAPSHOT_COMMANDS.observe(size as f64);
         let mut ret = Vec::with_capacity(size);
         for msg in batch {
-            match self.pre_propose_raft_command(&msg) {
+            match self.pre_propose_raft_command(&msg, false) {
                 Ok(Some(resp)) => {
                     ret.push(Some(resp));
                     continue;
@@ -1334,7 +1391,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         Ok(())
     }
 
-    fn validate_region(&self, msg: &RaftCmdRequest) -> Result<()> {
+    fn validate_region(&self, msg: &RaftCmdRequest, allow_follower: bool) -> Result<()> {
         let region_id = msg.get_header().get_region_id();
         let peer_id = msg.get_header().get_peer().get_id();
 
@@ -1342,7 +1399,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             Some(peer) => peer,
             None => return Err(Error::RegionNotFound(region_id)),
         };
-        if !peer.is_leader() {
+        if !allow_follower && !peer.is_leader() {
             return Err(Error::NotLeader(region_id, peer.get_peer_from_cache(peer.leader_id())));
         }
         if peer.peer_id() != peer_id {
@@ -1368,7 +1425,19 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             }
             return Err(Error::StaleEpoch(msg, new_regions));
         }
-        res
+        try!(res);
+
+        // A batch of writes with a key out of the region is rejected as a whole before it's
+        // proposed, so the client can split it by region and retry.
+        for req in msg.get_requests() {
+            let key = match req.get_cmd_type() {
+                CmdType::Put => req.get_put().get_key(),
+                CmdType::Delete => req.get_delete().get_key(),
+                _ => continue,
+            };
+            try!(util::check_key_in_region(key, peer.region()));
+        }
+        Ok(())
     }
 
     pub fn find_sibling_region(&self, region: &metapb::Region) -> Option<u64> {
@@ -1534,6 +1603,47 @@ impl<T: Transport, C: PdClient> Store<T, C> {
         self.register_split_region_check_tick(event_loop);
     }
 
+    fn register_load_split_check_tick(&self, event_loop: &mut EventLoop<Self>) {
+        if let Err(e) = register_timer(event_loop,
+                                       Tick::LoadSplitCheck,
+                                       self.cfg.load_split_check_tick_interval) {
+            error!("{} register load split check tick err: {:?}", self.tag, e);
+        }
+    }
+
+    fn on_load_split_check_tick(&mut self, event_loop: &mut EventLoop<Self>) {
+        for (region_id, split_key) in self.load_split_checker.check() {
+            let epoch = match self.region_peers.get(&region_id) {
+                Some(peer) if peer.is_leader() => {
+                    let region = peer.region();
+                    // The key may be sampled before the region changes.
+                    if split_key.as_slice() <= region.get_start_key() ||
+                       util::check_key_in_region(&split_key, region).is_err() {
+                        continue;
+                    }
+                    info!("{} qps exceeds {}, need to split at {}",
+                          peer.tag,
+                          self.cfg.region_split_qps_threshold,
+                          escape(&split_key));
+                    region.get_region_epoch().clone()
+                }
+                _ => continue,
+            };
+            // Split it the same way as the size based split.
+            let msg = Msg::SplitCheckResult {
+                region_id: region_id,
+                epoch: epoch,
+                split_key: keys::data_key(&split_key),
+            };
+            if let Err(e) = self.sendch.try_send(msg) {
+                error!("[region {}] failed to send load split check result: {:?}",
+                       region_id,
+                       e);
+            }
+        }
+        self.register_load_split_check_tick(event_loop);
+    }
+
     fn register_compact_check_tick(&self, event_loop: &mut EventLoop<Self>) {
         if let Err(e) = register_timer(event_loop,
                                        Tick::CompactCheck,
@@ -1547,7 +1657,7 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             if peer.delete_keys_hint < self.cfg.region_compact_delete_keys_count {
                 continue;
             }
-            for &cf in &[CF_DEFAULT, CF_WRITE] {
+            for &cf in &[CF_DEFAULT, CF_WRITE, CF_RAW] {
                 let task = CompactTask {
                     cf_name: String::from(cf),
                     start_key: Some(keys::enc_start_key(peer.region())),
@@ -1572,40 +1682,106 @@ impl<T: Transport, C: PdClient> Store<T, C> {
             error!("[region {}] split key should not be empty!!!", region_id);
             return;
         }
-        let p = self.region_peers.get(&region_id);
-        if p.is_none() || !p.unwrap().is_leader() {
-            // region on this store is no longer leader, skipped.
-            info!("[region {}] region on {} doesn't exist or is not leader, skip.",
-                  region_id,
-                  self.store_id());
+        let key = keys::origin_key(&split_key).to_vec();
+        self.on_prepare_split_region(region_id, epoch, key, None);
+    }
+
+    fn on_prepare_split_region(&mut self,
+                               region_id: u64,
+                               epoch: metapb::RegionEpoch,
+                               split_key: Vec<u8>,
+                               cb: Option<Callback>) {
+        if let Err(e) = self.validate_split_region(region_id, &epoch, &split_key) {
+            if let Some(cb) = cb {
+                cb.call_box((new_error(e),));
+            }
             return;
         }
 
-        let peer = p.unwrap();
-        let region = peer.region();
+        let peer = &self.region_peers[&region_id];
+        let task = PdTask::AskSplit {
+            region: peer.region().clone(),
+            split_key: split_key,
+            peer: peer.peer.clone(),
+            right_derive: self.cfg.right_derive_when_split,
+            callback: cb,
+        };
+
+        if let Err(Stopped(task)) = self.pd_worker.schedule(task) {
+            error!("{} failed to notify pd to split: pd worker is stopped", peer.tag);
+            if let PdTask::AskSplit { callback: Some(cb), .. } = task {
+                cb.call_box((new_error(box_err!("failed to notify pd to split")),));
+            }
+        }
+    }
+
+    fn on_batch_split_region(&mut self,
+                             region_id: u64,
+                             epoch: metapb::RegionEpoch,
+                             mut split_keys: Vec<Vec<u8>>,
+                             cb: SplitRegionCallback) {
+        split_keys.sort();
+        split_keys.dedup();
+        let res = if split_keys.is_empty() {
+            Err(box_err!("[region {}] no split key", region_id))
+        } else {
+            split_keys.iter()
+                .map(|key| self.validate_split_region(region_id, &epoch, key))
+                .find(|res| res.is_err())
+                .unwrap_or(Ok(()))
+        };
+        if let Err(e) = res {
+            return cb.call_box((Err(new_error(e).mut_header().take_error()),));
+        }
+
+        // TODO: propose one batch split admin command, with the region ids and peer ids of all the
+        // new regions allocated by one pd call, and create all the peers in one apply, once
+        // kvproto has the batch split admin command and the ask batch split rpc.
 
+        // Every split keeps the id and the leader of the region on the derived side, so the
+        // remaining keys have to be there.
+        if !self.cfg.right_derive_when_split {
+            split_keys.reverse();
+        }
+        split_region_at_keys(self.sendch.clone(), region_id, epoch, split_keys, vec![], cb);
+    }
+
+    fn validate_split_region(&self,
+                             region_id: u64,
+                             epoch: &metapb::RegionEpoch,
+                             split_key: &[u8])
+                             -> Result<()> {
+        let peer = match self.region_peers.get(&region_id) {
+            Some(peer) => peer,
+            None => {
+                info!("[region {}] region on {} doesn't exist, skip.",
+                      region_id,
+                      self.store_id());
+                return Err(Error::RegionNotFound(region_id));
+            }
+        };
+        if !peer.is_leader() {
+            // region on this store is no longer leader, skipped.
+            info!("{} is not leader, skip.", peer.tag);
+            return Err(Error::NotLeader(region_id, peer.get_peer_from_cache(peer.leader_id())));
+        }
+
+        let region = peer.region();
         if region.get_region_epoch().get_version() != epoch.get_version() {
             info!("{} epoch changed {:?} != {:?}, need re-check later",
                   peer.tag,
                   region.get_region_epoch(),
                   epoch);
-            return;
+            return Err(Error::StaleEpoch(format!("epoch changed {:?} != {:?}, retry later",
+                                                 region.get_region_epoch(),
+                                                 epoch),
+                                         vec![region.to_owned()]));
         }
 
-        let key = keys::origin_key(&split_key);
-        let task = PdTask::AskSplit {
-            region: region.clone(),
-            split_key: key.to_vec(),
-            peer: peer.peer.clone(),
-            right_derive: self.cfg.right_derive_when_split,
-        };
-
-        if let Err(e) = self.pd_worker.schedule(task) {
-            error!("{} failed to notify pd to split at {:?}: {}",
-                   peer.tag,
-                   split_key,
-                   e);
+        if split_key <= region.get_start_key() {
+            return Err(box_err!("{} invalid split key {}", peer.tag, escape(split_key)));
         }
+        util::check_key_in_region(split_key, region)
     }
 
     fn on_pd_heartbeat_tick(&mut self, event_loop: &mut EventLoop<Self>) {
@@ -2006,6 +2182,54 @@ impl<T: Transport, C: PdClient> Store<T, C> {
     }
 }
 
+// Splits the region at the keys in order, each split is sent once the previous one is applied,
+// with the epoch it returns. The regions split off so far are collected in `regions`.
+fn split_region_at_keys(ch: SendCh<Msg>,
+                        region_id: u64,
+                        epoch: metapb::RegionEpoch,
+                        mut split_keys: Vec<Vec<u8>>,
+                        mut regions: Vec<metapb::Region>,
+                        callback: SplitRegionCallback) {
+    let split_key = split_keys.remove(0);
+    let next_ch = ch.clone();
+    let cb: Callback = box move |mut resp: RaftCmdResponse| {
+        if resp.get_header().has_error() {
+            return callback.call_box((Err(resp.mut_header().take_error()),));
+        }
+        let mut split = resp.mut_admin_response().take_split();
+        let (derived, new) = if split.get_left().get_id() == region_id {
+            (split.take_left(), split.take_right())
+        } else {
+            (split.take_right(), split.take_left())
+        };
+        regions.push(new);
+        if split_keys.is_empty() {
+            regions.push(derived);
+            regions.sort_by(|a, b| a.get_start_key().cmp(b.get_start_key()));
+            return callback.call_box((Ok(regions),));
+        }
+        let epoch = derived.get_region_epoch().clone();
+        split_region_at_keys(next_ch, region_id, epoch, split_keys, regions, callback);
+    };
+
+    let msg = Msg::SplitRegion {
+        region_id: region_id,
+        region_epoch: epoch,
+        split_key: split_key,
+        callback: Some(cb),
+    };
+    if let Err(e) = ch.try_send_or_return(msg) {
+        error!("[region {}] failed to send split region: {:?}", region_id, e);
+        match e {
+            NotifyError::Full(Msg::SplitRegion { callback: Some(cb), .. }) |
+            NotifyError::Closed(Some(Msg::SplitRegion { callback: Some(cb), .. })) => {
+                cb.call_box((new_error(box_err!("failed to send split region")),))
+            }
+            _ => {}
+        }
+    }
+}
+
 fn new_admin_request(region_id: u64, peer: metapb::Peer) -> RaftCmdRequest {
     let mut request = RaftCmdRequest::new();
     request.mut_header().set_region_id(region_id);
@@ -2088,6 +2312,13 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
                     .observe(duration_to_sec(send_time.elapsed()) as f64);
                 self.propose_raft_command(request, callback)
             }
+            Msg::FollowerReadCmd { send_time, request, callback } => {
+                self.raft_metrics
+                    .propose
+                    .request_wait_time
+                    .observe(duration_to_sec(send_time.elapsed()) as f64);
+                self.propose_follower_read_command(request, callback)
+            }
             // For now, it is only called by batch snapshot.
             Msg::BatchRaftSnapCmds { send_time, batch, on_finished } => {
                 self.raft_metrics
@@ -2104,6 +2335,18 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
                 info!("[region {}] split check complete.", region_id);
                 self.on_split_check_result(region_id, epoch, split_key);
             }
+            Msg::SplitRegion { region_id, region_epoch, split_key, callback } => {
+                info!("[region {}] on split region at key {}.",
+                      region_id,
+                      escape(&split_key));
+                self.on_prepare_split_region(region_id, region_epoch, split_key, callback);
+            }
+            Msg::BatchSplitRegion { region_id, region_epoch, split_keys, callback } => {
+                info!("[region {}] on split region at {} keys.",
+                      region_id,
+                      split_keys.len());
+                self.on_batch_split_region(region_id, region_epoch, split_keys, callback);
+            }
             Msg::ReportUnreachable { region_id, to_peer_id } => {
                 self.on_unreachable(region_id, to_peer_id);
             }
@@ -2127,6 +2370,7 @@ impl<T: Transport, C: PdClient> mio::Handler for Store<T, C> {
             Tick::CompactLockCf => self.on_compact_lock_cf(event_loop),
             Tick::ConsistencyCheck => self.on_consistency_check_tick(event_loop),
             Tick::ReportRegionFlow => self.on_report_region_flow(event_loop),
+            Tick::LoadSplitCheck => self.on_load_split_check_tick(event_loop),
         }
         slow_log!(t, "{} handle timeout {:?}", self.tag, timeout);
     }
@@ -12,6 +12,7 @@
 // limitations under the License.
 
 use std::option::Option;
+use std::collections::Bound::Excluded;
 
 use kvproto::metapb;
 use kvproto::eraftpb::{self, ConfChangeType, MessageType};
@@ -19,8 +20,8 @@ use kvproto::raft_serverpb::RaftMessage;
 use raftstore::{Result, Error};
 use raftstore::store::keys;
 use rocksdb::{DB, Range, TablePropertiesCollection};
-use storage::LARGE_CFS;
-use util::properties::SizeProperties;
+use storage::{CF_WRITE, LARGE_CFS};
+use util::properties::{MvccProperties, SizeProperties};
 use util::rocksdb as rocksdb_util;
 
 use super::peer_storage;
@@ -130,6 +131,52 @@ pub fn get_region_approximate_size(db: &DB, region: &metapb::Region) -> Result<u
     Ok(size)
 }
 
+/// Returns the key where the size of the region from its start exceeds `split_size`, which is
+/// taken from the size properties of the sst files overlapping with the region. Returns None
+/// if the region in the sst files is not larger than `split_size`.
+pub fn get_region_approximate_split_key(db: &DB,
+                                        region: &metapb::Region,
+                                        split_size: u64)
+                                        -> Result<Option<Vec<u8>>> {
+    let start = keys::enc_start_key(region);
+    let end = keys::enc_end_key(region);
+    let mut handles = vec![];
+    for cfname in LARGE_CFS {
+        let collection = try!(get_region_properties_cf(db, cfname, region));
+        for (_, v) in &*collection {
+            let props = try!(SizeProperties::decode(v.user_collected_properties()));
+            let range = (Excluded(start.as_slice()), Excluded(end.as_slice()));
+            for (k, v) in props.index_handles.range::<[u8], _>(range) {
+                handles.push((k.clone(), v.size));
+            }
+        }
+    }
+    // The handles of different files are merged by their keys, so the sizes before a key
+    // add up to the approximate size of the region before it.
+    handles.sort();
+    let mut size = 0;
+    for (key, handle_size) in handles {
+        size += handle_size;
+        if size > split_size {
+            return Ok(Some(key));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the approximate number of keys of the region, not counting their MVCC versions,
+/// which is taken from the properties of the write cf sst files overlapping with the region.
+/// A key in several sst files is counted more than once.
+pub fn get_region_approximate_keys(db: &DB, region: &metapb::Region) -> Result<u64> {
+    let collection = try!(get_region_properties_cf(db, CF_WRITE, region));
+    let mut keys = 0;
+    for (_, v) in &*collection {
+        let props = try!(MvccProperties::decode(v.user_collected_properties()));
+        keys += props.num_rows;
+    }
+    Ok(keys)
+}
+
 #[cfg(test)]
 mod tests {
     use kvproto::metapb;
@@ -141,7 +188,9 @@ mod tests {
     use raftstore::store::peer_storage;
     use rocksdb::{DBOptions, ColumnFamilyOptions, Writable};
     use util::rocksdb::CFOptions;
-    use util::properties::SizePropertiesCollectorFactory;
+    use util::properties::{MvccPropertiesCollectorFactory, SizePropertiesCollectorFactory};
+    use storage::{CF_DEFAULT, Key};
+    use storage::mvcc::{Write, WriteType};
 
     // Tests the util function `check_key_in_region`.
     #[test]
@@ -270,4 +319,65 @@ mod tests {
             assert_eq!(size, cf_size);
         }
     }
+
+    #[test]
+    fn test_region_approximate_keys() {
+        let path = TempDir::new("_test_raftstore_region_approximate_keys").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(MvccPropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.mvcc-collector", f);
+        let cfs_opts = vec![CFOptions::new(CF_DEFAULT, ColumnFamilyOptions::new()),
+                            CFOptions::new(CF_WRITE, cf_opts)];
+        let db = rocksdb_util::new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+
+        let region = make_region(1, vec![], vec![]);
+        assert_eq!(get_region_approximate_keys(&db, &region).unwrap(), 0);
+
+        let cf = db.cf_handle(CF_WRITE).unwrap();
+        let write = Write::new(WriteType::Put, 1, None).to_bytes();
+        for key in &[b"a", b"b", b"c"] {
+            for ts in 2..4 {
+                let k = keys::data_key(Key::from_raw(*key).append_ts(ts).encoded());
+                db.put_cf(cf, &k, &write).unwrap();
+            }
+        }
+        db.flush_cf(cf, true).unwrap();
+        // The versions of a key are counted once.
+        assert_eq!(get_region_approximate_keys(&db, &region).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_region_approximate_split_key() {
+        let path = TempDir::new("_test_raftstore_region_approximate_split_key").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let db_opts = DBOptions::new();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(SizePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.size-collector", f);
+        let cfs_opts = LARGE_CFS.iter().map(|cf| CFOptions::new(cf, cf_opts.clone())).collect();
+        let db = rocksdb_util::new_engine_opt(path_str, db_opts, cfs_opts).unwrap();
+
+        // Every key is large enough to take an index handle of its own.
+        let value = vec![0; 8 * 1024 * 1024];
+        for key in &[b"a", b"b", b"c", b"d"] {
+            let cf = db.cf_handle(CF_DEFAULT).unwrap();
+            db.put_cf(cf, &keys::data_key(*key), &value).unwrap();
+            db.flush_cf(cf, true).unwrap();
+        }
+
+        let region = make_region(1, vec![], vec![]);
+        let split_key = get_region_approximate_split_key(&db, &region, 20 * 1024 * 1024);
+        assert_eq!(split_key.unwrap(), Some(keys::data_key(b"c")));
+        let split_key = get_region_approximate_split_key(&db, &region, 40 * 1024 * 1024);
+        assert_eq!(split_key.unwrap(), None);
+
+        // The keys out of the region are not counted.
+        let region = make_region(1, b"bb".to_vec(), b"d".to_vec());
+        let split_key = get_region_approximate_split_key(&db, &region, 10 * 1024 * 1024);
+        assert_eq!(split_key.unwrap(), None);
+        let split_key = get_region_approximate_split_key(&db, &region, 4 * 1024 * 1024);
+        assert_eq!(split_key.unwrap(), Some(keys::data_key(b"c")));
+    }
 }
@@ -482,6 +482,7 @@ impl ApplyDelegate {
         let cmd_cb = self.find_cb(index, term, &cmd);
         apply_ctx.host.pre_apply(&self.region, &mut cmd);
         let (mut resp, exec_result) = self.apply_raft_cmd(apply_ctx.wb_mut(), index, term, &cmd);
+        apply_ctx.host.post_apply(&self.region, index, &cmd, &resp);
 
         debug!("{} applied command at log index {}", self.tag, index);
 
@@ -490,7 +491,6 @@ impl ApplyDelegate {
             Some(cb) => cb,
         };
 
-        // TODO: Involve post apply hook.
         // TODO: if we have exec_result, maybe we should return this callback too. Outer
         // store will call it after handing exec result.
         cmd_resp::bind_term(&mut resp, self.term);
@@ -678,6 +678,8 @@ impl ApplyDelegate {
 
         region.mut_region_epoch().set_conf_ver(conf_ver);
 
+        // TODO: add peers as learners and promote them once kvproto has the AddLearnerNode
+        // conf change type and the learner flag of metapb::Peer, raft supports learners already.
         match change_type {
             ConfChangeType::AddNode => {
                 PEER_ADMIN_CMD_COUNTER_VEC.with_label_values(&["add_peer", "all"]).inc();
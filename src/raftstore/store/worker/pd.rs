@@ -25,9 +25,10 @@ use kvproto::pdpb;
 
 use util::worker::FutureRunnable as Runnable;
 use util::escape;
-use util::transport::SendCh;
+use util::transport::{NotifyError, SendCh};
 use pd::{PdClient, RegionStat};
-use raftstore::store::Msg;
+use raftstore::store::{Callback, Msg};
+use raftstore::store::cmd_resp::new_error;
 use raftstore::store::util::is_epoch_stale;
 
 use super::metrics::*;
@@ -40,6 +41,8 @@ pub enum Task {
         peer: metapb::Peer,
         // If true, right region derive origin region_id.
         right_derive: bool,
+        // Gets the response of the split command, if any.
+        callback: Option<Callback>,
     },
     Heartbeat {
         region: metapb::Region,
@@ -109,7 +112,8 @@ impl<T: PdClient> Runner<T> {
                         region: metapb::Region,
                         split_key: Vec<u8>,
                         peer: metapb::Peer,
-                        right_derive: bool) {
+                        right_derive: bool,
+                        callback: Option<Callback>) {
         PD_REQ_COUNTER_VEC.with_label_values(&["ask split", "all"]).inc();
 
         let ch = self.ch.clone();
@@ -128,10 +132,14 @@ impl<T: PdClient> Runner<T> {
                                                            resp.get_new_region_id(),
                                                            resp.take_new_peer_ids(),
                                                            right_derive);
-                        send_admin_request(ch, region, peer, req);
+                        let cb = callback.unwrap_or_else(|| Box::new(|_| {}));
+                        send_admin_request(ch, region, peer, req, cb);
                     }
                     Err(e) => {
                         debug!("[region {}] failed to ask split: {:?}", region.get_id(), e);
+                        if let Some(cb) = callback {
+                            cb.call_box((new_error(e.into()),));
+                        }
                     }
                 }
                 Ok(())
@@ -264,7 +272,7 @@ impl<T: PdClient> Runner<T> {
                           change_peer.get_peer());
                     let req = new_change_peer_request(change_peer.get_change_type().into(),
                                                       change_peer.take_peer());
-                    send_admin_request_raw(&ch, region_id, epoch, peer, req);
+                    send_admin_request_raw(&ch, region_id, epoch, peer, req, Box::new(|_| {}));
                 } else if resp.has_transfer_leader() {
                     PD_HEARTBEAT_COUNTER_VEC.with_label_values(&["transfer leader"]).inc();
 
@@ -274,7 +282,7 @@ impl<T: PdClient> Runner<T> {
                           peer,
                           transfer_leader.get_peer());
                     let req = new_transfer_leader_request(transfer_leader.take_peer());
-                    send_admin_request_raw(&ch, region_id, epoch, peer, req)
+                    send_admin_request_raw(&ch, region_id, epoch, peer, req, Box::new(|_| {}))
                 }
             })
             .map_err(|e| panic!("unexpected error: {:?}", e))
@@ -296,8 +304,8 @@ impl<T: PdClient> Runnable<Task> for Runner<T> {
         }
 
         match task {
-            Task::AskSplit { region, split_key, peer, right_derive } => {
-                self.handle_ask_split(handle, region, split_key, peer, right_derive)
+            Task::AskSplit { region, split_key, peer, right_derive, callback } => {
+                self.handle_ask_split(handle, region, split_key, peer, right_derive, callback)
             }
             Task::Heartbeat { region,
                               peer,
@@ -354,17 +362,19 @@ fn new_transfer_leader_request(peer: metapb::Peer) -> AdminRequest {
 fn send_admin_request(ch: SendCh<Msg>,
                       mut region: metapb::Region,
                       peer: metapb::Peer,
-                      request: AdminRequest) {
+                      request: AdminRequest,
+                      callback: Callback) {
     let region_id = region.get_id();
     let epoch = region.take_region_epoch();
-    send_admin_request_raw(&ch, region_id, epoch, peer, request)
+    send_admin_request_raw(&ch, region_id, epoch, peer, request, callback)
 }
 
 fn send_admin_request_raw(ch: &SendCh<Msg>,
                           region_id: u64,
                           epoch: metapb::RegionEpoch,
                           peer: metapb::Peer,
-                          request: AdminRequest) {
+                          request: AdminRequest,
+                          callback: Callback) {
     let cmd_type = request.get_cmd_type();
 
     let mut req = RaftCmdRequest::new();
@@ -374,11 +384,21 @@ fn send_admin_request_raw(ch: &SendCh<Msg>,
 
     req.set_admin_request(request);
 
-    if let Err(e) = ch.try_send(Msg::new_raft_cmd(req, Box::new(|_| {}))) {
-        error!("[region {}] send {:?} request err {:?}",
-               region_id,
-               cmd_type,
-               e);
+    let e = match ch.try_send_or_return(Msg::new_raft_cmd(req, callback)) {
+        Ok(()) => return,
+        Err(e) => e,
+    };
+    error!("[region {}] send {:?} request err {:?}",
+           region_id,
+           cmd_type,
+           e);
+    // The request is never handled, so its callback gets the error instead.
+    match e {
+        NotifyError::Full(Msg::RaftCmd { callback, .. }) |
+        NotifyError::Closed(Some(Msg::RaftCmd { callback, .. })) => {
+            callback.call_box((new_error(box_err!("failed to send {:?} request", cmd_type)),))
+        }
+        _ => {}
     }
 }
 
@@ -21,14 +21,17 @@ use rocksdb::DB;
 use kvproto::metapb::RegionEpoch;
 use kvproto::metapb::Region;
 
-use raftstore::store::{keys, Msg};
+use coprocessor::codec::table;
+use raftstore::store::{keys, util, Config, Msg};
 use raftstore::store::engine::{Iterable, IterOption};
 use raftstore::Result;
 use rocksdb::DBIterator;
 use util::escape;
+use util::codec::bytes::{encode_bytes, BytesDecoder};
 use util::transport::{RetryableSendCh, Sender};
 use util::worker::Runnable;
-use storage::{CfName, LARGE_CFS};
+use storage::{CfName, CF_WRITE, LARGE_CFS};
+use storage::types::split_encoded_key_on_ts;
 
 use super::metrics::*;
 
@@ -119,8 +122,7 @@ impl<'a> MergedIterator<'a> {
 
 /// Split checking task.
 pub struct Task {
-    region_id: u64,
-    epoch: RegionEpoch,
+    region: Region,
     start_key: Vec<u8>,
     end_key: Vec<u8>,
 }
@@ -128,8 +130,7 @@ pub struct Task {
 impl Task {
     pub fn new(region: &Region) -> Task {
         Task {
-            region_id: region.get_id(),
-            epoch: region.get_region_epoch().clone(),
+            region: region.clone(),
             start_key: keys::enc_start_key(region),
             end_key: keys::enc_end_key(region),
         }
@@ -138,7 +139,153 @@ impl Task {
 
 impl Display for Task {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "Split Check Task for {}", self.region_id)
+        write!(f, "Split Check Task for {}", self.region.get_id())
+    }
+}
+
+/// `SplitChecker` is fed the keys of a region in order, and finds the key to split the region
+/// at.
+trait SplitChecker {
+    /// Returns true if the split key is found, then the rest of the region needn't be scanned.
+    fn on_kv(&mut self, entry: &KeyEntry) -> bool;
+
+    /// Returns the key to split the region at, or None if the region needn't be split.
+    fn split_key(&mut self) -> Option<Vec<u8>>;
+}
+
+/// Splits the region when its size reaches `max_size`, the left region will be about
+/// `split_size`.
+struct SizeChecker {
+    max_size: u64,
+    split_size: u64,
+    current_size: u64,
+    split_key: Option<Vec<u8>>,
+}
+
+impl SizeChecker {
+    fn new(max_size: u64, split_size: u64) -> SizeChecker {
+        SizeChecker {
+            max_size: max_size,
+            split_size: split_size,
+            current_size: 0,
+            split_key: None,
+        }
+    }
+}
+
+impl SplitChecker for SizeChecker {
+    fn on_kv(&mut self, entry: &KeyEntry) -> bool {
+        self.current_size += entry.len() as u64;
+        if self.split_key.is_none() && self.current_size > self.split_size {
+            self.split_key = entry.key.clone();
+        }
+        self.current_size >= self.max_size
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        if self.current_size < self.max_size {
+            return None;
+        }
+        self.split_key.take()
+    }
+}
+
+/// Splits the region when it has `max_keys` keys in the write cf, the left region will have
+/// `split_keys` of them. The versions of a key are counted once, and are never split apart.
+struct KeysChecker {
+    max_keys: u64,
+    split_keys: u64,
+    // The position of the write cf in the merged iterator.
+    write_pos: usize,
+    current_keys: u64,
+    // The last key seen, without timestamp.
+    last_key: Vec<u8>,
+    split_key: Option<Vec<u8>>,
+}
+
+impl KeysChecker {
+    fn new(max_keys: u64, split_keys: u64, write_pos: usize) -> KeysChecker {
+        KeysChecker {
+            max_keys: max_keys,
+            split_keys: split_keys,
+            write_pos: write_pos,
+            current_keys: 0,
+            last_key: vec![],
+            split_key: None,
+        }
+    }
+}
+
+impl SplitChecker for KeysChecker {
+    fn on_kv(&mut self, entry: &KeyEntry) -> bool {
+        if entry.pos != self.write_pos {
+            return false;
+        }
+        let key = entry.key.as_ref().unwrap();
+        let key = match split_encoded_key_on_ts(key) {
+            Ok((key, _)) => key,
+            Err(_) => key.as_slice(),
+        };
+        if self.current_keys > 0 && key == self.last_key.as_slice() {
+            return false;
+        }
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.current_keys += 1;
+        if self.split_key.is_none() && self.current_keys > self.split_keys {
+            self.split_key = Some(key.to_vec());
+        }
+        self.current_keys >= self.max_keys
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        if self.current_keys < self.max_keys {
+            return None;
+        }
+        self.split_key.take()
+    }
+}
+
+// Returns the table prefix of an encoded key, or None if it's not a key of a table.
+fn table_prefix(mut key: &[u8]) -> Option<Vec<u8>> {
+    let key = match key.decode_bytes(false) {
+        Ok(key) => key,
+        Err(_) => return None,
+    };
+    let prefix_len = table::TABLE_PREFIX_LEN + table::ID_LEN;
+    if !key.starts_with(table::TABLE_PREFIX) || key.len() < prefix_len {
+        return None;
+    }
+    Some(key[..prefix_len].to_vec())
+}
+
+/// Splits the region at the first table boundary in it, so the left region holds the data of
+/// one table only.
+#[derive(Default)]
+struct TableChecker {
+    first_table: Option<Vec<u8>>,
+    split_key: Option<Vec<u8>>,
+}
+
+impl SplitChecker for TableChecker {
+    fn on_kv(&mut self, entry: &KeyEntry) -> bool {
+        let prefix = match table_prefix(keys::origin_key(entry.key.as_ref().unwrap())) {
+            Some(prefix) => prefix,
+            None => return false,
+        };
+        if self.first_table.is_none() {
+            self.first_table = Some(prefix);
+            return false;
+        }
+        if self.first_table.as_ref() == Some(&prefix) {
+            return false;
+        }
+        self.split_key = Some(keys::data_key(&encode_bytes(&prefix)));
+        true
+    }
+
+    fn split_key(&mut self) -> Option<Vec<u8>> {
+        self.split_key.take()
     }
 }
 
@@ -147,73 +294,143 @@ pub struct Runner<C> {
     ch: RetryableSendCh<Msg, C>,
     region_max_size: u64,
     split_size: u64,
+    region_max_keys: u64,
+    split_keys: u64,
+    split_on_table: bool,
+    approximate: bool,
 }
 
 impl<C> Runner<C> {
-    pub fn new(engine: Arc<DB>,
-               ch: RetryableSendCh<Msg, C>,
-               region_max_size: u64,
-               split_size: u64)
-               -> Runner<C> {
+    pub fn new(engine: Arc<DB>, ch: RetryableSendCh<Msg, C>, cfg: &Config) -> Runner<C> {
         Runner {
             engine: engine,
             ch: ch,
-            region_max_size: region_max_size,
-            split_size: split_size,
+            region_max_size: cfg.region_max_size,
+            split_size: cfg.region_split_size,
+            region_max_keys: cfg.region_max_keys,
+            split_keys: cfg.region_split_keys,
+            split_on_table: cfg.split_region_on_table,
+            approximate: cfg.split_region_check_approximate,
+        }
+    }
+
+    // Returns the key to split the region at by its size, which is taken from the size
+    // properties of the sst files.
+    fn approximate_split_key(&self, region: &Region) -> Result<Option<Vec<u8>>> {
+        let size = try!(util::get_region_approximate_size(&self.engine, region));
+        if size < self.region_max_size {
+            return Ok(None);
+        }
+        let key = try!(util::get_region_approximate_split_key(&self.engine,
+                                                              region,
+                                                              self.split_size));
+        match key {
+            Some(key) => Ok(Some(key)),
+            // Most of the region is still in the memtables.
+            None => Err(box_err!("no split key in the size properties")),
+        }
+    }
+
+    // Returns the checkers the region should go through, the first one finding a split key
+    // decides where the region is split.
+    fn new_checkers(&self, region: &Region) -> Vec<Box<SplitChecker>> {
+        let mut checkers: Vec<Box<SplitChecker>> = vec![];
+        if self.split_on_table {
+            // There is no table boundary in the region if it starts and ends in the same table.
+            let start_table = table_prefix(region.get_start_key());
+            if start_table.is_none() || start_table != table_prefix(region.get_end_key()) {
+                checkers.push(box TableChecker::default());
+            }
+        }
+        if self.region_max_keys > 0 {
+            let need_check = match util::get_region_approximate_keys(&self.engine, region) {
+                Ok(keys) => keys >= self.region_max_keys,
+                Err(e) => {
+                    debug!("[region {}] failed to get approximate keys: {:?}",
+                           region.get_id(),
+                           e);
+                    true
+                }
+            };
+            if need_check {
+                let write_pos = LARGE_CFS.iter().position(|cf| *cf == CF_WRITE).unwrap();
+                let checker = KeysChecker::new(self.region_max_keys, self.split_keys, write_pos);
+                checkers.push(box checker);
+            }
+        }
+        if !self.approximate {
+            checkers.push(box SizeChecker::new(self.region_max_size, self.split_size));
         }
+        checkers
     }
 }
 
 impl<C: Sender<Msg>> Runnable<Task> for Runner<C> {
     fn run(&mut self, task: Task) {
+        let region_id = task.region.get_id();
         debug!("[region {}] executing task {} {}",
-               task.region_id,
+               region_id,
                escape(&task.start_key),
                escape(&task.end_key));
         CHECK_SPILT_COUNTER_VEC.with_label_values(&["all"]).inc();
 
-        let mut size = 0;
-        let mut split_key = vec![];
         let timer = CHECK_SPILT_HISTOGRAM.start_timer();
-        let res = MergedIterator::new(self.engine.as_ref(),
-                                      LARGE_CFS,
-                                      &task.start_key,
-                                      &task.end_key,
-                                      false)
-            .map(|mut iter| {
-                while let Some(e) = iter.next() {
-                    size += e.len() as u64;
-                    if split_key.is_empty() && size > self.split_size {
-                        split_key = e.key.unwrap();
-                    }
-                    if size >= self.region_max_size {
-                        break;
-                    }
+        let mut checkers = self.new_checkers(&task.region);
+        let mut approximate_key = None;
+        if self.approximate {
+            match self.approximate_split_key(&task.region) {
+                Ok(key) => approximate_key = key,
+                Err(e) => {
+                    info!("[region {}] failed to get approximate split key, scan it instead: \
+                           {:?}",
+                          region_id,
+                          e);
+                    checkers.push(box SizeChecker::new(self.region_max_size, self.split_size));
                 }
-            });
+            }
+        }
 
-        if let Err(e) = res {
-            error!("failed to scan split key of region {}: {:?}",
-                   task.region_id,
-                   e);
-            return;
+        if !checkers.is_empty() {
+            let res = MergedIterator::new(self.engine.as_ref(),
+                                          LARGE_CFS,
+                                          &task.start_key,
+                                          &task.end_key,
+                                          false)
+                .map(|mut iter| {
+                    while let Some(e) = iter.next() {
+                        let mut found = false;
+                        for checker in &mut checkers {
+                            found |= checker.on_kv(&e);
+                        }
+                        if found {
+                            break;
+                        }
+                    }
+                });
+
+            if let Err(e) = res {
+                error!("failed to scan split key of region {}: {:?}", region_id, e);
+                return;
+            }
         }
 
         timer.observe_duration();
 
-        if size < self.region_max_size {
-            debug!("[region {}] no need to send for {} < {}",
-                   task.region_id,
-                   size,
-                   self.region_max_size);
-
-            CHECK_SPILT_COUNTER_VEC.with_label_values(&["ignore"]).inc();
-            return;
-        }
-        let res = self.ch.try_send(new_split_check_result(task.region_id, task.epoch, split_key));
+        // The checkers scanning the region go before the size properties.
+        let split_key = checkers.iter_mut().filter_map(|c| c.split_key()).next();
+        let split_key = match split_key.or(approximate_key) {
+            Some(key) => key,
+            None => {
+                debug!("[region {}] no need to split", region_id);
+                CHECK_SPILT_COUNTER_VEC.with_label_values(&["ignore"]).inc();
+                return;
+            }
+        };
+        let epoch = task.region.get_region_epoch().clone();
+        let res = self.ch.try_send(new_split_check_result(region_id, epoch, split_key));
         if let Err(e) = res {
             warn!("[region {}] failed to send check result, err {:?}",
-                  task.region_id,
+                  region_id,
                   e);
         }
 
@@ -231,17 +448,41 @@ fn new_split_check_result(region_id: u64, epoch: RegionEpoch, split_key: Vec<u8>
 
 #[cfg(test)]
 mod tests {
-    use std::sync::mpsc::{self, TryRecvError};
+    use std::sync::mpsc::{self, Receiver, TryRecvError};
     use std::sync::Arc;
 
     use tempdir::TempDir;
-    use rocksdb::Writable;
+    use rocksdb::{ColumnFamilyOptions, DBOptions, Writable};
     use kvproto::metapb::Peer;
 
-    use storage::ALL_CFS;
-    use util::rocksdb;
+    use coprocessor::codec::table;
+    use storage::{Key, ALL_CFS, CF_DEFAULT};
+    use storage::mvcc::{Write, WriteType};
+    use util::codec::bytes::encode_bytes;
+    use util::properties::{MvccPropertiesCollectorFactory, SizePropertiesCollectorFactory};
+    use util::rocksdb::{self, CFOptions};
     use super::*;
 
+    fn new_region() -> Region {
+        let mut region = Region::new();
+        region.set_id(1);
+        region.mut_peers().push(Peer::new());
+        region.mut_region_epoch().set_version(2);
+        region.mut_region_epoch().set_conf_ver(5);
+        region
+    }
+
+    fn must_split_at(rx: &Receiver<Msg>, region: &Region, key: &[u8]) {
+        match rx.try_recv() {
+            Ok(Msg::SplitCheckResult { region_id, epoch, split_key }) => {
+                assert_eq!(region_id, region.get_id());
+                assert_eq!(&epoch, region.get_region_epoch());
+                assert_eq!(split_key, key);
+            }
+            others => panic!("expect split check result, but got {:?}", others),
+        }
+    }
+
     #[test]
     fn test_split_check() {
         let path = TempDir::new("test-raftstore").unwrap();
@@ -257,7 +498,10 @@ mod tests {
 
         let (tx, rx) = mpsc::sync_channel(100);
         let ch = RetryableSendCh::new(tx, "test-split");
-        let mut runnable = Runner::new(engine.clone(), ch, 100, 60);
+        let mut cfg = Config::default();
+        cfg.region_max_size = 100;
+        cfg.region_split_size = 60;
+        let mut runnable = Runner::new(engine.clone(), ch, &cfg);
 
         // so split key will be z0006
         for i in 0..7 {
@@ -310,4 +554,173 @@ mod tests {
         // It should be safe even the result can't be sent back.
         runnable.run(Task::new(&region));
     }
+
+    #[test]
+    fn test_keys_split_check() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(MvccPropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.mvcc-collector", f);
+        let cfs_opts = vec![CFOptions::new(CF_DEFAULT, ColumnFamilyOptions::new()),
+                            CFOptions::new(CF_WRITE, cf_opts)];
+        let engine = rocksdb::new_engine_opt(path.path().to_str().unwrap(),
+                                             DBOptions::new(),
+                                             cfs_opts)
+            .unwrap();
+        let engine = Arc::new(engine);
+        let region = new_region();
+
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.region_max_keys = 10;
+        cfg.region_split_keys = 6;
+        let mut runnable = Runner::new(engine.clone(), ch, &cfg);
+
+        let write_cf = engine.cf_handle(CF_WRITE).unwrap();
+        let write = Write::new(WriteType::Put, 1, None).to_bytes();
+        // Every key has two versions, which are counted once.
+        let put_keys = |range: ::std::ops::Range<u64>| {
+            for i in range {
+                for ts in 2..4 {
+                    let key = Key::from_raw(format!("{:04}", i).as_bytes()).append_ts(ts);
+                    engine.put_cf(write_cf, &keys::data_key(key.encoded()), &write).unwrap();
+                }
+            }
+            engine.flush_cf(write_cf, true).unwrap();
+        };
+
+        put_keys(0..9);
+        runnable.run(Task::new(&region));
+        // The region has not reached the max keys 10 yet.
+        match rx.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            others => panic!("expect recv empty, but got {:?}", others),
+        }
+
+        put_keys(9..12);
+        runnable.run(Task::new(&region));
+        // The versions of the key stay in the right region.
+        let split_key = Key::from_raw(b"0006");
+        must_split_at(&rx, &region, &keys::data_key(split_key.encoded()));
+    }
+
+    #[test]
+    fn test_table_split_check() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let engine = Arc::new(rocksdb::new_engine(path.path().to_str().unwrap(), ALL_CFS).unwrap());
+
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.split_region_on_table = true;
+        let mut runnable = Runner::new(engine.clone(), ch, &cfg);
+
+        let row_key = |table_id: i64, handle: i64| {
+            let key = table::encode_row_key(table_id, &format!("{:08}", handle).into_bytes());
+            keys::data_key(&encode_bytes(&key))
+        };
+        for handle in 0..3 {
+            engine.put(&row_key(1, handle), b"v").unwrap();
+        }
+
+        let region = new_region();
+        runnable.run(Task::new(&region));
+        // All the keys are in table 1.
+        match rx.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            others => panic!("expect recv empty, but got {:?}", others),
+        }
+
+        for handle in 0..3 {
+            engine.put(&row_key(2, handle), b"v").unwrap();
+            engine.put(&row_key(3, handle), b"v").unwrap();
+        }
+        runnable.run(Task::new(&region));
+        let prefix_len = table::TABLE_PREFIX_LEN + table::ID_LEN;
+        let prefix = table::encode_row_key(2, b"")[..prefix_len].to_vec();
+        must_split_at(&rx, &region, &keys::data_key(&encode_bytes(&prefix)));
+
+        // The region starts and ends in table 3, no need to check the table boundaries.
+        let mut region = new_region();
+        region.set_start_key(encode_bytes(&table::encode_row_key(3, b"")));
+        region.set_end_key(encode_bytes(&table::encode_row_key(3, b"99999999")));
+        runnable.run(Task::new(&region));
+        match rx.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            others => panic!("expect recv empty, but got {:?}", others),
+        }
+    }
+
+    #[test]
+    fn test_approximate_split_check() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let mut cf_opts = ColumnFamilyOptions::new();
+        let f = Box::new(SizePropertiesCollectorFactory::default());
+        cf_opts.add_table_properties_collector_factory("tikv.size-collector", f);
+        let cfs_opts = LARGE_CFS.iter().map(|cf| CFOptions::new(cf, cf_opts.clone())).collect();
+        let engine = rocksdb::new_engine_opt(path.path().to_str().unwrap(),
+                                             DBOptions::new(),
+                                             cfs_opts)
+            .unwrap();
+        let engine = Arc::new(engine);
+        let region = new_region();
+
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.region_max_size = 30 * 1024 * 1024;
+        cfg.region_split_size = 18 * 1024 * 1024;
+        cfg.split_region_check_approximate = true;
+        let mut runnable = Runner::new(engine.clone(), ch, &cfg);
+
+        // Every sst file has 20 keys of 1MB, with an index handle every 4 keys.
+        let value = vec![0; 1024 * 1024];
+        let cf = engine.cf_handle(CF_DEFAULT).unwrap();
+        let put_keys = |range: ::std::ops::Range<u64>| {
+            for i in range {
+                let key = keys::data_key(format!("{:04}", i).as_bytes());
+                engine.put_cf(cf, &key, &value).unwrap();
+            }
+            engine.flush_cf(cf, true).unwrap();
+        };
+
+        put_keys(0..20);
+        runnable.run(Task::new(&region));
+        // The size has not reached the max size yet.
+        match rx.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            others => panic!("expect recv empty, but got {:?}", others),
+        }
+
+        put_keys(20..40);
+        runnable.run(Task::new(&region));
+        // The handles of the first file are 0000, 0004, ..., 0016 and 0019.
+        must_split_at(&rx, &region, &keys::data_key(b"0019"));
+    }
+
+    #[test]
+    fn test_approximate_split_check_fallback() {
+        let path = TempDir::new("test-raftstore").unwrap();
+        let engine = Arc::new(rocksdb::new_engine(path.path().to_str().unwrap(), ALL_CFS).unwrap());
+        let region = new_region();
+
+        let (tx, rx) = mpsc::sync_channel(100);
+        let ch = RetryableSendCh::new(tx, "test-split");
+        let mut cfg = Config::default();
+        cfg.region_max_size = 100;
+        cfg.region_split_size = 60;
+        cfg.split_region_check_approximate = true;
+        let mut runnable = Runner::new(engine.clone(), ch, &cfg);
+
+        // There are no size properties, so the region is scanned.
+        let cf = engine.cf_handle(CF_DEFAULT).unwrap();
+        for i in 0..11 {
+            let s = keys::data_key(format!("{:04}", i).as_bytes());
+            engine.put_cf(cf, &s, &s).unwrap();
+        }
+        engine.flush_cf(cf, true).unwrap();
+        runnable.run(Task::new(&region));
+        must_split_at(&rx, &region, &keys::data_key(b"0006"));
+    }
 }
@@ -13,6 +13,7 @@
 
 use util::collections::HashMap;
 
+use cdc::DEFAULT_RESOLVED_TS_INTERVAL;
 use super::Result;
 
 pub use raftstore::store::Config as RaftStoreConfig;
@@ -51,6 +52,8 @@ pub struct Config {
     pub storage: StorageConfig,
     pub raft_store: RaftStoreConfig,
     pub end_point_concurrency: usize,
+    // The interval in milliseconds of sending resolved ts to cdc subscribers.
+    pub cdc_resolved_ts_interval: u64,
 }
 
 impl Default for Config {
@@ -67,6 +70,7 @@ impl Default for Config {
             grpc_raft_conn_num: DEFAULT_GRPC_RAFT_CONN_NUM,
             grpc_stream_initial_window_size: DEFAULT_GRPC_STREAM_INITIAL_WINDOW_SIZE,
             end_point_concurrency: DEFAULT_END_POINT_CONCURRENCY,
+            cdc_resolved_ts_interval: DEFAULT_RESOLVED_TS_INTERVAL,
             storage: StorageConfig::default(),
             raft_store: RaftStoreConfig::default(),
         }
@@ -85,6 +89,9 @@ impl Config {
                                  shouldn't be 0",
                                 self.end_point_concurrency));
         }
+        if self.cdc_resolved_ts_interval == 0 {
+            return Err(box_err!("server.cdc-resolved-ts-interval shouldn't be 0"));
+        }
 
         Ok(())
     }
@@ -0,0 +1,365 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The gc worker collects old MVCC versions of the regions led by the local store, so that GC
+//! doesn't depend on an external client calling `kv_gc` for every region.
+//!
+//! It polls pd for the cluster safe point. Every time the safe point advances, it walks all the
+//! regions of the local store and collects them in batches, under a limit of keys per second.
+//! Regions whose peers aren't leaders are skipped, their leaders collect them. If any region
+//! fails, the round is retried at the next poll.
+
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::Future;
+use kvproto::kvrpcpb::Context;
+use kvproto::metapb;
+use kvproto::raft_serverpb::{RegionLocalState, PeerState};
+use protobuf;
+use rocksdb::DB;
+
+use pd::PdClient;
+use raftstore::store::keys;
+use raftstore::store::engine::Iterable;
+use storage::{Engine, EngineError, ScanMode, Statistics, Config as StorageConfig};
+use storage::mvcc::{MvccReader, MvccTxn, MAX_TXN_WRITE_SIZE, extract_physical};
+use storage::gc_filter::GcContext;
+use util::time::{duration_to_ms, duration_to_sec};
+
+use super::Result;
+use super::metrics::*;
+
+enum RegionGc {
+    Done,
+    // The local peer isn't the leader, or the region has nothing to collect.
+    Skipped,
+    Stopped,
+}
+
+/// Limits the rate of keys collected.
+struct KeysLimiter {
+    max_keys_per_sec: usize,
+    start: Instant,
+    keys: usize,
+}
+
+impl KeysLimiter {
+    fn new(max_keys_per_sec: usize) -> KeysLimiter {
+        KeysLimiter {
+            max_keys_per_sec: max_keys_per_sec,
+            start: Instant::now(),
+            keys: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.start = Instant::now();
+        self.keys = 0;
+    }
+
+    /// Returns how long to wait after `keys` more keys are collected, to keep under the limit.
+    fn consume(&mut self, keys: usize) -> Duration {
+        if self.max_keys_per_sec == 0 {
+            return Duration::from_secs(0);
+        }
+        self.keys += keys;
+        let expected = Duration::from_millis((self.keys * 1000 / self.max_keys_per_sec) as u64);
+        let elapsed = self.start.elapsed();
+        if expected > elapsed {
+            expected - elapsed
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+}
+
+struct Runner<C: PdClient> {
+    store_id: u64,
+    engine: Box<Engine>,
+    db: Arc<DB>,
+    pd_client: Arc<C>,
+    gc_context: Option<GcContext>,
+    poll_interval: Duration,
+    ratio_threshold: f64,
+    batch_keys: usize,
+    limiter: KeysLimiter,
+    // The last safe point all regions were walked at.
+    safe_point: u64,
+    stop_rx: Receiver<()>,
+}
+
+impl<C: PdClient> Runner<C> {
+    fn run(&mut self) {
+        while !self.wait(self.poll_interval) {
+            self.poll();
+        }
+        info!("gc worker stopped");
+    }
+
+    /// Waits for `timeout`, returns true if the worker is stopped.
+    fn wait(&self, timeout: Duration) -> bool {
+        match self.stop_rx.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => false,
+            Ok(_) | Err(RecvTimeoutError::Disconnected) => true,
+        }
+    }
+
+    fn poll(&mut self) {
+        let safe_point = match self.pd_client.get_gc_safe_point().wait() {
+            Ok(safe_point) => safe_point,
+            Err(e) => {
+                error!("gc worker failed to get safe point: {:?}", e);
+                return;
+            }
+        };
+        if safe_point > self.safe_point {
+            let t = Instant::now();
+            if !self.gc(safe_point) {
+                return;
+            }
+            GC_WORKER_ROUND_HISTOGRAM.observe(duration_to_sec(t.elapsed()));
+            info!("gc worker finished safe point {} in {:?}", safe_point, t.elapsed());
+            self.safe_point = safe_point;
+            GC_WORKER_SAFE_POINT_GAUGE.set(extract_physical(safe_point) as f64);
+        }
+        if self.safe_point > 0 {
+            let now = duration_to_ms(SystemTime::now().duration_since(UNIX_EPOCH).unwrap());
+            let lag = now.saturating_sub(extract_physical(self.safe_point));
+            GC_WORKER_LAG_GAUGE.set(lag as f64 / 1000.0);
+        }
+    }
+
+    /// Collects all the regions of the store at `safe_point`, returns false if the round is not
+    /// finished.
+    fn gc(&mut self, safe_point: u64) -> bool {
+        if let Some(ref gc_context) = self.gc_context {
+            // The compaction filters collect the versions.
+            gc_context.update_safe_point(safe_point);
+            return true;
+        }
+
+        let regions = match self.load_regions() {
+            Ok(regions) => regions,
+            Err(e) => {
+                error!("gc worker failed to load regions: {:?}", e);
+                return false;
+            }
+        };
+        self.limiter.reset();
+        GC_WORKER_PROGRESS_GAUGE.set(0.0);
+        let mut failed = 0;
+        for (i, region) in regions.iter().enumerate() {
+            let label = match self.gc_region(region, safe_point) {
+                Ok(RegionGc::Done) => "gc",
+                Ok(RegionGc::Skipped) => "skipped",
+                Ok(RegionGc::Stopped) => return false,
+                Err(e) => {
+                    warn!("gc worker failed to collect region {}: {:?}",
+                          region.get_id(),
+                          e);
+                    failed += 1;
+                    "failed"
+                }
+            };
+            GC_WORKER_REGION_COUNTER_VEC.with_label_values(&[label]).inc();
+            GC_WORKER_PROGRESS_GAUGE.set((i + 1) as f64 / regions.len() as f64);
+        }
+        if failed > 0 {
+            warn!("gc worker failed to collect {} regions at safe point {}, will retry",
+                  failed,
+                  safe_point);
+            return false;
+        }
+        true
+    }
+
+    /// Loads the regions with a peer in the local store.
+    fn load_regions(&self) -> Result<Vec<metapb::Region>> {
+        let mut regions = vec![];
+        box_try!(self.db.scan(keys::REGION_META_MIN_KEY,
+                              keys::REGION_META_MAX_KEY,
+                              false,
+                              &mut |key, value| {
+            let (_, suffix) = try!(keys::decode_region_meta_key(key));
+            if suffix != keys::REGION_STATE_SUFFIX {
+                return Ok(true);
+            }
+            let mut local_state = try!(protobuf::parse_from_bytes::<RegionLocalState>(value));
+            if local_state.get_state() == PeerState::Normal {
+                regions.push(local_state.take_region());
+            }
+            Ok(true)
+        }));
+        Ok(regions)
+    }
+
+    fn gc_region(&mut self, region: &metapb::Region, safe_point: u64) -> Result<RegionGc> {
+        let peer = match region.get_peers().iter().find(|p| p.get_store_id() == self.store_id) {
+            Some(peer) => peer.clone(),
+            None => return Ok(RegionGc::Skipped),
+        };
+        let mut ctx = Context::new();
+        ctx.set_region_id(region.get_id());
+        ctx.set_region_epoch(region.get_region_epoch().clone());
+        ctx.set_peer(peer);
+
+        let mut scan_key = None;
+        let mut is_range_start = true;
+        loop {
+            let snapshot = match self.engine.snapshot(&ctx) {
+                Ok(snapshot) => snapshot,
+                Err(EngineError::Request(ref e)) if e.has_not_leader() => {
+                    return Ok(RegionGc::Skipped);
+                }
+                Err(e) => return Err(box_err!(e)),
+            };
+            let mut statistics = Statistics::default();
+            let (keys, next_key) = {
+                let mut reader = MvccReader::new(snapshot.as_ref(),
+                                                 &mut statistics,
+                                                 Some(ScanMode::Forward),
+                                                 false,
+                                                 None,
+                                                 ctx.get_isolation_level());
+                if is_range_start && !reader.need_gc(safe_point, self.ratio_threshold) {
+                    return Ok(RegionGc::Skipped);
+                }
+                box_try!(reader.scan_keys(scan_key.take(), self.batch_keys))
+            };
+            is_range_start = false;
+            if keys.is_empty() {
+                return Ok(RegionGc::Done);
+            }
+
+            let mut processed = 0;
+            let modifies = {
+                let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                           &mut statistics,
+                                           0,
+                                           Some(ScanMode::Forward),
+                                           ctx.get_isolation_level());
+                for k in &keys {
+                    box_try!(txn.gc(k, safe_point));
+                    processed += 1;
+                    if txn.write_size() >= MAX_TXN_WRITE_SIZE {
+                        scan_key = Some(k.to_owned());
+                        break;
+                    }
+                }
+                txn.modifies()
+            };
+            if !modifies.is_empty() {
+                box_try!(self.engine.write(&ctx, modifies));
+            }
+            GC_WORKER_KEYS_COUNTER.inc_by(processed as f64).unwrap();
+
+            let wait = self.limiter.consume(processed);
+            if self.wait(wait) {
+                return Ok(RegionGc::Stopped);
+            }
+            if scan_key.is_none() {
+                scan_key = next_key;
+                if scan_key.is_none() {
+                    return Ok(RegionGc::Done);
+                }
+            }
+        }
+    }
+}
+
+/// Drives the gc of the local store by the cluster safe point, see the module document.
+pub struct GcWorker<C: PdClient + 'static> {
+    runner: Option<Runner<C>>,
+    stop_tx: Sender<()>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl<C: PdClient + 'static> GcWorker<C> {
+    /// Creates a gc worker of the store. If `gc_context` is given, the worker only pushes the
+    /// safe point to the compaction filters.
+    pub fn new(store_id: u64,
+               engine: Box<Engine>,
+               db: Arc<DB>,
+               pd_client: Arc<C>,
+               gc_context: Option<GcContext>,
+               cfg: &StorageConfig)
+               -> GcWorker<C> {
+        let (tx, rx) = mpsc::channel();
+        let runner = Runner {
+            store_id: store_id,
+            engine: engine,
+            db: db,
+            pd_client: pd_client,
+            gc_context: gc_context,
+            poll_interval: Duration::from_millis(cfg.gc_safe_point_poll_interval),
+            ratio_threshold: cfg.gc_ratio_threshold,
+            batch_keys: cfg.gc_batch_keys,
+            limiter: KeysLimiter::new(cfg.gc_max_keys_per_sec),
+            safe_point: 0,
+            stop_rx: rx,
+        };
+        GcWorker {
+            runner: Some(runner),
+            stop_tx: tx,
+            handle: None,
+        }
+    }
+
+    pub fn start(&mut self) -> Result<()> {
+        let mut runner = match self.runner.take() {
+            Some(runner) => runner,
+            None => return Err(box_err!("gc worker is already started")),
+        };
+        let h = try!(thread::Builder::new()
+            .name(thd_name!("gc-worker"))
+            .spawn(move || runner.run()));
+        self.handle = Some(h);
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> Option<JoinHandle<()>> {
+        let h = self.handle.take();
+        if h.is_some() {
+            if let Err(e) = self.stop_tx.send(()) {
+                warn!("failed to stop gc worker: {:?}", e);
+            }
+        }
+        h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::KeysLimiter;
+
+    #[test]
+    fn test_keys_limiter() {
+        let mut limiter = KeysLimiter::new(0);
+        assert_eq!(limiter.consume(100000), Duration::from_secs(0));
+
+        let mut limiter = KeysLimiter::new(100);
+        let wait = limiter.consume(100);
+        assert!(wait <= Duration::from_secs(1) && wait > Duration::from_millis(900));
+        let wait = limiter.consume(50);
+        assert!(wait <= Duration::from_millis(1500) && wait > Duration::from_millis(1400));
+
+        limiter.reset();
+        assert_eq!(limiter.consume(0), Duration::from_secs(0));
+        assert!(limiter.consume(10) <= Duration::from_millis(100));
+    }
+}
@@ -87,6 +87,18 @@ fn make_callback<T: Debug + Send + 'static>() -> (Box<FnBox(T) + Send>, oneshot:
     (box callback, rx)
 }
 
+// TODO: add the rpcs below once kvproto has them.
+// - split region, which splits the region at the keys through `Msg::BatchSplitRegion` and returns
+//   the resulting regions.
+// - txn heart beat, through `Storage::async_txn_heart_beat`, which returns the lock ttl.
+// - check txn status, through `Storage::async_check_txn_status`, which returns the lock ttl or
+//   the commit ts of the transaction.
+// - raw batch get, put and delete, through `Storage::async_raw_batch_*`.
+// - raw delete range, through `Storage::async_raw_delete_range`.
+// - checksum, through `Storage::async_checksum`, which returns the checksum, the number of keys
+//   and the size of a range at a ts.
+// - change data, a server streaming rpc which registers the client to `cdc::Endpoint` and sends
+//   it the events, the subscription is deregistered once the client goes away.
 impl<T: RaftStoreRouter + 'static> tikvpb_grpc::Tikv for Service<T> {
     fn kv_get(&self, ctx: RpcContext, mut req: GetRequest, sink: UnarySink<GetResponse>) {
         let label = "kv_get";
@@ -133,10 +145,12 @@ impl<T: RaftStoreRouter + 'static> tikvpb_grpc::Tikv for Service<T> {
         let storage = self.storage.clone();
         let mut options = Options::default();
         options.key_only = req.get_key_only();
+        // TODO: set `options.reverse_scan` and the end key once `ScanRequest` has them in kvproto.
 
         let (cb, future) = make_callback();
         let res = storage.async_scan(req.take_context(),
                                      Key::from_raw(req.get_start_key()),
+                                     None,
                                      req.get_limit() as usize,
                                      req.get_version(),
                                      options,
@@ -525,7 +539,7 @@ impl<T: RaftStoreRouter + 'static> tikvpb_grpc::Tikv for Service<T> {
         let timer = GRPC_MSG_HISTOGRAM_VEC.with_label_values(&[label]).start_timer();
 
         let (cb, future) = make_callback();
-        let res = self.storage.async_raw_get(req.take_context(), req.take_key(), cb);
+        let res = self.storage.async_raw_get(req.take_context(), "", req.take_key(), cb);
         if let Err(e) = res {
             self.send_fail_status(ctx, sink, Error::from(e), RpcStatusCode::ResourceExhausted);
             return;
@@ -564,7 +578,9 @@ impl<T: RaftStoreRouter + 'static> tikvpb_grpc::Tikv for Service<T> {
 
         let (cb, future) = make_callback();
         let res = self.storage.async_raw_scan(req.take_context(),
+                                              "",
                                               req.take_start_key(),
+                                              None,
                                               req.get_limit() as usize,
                                               cb);
         if let Err(e) = res {
@@ -596,9 +612,11 @@ impl<T: RaftStoreRouter + 'static> tikvpb_grpc::Tikv for Service<T> {
         let label = "raw_put";
         let timer = GRPC_MSG_HISTOGRAM_VEC.with_label_values(&[label]).start_timer();
 
+        // TODO: pass the cf and the ttl once `RawPutRequest` has them in kvproto, the raw values
+        // can only expire in `CF_RAW`.
         let (cb, future) = make_callback();
         let res = self.storage
-            .async_raw_put(req.take_context(), req.take_key(), req.take_value(), cb);
+            .async_raw_put(req.take_context(), "", req.take_key(), req.take_value(), 0, cb);
         if let Err(e) = res {
             self.send_fail_status(ctx, sink, Error::from(e), RpcStatusCode::ResourceExhausted);
             return;
@@ -632,7 +650,7 @@ impl<T: RaftStoreRouter + 'static> tikvpb_grpc::Tikv for Service<T> {
         let timer = GRPC_MSG_HISTOGRAM_VEC.with_label_values(&[label]).start_timer();
 
         let (cb, future) = make_callback();
-        let res = self.storage.async_raw_delete(req.take_context(), req.take_key(), cb);
+        let res = self.storage.async_raw_delete(req.take_context(), "", req.take_key(), cb);
         if let Err(e) = res {
             self.send_fail_status(ctx, sink, Error::from(e), RpcStatusCode::ResourceExhausted);
             return;
@@ -871,6 +889,18 @@ fn extract_key_error(err: &storage::Error) -> KeyError {
             warn!("txn conflicts: {:?}", err);
             key_error.set_retryable(format!("{:?}", err));
         }
+        // There is no dedicated deadlock field in `KeyError`. The victim transaction is aborted
+        // rather than told to retry, or it would meet the same deadlock again.
+        storage::Error::Txn(TxnError::Mvcc(MvccError::Deadlock { .. })) => {
+            warn!("txn deadlocks: {:?}", err);
+            key_error.set_abort(format!("{:?}", err));
+        }
+        // The transaction isn't committed by the one-phase prewrite, it can be prewritten again
+        // in two phases.
+        storage::Error::Txn(TxnError::CommitTsTooOld { .. }) => {
+            warn!("one-phase commit fails: {:?}", err);
+            key_error.set_retryable(format!("{:?}", err));
+        }
         _ => {
             error!("txn aborts: {:?}", err);
             key_error.set_abort(format!("{:?}", err));
@@ -11,7 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use prometheus::{Counter, CounterVec, Histogram, HistogramVec};
+use prometheus::{Counter, CounterVec, Gauge, Histogram, HistogramVec};
 
 lazy_static! {
     pub static ref SEND_SNAP_HISTOGRAM: Histogram =
@@ -60,4 +60,41 @@ lazy_static! {
             "Total number of reporting failure messages",
             &["type", "store_id"]
         ).unwrap();
+
+    pub static ref GC_WORKER_SAFE_POINT_GAUGE: Gauge =
+        register_gauge!(
+            "tikv_gc_worker_safe_point_ms",
+            "Physical time of the last safe point the gc worker finished"
+        ).unwrap();
+
+    pub static ref GC_WORKER_LAG_GAUGE: Gauge =
+        register_gauge!(
+            "tikv_gc_worker_lag_seconds",
+            "Time the last safe point the gc worker finished falls behind now"
+        ).unwrap();
+
+    pub static ref GC_WORKER_PROGRESS_GAUGE: Gauge =
+        register_gauge!(
+            "tikv_gc_worker_progress",
+            "Ratio of the regions walked by the running gc round"
+        ).unwrap();
+
+    pub static ref GC_WORKER_ROUND_HISTOGRAM: Histogram =
+        register_histogram!(
+            "tikv_gc_worker_round_duration_seconds",
+            "Bucketed histogram of gc worker round duration"
+        ).unwrap();
+
+    pub static ref GC_WORKER_REGION_COUNTER_VEC: CounterVec =
+        register_counter_vec!(
+            "tikv_gc_worker_region_total",
+            "Total number of regions walked by the gc worker",
+            &["type"]
+        ).unwrap();
+
+    pub static ref GC_WORKER_KEYS_COUNTER: Counter =
+        register_counter!(
+            "tikv_gc_worker_keys_total",
+            "Total number of keys collected by the gc worker"
+        ).unwrap();
 }
@@ -26,11 +26,12 @@ use kvproto::metapb;
 use protobuf::RepeatedField;
 use util::transport::SendCh;
 use raftstore::store::{self, Msg, SnapshotStatusMsg, StoreChannel, Store, Config as StoreConfig,
-                       keys, Peekable, Transport, SnapManager};
+                       keys, Peekable, Transport, SnapManager, LoadRecorder};
 use super::Result;
 use super::config::Config;
 use storage::{Storage, RaftKv};
 use super::transport::RaftStoreRouter;
+use raftstore::coprocessor::CoprocessorHost;
 
 const MAX_CHECK_CLUSTER_BOOTSTRAPPED_RETRY_COUNT: u64 = 60;
 const CHECK_CLUSTER_BOOTSTRAPPED_RETRY_SECONDS: u64 = 3;
@@ -67,6 +68,7 @@ pub struct Node<C: PdClient + 'static> {
     store_cfg: StoreConfig,
     store_handle: Option<thread::JoinHandle<()>>,
     ch: SendCh<Msg>,
+    load_recorder: LoadRecorder,
 
     pd_client: Arc<C>,
 }
@@ -105,15 +107,23 @@ impl<C> Node<C>
             store_handle: None,
             pd_client: pd_client,
             ch: ch,
+            load_recorder: LoadRecorder::new(),
         }
     }
 
+    /// The recorder of the keys accessed in the regions, the storage should record the keys of
+    /// its requests in it so that the hot regions are split.
+    pub fn load_recorder(&self) -> LoadRecorder {
+        self.load_recorder.clone()
+    }
+
     pub fn start<T>(&mut self,
                     event_loop: EventLoop<Store<T, C>>,
                     engine: Arc<DB>,
                     trans: T,
                     snap_mgr: SnapManager,
-                    snap_status_receiver: Receiver<SnapshotStatusMsg>)
+                    snap_status_receiver: Receiver<SnapshotStatusMsg>,
+                    coprocessor_host: CoprocessorHost)
                     -> Result<()>
         where T: Transport + 'static
     {
@@ -147,7 +157,8 @@ impl<C> Node<C>
                               engine,
                               trans,
                               snap_mgr,
-                              snap_status_receiver));
+                              snap_status_receiver,
+                              coprocessor_host));
         Ok(())
     }
 
@@ -278,7 +289,8 @@ impl<C> Node<C>
                       db: Arc<DB>,
                       trans: T,
                       snap_mgr: SnapManager,
-                      snapshot_status_receiver: Receiver<SnapshotStatusMsg>)
+                      snapshot_status_receiver: Receiver<SnapshotStatusMsg>,
+                      coprocessor_host: CoprocessorHost)
                       -> Result<()>
         where T: Transport + 'static
     {
@@ -292,6 +304,7 @@ impl<C> Node<C>
         let pd_client = self.pd_client.clone();
         let store = self.store.clone();
         let sender = event_loop.channel();
+        let load_recorder = self.load_recorder.clone();
 
         let (tx, rx) = mpsc::channel();
         let builder = thread::Builder::new().name(thd_name!(format!("raftstore-{}", store_id)));
@@ -300,7 +313,15 @@ impl<C> Node<C>
                 sender: sender,
                 snapshot_status_receiver: snapshot_status_receiver,
             };
-            let mut store = match Store::new(ch, store, cfg, db, trans, pd_client, snap_mgr) {
+            let mut store = match Store::new(ch,
+                                             store,
+                                             cfg,
+                                             db,
+                                             trans,
+                                             pd_client,
+                                             snap_mgr,
+                                             coprocessor_host,
+                                             load_recorder) {
                 Err(e) => panic!("construct store {} err {:?}", store_id, e),
                 Ok(s) => s,
             };
@@ -222,6 +222,12 @@ mod tests {
         fn report_split(&self, _: metapb::Region, _: metapb::Region) -> PdFuture<()> {
             unimplemented!();
         }
+        fn get_gc_safe_point(&self) -> PdFuture<u64> {
+            unimplemented!();
+        }
+        fn get_timestamp(&self) -> PdFuture<u64> {
+            unimplemented!();
+        }
     }
 
     fn new_store(addr: &str, state: metapb::StoreState) -> metapb::Store {
@@ -47,6 +47,11 @@ pub trait RaftStoreRouter: Send + Clone {
         self.try_send(StoreMsg::new_raft_cmd(req, cb))
     }
 
+    // Send a read-only RaftCmdRequest to local store, which may be served by a follower.
+    fn send_follower_read_command(&self, req: RaftCmdRequest, cb: Callback) -> RaftStoreResult<()> {
+        self.try_send(StoreMsg::new_follower_read_cmd(req, cb))
+    }
+
     // Send a batch of RaftCmdRequests to local store.
     fn send_batch_commands(&self,
                            batch: Vec<RaftCmdRequest>,
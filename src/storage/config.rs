@@ -19,6 +19,9 @@ const DEFAULT_SCHED_CAPACITY: usize = 10240;
 const DEFAULT_SCHED_MSG_PER_TICK: usize = 1024;
 const DEFAULT_SCHED_CONCURRENCY: usize = 102400;
 const DEFAULT_SCHED_TOO_BUSY_THRESHOLD: usize = 1000;
+const DEFAULT_GC_SAFE_POINT_POLL_INTERVAL: u64 = 0; // disable the gc worker by default.
+const DEFAULT_GC_BATCH_KEYS: usize = 512;
+const DEFAULT_GC_MAX_KEYS_PER_SEC: usize = 0;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
@@ -31,6 +34,23 @@ pub struct Config {
     pub scheduler_concurrency: usize,
     pub scheduler_worker_pool_size: usize,
     pub scheduler_too_busy_threshold: usize,
+    // Whether the raw values in `CF_RAW` are stored with an expire time. It can't be changed
+    // once raw data has been written there.
+    pub enable_ttl: bool,
+    // Whether old MVCC versions are dropped by compaction filters instead of being deleted by
+    // GC commands. It can't be used together with the consistency check, and raw values can
+    // only be stored in `CF_RAW` then.
+    pub enable_compaction_filter_gc: bool,
+    // How often, in milliseconds, the gc worker asks pd for the safe point. 0 disables the gc
+    // worker.
+    pub gc_safe_point_poll_interval: u64,
+    // The number of keys the gc worker collects in a batch.
+    pub gc_batch_keys: usize,
+    // The max number of keys the gc worker collects per second, 0 means no limit.
+    pub gc_max_keys_per_sec: usize,
+    // Whether followers serve Get, BatchGet and Scan once they have applied up to the read index
+    // of the leader.
+    pub enable_follower_read: bool,
 }
 
 impl Default for Config {
@@ -44,6 +64,12 @@ impl Default for Config {
             scheduler_concurrency: DEFAULT_SCHED_CONCURRENCY,
             scheduler_worker_pool_size: if total_cpu >= 16 { 8 } else { 4 },
             scheduler_too_busy_threshold: DEFAULT_SCHED_TOO_BUSY_THRESHOLD,
+            enable_ttl: false,
+            enable_compaction_filter_gc: false,
+            gc_safe_point_poll_interval: DEFAULT_GC_SAFE_POINT_POLL_INTERVAL,
+            gc_batch_keys: DEFAULT_GC_BATCH_KEYS,
+            gc_max_keys_per_sec: DEFAULT_GC_MAX_KEYS_PER_SEC,
+            enable_follower_read: false,
         }
     }
 }
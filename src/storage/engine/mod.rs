@@ -68,6 +68,14 @@ pub enum Modify {
 pub trait Engine: Send + Debug {
     fn async_write(&self, ctx: &Context, batch: Vec<Modify>, callback: Callback<()>) -> Result<()>;
     fn async_snapshot(&self, ctx: &Context, callback: Callback<Box<Snapshot>>) -> Result<()>;
+    /// Like `async_snapshot`, but the snapshot may be taken on a follower once it has applied up
+    /// to the read index of the leader. Engines without followers take a normal snapshot.
+    fn async_follower_snapshot(&self,
+                               ctx: &Context,
+                               callback: Callback<Box<Snapshot>>)
+                               -> Result<()> {
+        self.async_snapshot(ctx, callback)
+    }
     /// Snapshots are token by `Context`s, the results are send to the `on_finished` callback,
     /// with the same order. If a read-index is occurred, a `None` is placed in the corresponding
     /// slot, and the caller is responsible for reissuing it again, in `async_snapshot`.
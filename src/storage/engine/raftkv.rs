@@ -178,6 +178,17 @@ impl<S: RaftStoreRouter> RaftKv<S> {
         Ok(())
     }
 
+    fn call_follower_read_command(&self, req: RaftCmdRequest, cb: Callback<CmdRes>) -> Result<()> {
+        let l = req.get_requests().len();
+        let db = self.db.clone();
+        try!(self.router.send_follower_read_command(req,
+                                                    box move |resp| {
+            let (cb_ctx, res) = on_result(resp, l, db);
+            cb((cb_ctx, res.map_err(Error::into)));
+        }));
+        Ok(())
+    }
+
     fn batch_call_snap_commands(&self,
                                 batch: Vec<RaftCmdRequest>,
                                 on_finished: BatchCallback<CmdRes>)
@@ -234,11 +245,65 @@ impl<S: RaftStoreRouter> RaftKv<S> {
     }
 
     fn exec_requests(&self, ctx: &Context, reqs: Vec<Request>, cb: Callback<CmdRes>) -> Result<()> {
+        let cmd = self.new_cmd(ctx, reqs);
+        self.call_command(cmd, cb)
+    }
+
+    fn exec_follower_read_requests(&self,
+                                   ctx: &Context,
+                                   reqs: Vec<Request>,
+                                   cb: Callback<CmdRes>)
+                                   -> Result<()> {
+        let cmd = self.new_cmd(ctx, reqs);
+        self.call_follower_read_command(cmd, cb)
+    }
+
+    fn new_cmd(&self, ctx: &Context, reqs: Vec<Request>) -> RaftCmdRequest {
         let header = self.new_request_header(ctx);
         let mut cmd = RaftCmdRequest::new();
         cmd.set_header(header);
         cmd.set_requests(RepeatedField::from_vec(reqs));
-        self.call_command(cmd, cb)
+        cmd
+    }
+
+    fn exec_snapshot(&self,
+                     ctx: &Context,
+                     cb: Callback<Box<Snapshot>>,
+                     allow_follower: bool)
+                     -> engine::Result<()> {
+        let mut req = Request::new();
+        req.set_cmd_type(CmdType::Snap);
+
+        ASYNC_REQUESTS_COUNTER_VEC.with_label_values(&["snapshot", "all"]).inc();
+        let req_timer = ASYNC_REQUESTS_DURATIONS_VEC.with_label_values(&["snapshot"]).start_timer();
+
+        let on_finished: Callback<CmdRes> = box move |(cb_ctx, res)| {
+            match res {
+                Ok(CmdRes::Resp(r)) => {
+                    cb((cb_ctx, Err(invalid_resp_type(CmdType::Snap, r[0].get_cmd_type()).into())))
+                }
+                Ok(CmdRes::Snap(s)) => {
+                    req_timer.observe_duration();
+                    ASYNC_REQUESTS_COUNTER_VEC.with_label_values(&["snapshot", "success"]).inc();
+                    cb((cb_ctx, Ok(box s)))
+                }
+                Err(e) => {
+                    let tag = get_tag_from_engine_error(&e);
+                    ASYNC_REQUESTS_COUNTER_VEC.with_label_values(&["snapshot", tag]).inc();
+                    cb((cb_ctx, Err(e)))
+                }
+            }
+        };
+        let res = if allow_follower {
+            self.exec_follower_read_requests(ctx, vec![req], on_finished)
+        } else {
+            self.exec_requests(ctx, vec![req], on_finished)
+        };
+        res.map_err(|e| {
+            let tag = get_tag_from_error(&e);
+            ASYNC_REQUESTS_COUNTER_VEC.with_label_values(&["snapshot", tag]).inc();
+            e.into()
+        })
     }
 
     fn batch_exec_snap_requests(&self,
@@ -341,38 +406,14 @@ impl<S: RaftStoreRouter> Engine for RaftKv<S> {
     }
 
     fn async_snapshot(&self, ctx: &Context, cb: Callback<Box<Snapshot>>) -> engine::Result<()> {
-        let mut req = Request::new();
-        req.set_cmd_type(CmdType::Snap);
-
-        ASYNC_REQUESTS_COUNTER_VEC.with_label_values(&["snapshot", "all"]).inc();
-        let req_timer = ASYNC_REQUESTS_DURATIONS_VEC.with_label_values(&["snapshot"]).start_timer();
+        self.exec_snapshot(ctx, cb, false)
+    }
 
-        self.exec_requests(ctx,
-                           vec![req],
-                           box move |(cb_ctx, res)| {
-                match res {
-                    Ok(CmdRes::Resp(r)) => {
-                        cb((cb_ctx,
-                            Err(invalid_resp_type(CmdType::Snap, r[0].get_cmd_type()).into())))
-                    }
-                    Ok(CmdRes::Snap(s)) => {
-                        req_timer.observe_duration();
-                        ASYNC_REQUESTS_COUNTER_VEC.with_label_values(&["snapshot", "success"])
-                            .inc();
-                        cb((cb_ctx, Ok(box s)))
-                    }
-                    Err(e) => {
-                        let tag = get_tag_from_engine_error(&e);
-                        ASYNC_REQUESTS_COUNTER_VEC.with_label_values(&["snapshot", tag]).inc();
-                        cb((cb_ctx, Err(e)))
-                    }
-                }
-            })
-            .map_err(|e| {
-                let tag = get_tag_from_error(&e);
-                ASYNC_REQUESTS_COUNTER_VEC.with_label_values(&["snapshot", tag]).inc();
-                e.into()
-            })
+    fn async_follower_snapshot(&self,
+                               ctx: &Context,
+                               cb: Callback<Box<Snapshot>>)
+                               -> engine::Result<()> {
+        self.exec_snapshot(ctx, cb, true)
     }
 
     fn async_batch_snapshot(&self,
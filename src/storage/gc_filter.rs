@@ -0,0 +1,522 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! MVCC garbage collection in compaction.
+//!
+//! Instead of scanning keys and writing deletes through Raft, old versions are dropped by
+//! compaction filters on `CF_WRITE` and `CF_DEFAULT` once they are older than the safe point
+//! pushed to the store. Every replica collects its own garbage, so nothing is replicated.
+//!
+//! For every key, the latest `Put` or `Delete` committed before the safe point is kept, as it
+//! is what reads at the safe point see. Versions older than it are dropped, as well as `Lock`
+//! and `Rollback` records committed before the safe point. The latest `Delete` itself is
+//! dropped once no older version of the key is left in the engine, the compaction can't see
+//! the versions in other levels.
+//!
+//! A value in `CF_DEFAULT` started before the safe point is dropped when neither a lock of its
+//! transaction nor a `Put` record pointing to it is left, so the values of the writes dropped
+//! from `CF_WRITE` go away in the following compactions of `CF_DEFAULT`, even across restarts.
+//!
+//! A key locked by a transaction started before the safe point is left alone, the transaction
+//! may still commit before the safe point.
+//!
+//! Replicas collect garbage at different times, so the data of a region differs between them
+//! for a while. It can't be used together with the consistency check, which compares the data
+//! of the replicas, nor with raw values in `CF_DEFAULT`, which have no MVCC records.
+
+use std::cell::RefCell;
+use std::sync::{Arc, RwLock, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use rocksdb::{DB, CompactOptions, CompactionFilter};
+
+use raftstore::store::keys;
+use raftstore::store::engine::{Iterable, Peekable};
+use storage::{Key, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use storage::mvcc::{Lock, Write, WriteType};
+use storage::types::split_encoded_key_on_ts;
+use util::rocksdb::get_cf_handle;
+
+pub const GC_WRITE_COMPACTION_FILTER: &'static str = "tikv.gc_write";
+pub const GC_DEFAULT_COMPACTION_FILTER: &'static str = "tikv.gc_default";
+
+#[derive(Default)]
+struct Inner {
+    safe_point: u64,
+    db: Option<Weak<DB>>,
+}
+
+/// The GC state shared by the compaction filters of a store.
+#[derive(Clone, Default)]
+pub struct GcContext {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl GcContext {
+    pub fn new() -> GcContext {
+        GcContext::default()
+    }
+
+    /// Sets the engine the filters run in, nothing is collected until it's set.
+    pub fn set_db(&self, db: &Arc<DB>) {
+        self.inner.write().unwrap().db = Some(Arc::downgrade(db));
+    }
+
+    pub fn db(&self) -> Option<Arc<DB>> {
+        self.inner.read().unwrap().db.as_ref().and_then(|db| db.upgrade())
+    }
+
+    pub fn safe_point(&self) -> u64 {
+        self.inner.read().unwrap().safe_point
+    }
+
+    /// Pushes the safe point, returns false if it is not newer than the current one, which is
+    /// kept then.
+    pub fn update_safe_point(&self, safe_point: u64) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        if safe_point <= inner.safe_point {
+            return false;
+        }
+        inner.safe_point = safe_point;
+        true
+    }
+
+    /// Compacts `CF_WRITE` and then `CF_DEFAULT` in the range of data keys [`start_key`,
+    /// `end_key`], so that the filters can drop the garbage in it.
+    pub fn compact_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<(), String> {
+        let db = match self.db() {
+            Some(db) => db,
+            None => return Err("the engine of gc is not set".to_owned()),
+        };
+        let mut compact_opts = CompactOptions::new();
+        // manual compaction can concurrently run with background compaction threads.
+        compact_opts.set_exclusive_manual_compaction(false);
+        // The values are dropped after the writes pointing to them.
+        for cf in &[CF_WRITE, CF_DEFAULT] {
+            let handle = try!(get_cf_handle(&db, cf));
+            db.compact_range_cf_opt(handle, &compact_opts, Some(start_key), Some(end_key));
+        }
+        Ok(())
+    }
+}
+
+/// Checks whether `key`, a data key without timestamp, is locked by a transaction started not
+/// later than `ts`. Errors are treated as locked, so that nothing is dropped.
+fn is_locked_before(db: &DB, key: &[u8], ts: u64) -> bool {
+    match db.get_value_cf(CF_LOCK, key) {
+        Ok(Some(value)) => Lock::parse(&value).map(|lock| lock.ts <= ts).unwrap_or(true),
+        Ok(None) => false,
+        Err(_) => true,
+    }
+}
+
+/// Checks whether `key`, a data key without timestamp, has a record in `CF_WRITE` committed
+/// before `commit_ts`. Errors are treated as found.
+fn has_older_write(db: &DB, key: &[u8], commit_ts: u64) -> bool {
+    if commit_ts == 0 {
+        return false;
+    }
+    let seek_key = Key::from_encoded(key.to_vec()).append_ts(commit_ts - 1);
+    match db.seek_cf(CF_WRITE, seek_key.encoded()) {
+        Ok(Some((k, _))) => split_data_key(&k).map_or(false, |(k, _)| k == key),
+        Ok(None) => false,
+        Err(_) => true,
+    }
+}
+
+/// Checks whether the value of `key`, a data key without timestamp, written by the transaction
+/// started at `start_ts` is still used, by its lock or by a `Put` record. Errors are treated as
+/// used.
+fn is_value_used(db: &DB, key: &[u8], start_ts: u64) -> bool {
+    // The lock is checked first, a commit replaces it with the record in one write batch.
+    match db.get_value_cf(CF_LOCK, key) {
+        Ok(Some(value)) => {
+            match Lock::parse(&value) {
+                Ok(lock) => {
+                    if lock.ts == start_ts {
+                        return true;
+                    }
+                }
+                Err(_) => return true,
+            }
+        }
+        Ok(None) => {}
+        Err(_) => return true,
+    }
+    // The record is committed after the transaction starts, so only the newer records are
+    // scanned, from the latest one.
+    let end_key = Key::from_encoded(key.to_vec()).append_ts(start_ts);
+    let mut used = false;
+    let res = db.scan_cf(CF_WRITE,
+                         key,
+                         end_key.encoded(),
+                         false,
+                         &mut |_, value| {
+        let write = box_try!(Write::parse(value));
+        if write.start_ts == start_ts && write.write_type == WriteType::Put {
+            used = true;
+            return Ok(false);
+        }
+        Ok(true)
+    });
+    used || res.is_err()
+}
+
+/// Splits a data key of MVCC data into the key and its timestamp, returns `None` for keys that
+/// aren't MVCC data.
+fn split_data_key(key: &[u8]) -> Option<(&[u8], u64)> {
+    if !keys::validate_data_key(key) {
+        return None;
+    }
+    split_encoded_key_on_ts(key).ok()
+}
+
+static FILTER_ID_ALLOC: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// The state of the compaction running in the thread. RocksDB runs a compaction, or a part of
+// it, in one thread and passes the keys to the filter in order, so a key not greater than the
+// last one starts another compaction.
+struct CompactionState {
+    filter_id: usize,
+    last_key: Vec<u8>,
+    // The key, without timestamp, of the versions being filtered.
+    key: Vec<u8>,
+    // Whether the key is locked by a transaction started before the safe point.
+    locked: bool,
+    // The commit ts of the latest `Put` or `Delete` of the key before the safe point seen in
+    // the compaction, all the older versions of the key can be dropped.
+    latest: Option<u64>,
+}
+
+thread_local!(static COMPACTION_STATE: RefCell<Option<CompactionState>> = RefCell::new(None));
+
+/// Drops the records of `CF_WRITE` that are no longer visible at the safe point.
+pub struct WriteCompactionFilter {
+    id: usize,
+    ctx: GcContext,
+}
+
+impl WriteCompactionFilter {
+    pub fn new(ctx: GcContext) -> WriteCompactionFilter {
+        WriteCompactionFilter {
+            id: FILTER_ID_ALLOC.fetch_add(1, Ordering::SeqCst),
+            ctx: ctx,
+        }
+    }
+
+    fn filter_write(&self, state: &mut CompactionState, key: &[u8], value: &[u8]) -> bool {
+        let (key, commit_ts) = match split_data_key(key) {
+            Some(res) => res,
+            None => return false,
+        };
+        let safe_point = self.ctx.safe_point();
+        if commit_ts > safe_point {
+            return false;
+        }
+        let db = match self.ctx.db() {
+            Some(db) => db,
+            None => return false,
+        };
+        if state.key.as_slice() != key {
+            state.key.clear();
+            state.key.extend_from_slice(key);
+            state.locked = is_locked_before(&db, key, safe_point);
+            state.latest = None;
+        }
+        if state.locked {
+            return false;
+        }
+        let write = match Write::parse(value) {
+            Ok(write) => write,
+            Err(_) => return false,
+        };
+
+        if let Some(ts) = state.latest {
+            if commit_ts < ts {
+                return true;
+            }
+        }
+        match write.write_type {
+            WriteType::Put => {
+                state.latest = Some(commit_ts);
+                false
+            }
+            WriteType::Delete => {
+                state.latest = Some(commit_ts);
+                !has_older_write(&db, key, commit_ts)
+            }
+            WriteType::Rollback | WriteType::Lock => true,
+        }
+    }
+}
+
+impl CompactionFilter for WriteCompactionFilter {
+    fn filter(&mut self, _: usize, key: &[u8], value: &[u8]) -> bool {
+        COMPACTION_STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let new_compaction = match *state {
+                Some(ref s) => s.filter_id != self.id || key <= s.last_key.as_slice(),
+                None => true,
+            };
+            if new_compaction {
+                *state = Some(CompactionState {
+                    filter_id: self.id,
+                    last_key: vec![],
+                    key: vec![],
+                    locked: false,
+                    latest: None,
+                });
+            }
+            let state = state.as_mut().unwrap();
+            state.last_key.clear();
+            state.last_key.extend_from_slice(key);
+            self.filter_write(state, key, value)
+        })
+    }
+}
+
+/// Drops the values in `CF_DEFAULT` no longer used by a lock or a record in `CF_WRITE`.
+pub struct DefaultCompactionFilter {
+    ctx: GcContext,
+}
+
+impl DefaultCompactionFilter {
+    pub fn new(ctx: GcContext) -> DefaultCompactionFilter {
+        DefaultCompactionFilter { ctx: ctx }
+    }
+}
+
+impl CompactionFilter for DefaultCompactionFilter {
+    fn filter(&mut self, _: usize, key: &[u8], _: &[u8]) -> bool {
+        let (key, start_ts) = match split_data_key(key) {
+            Some(res) => res,
+            None => return false,
+        };
+        if start_ts > self.ctx.safe_point() {
+            return false;
+        }
+        match self.ctx.db() {
+            Some(db) => !is_value_used(&db, key, start_ts),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rocksdb::{DB, DBOptions, ColumnFamilyOptions, CompactionFilter, Writable};
+    use tempdir::TempDir;
+
+    use raftstore::store::keys;
+    use storage::{make_key, ALL_CFS, CF_DEFAULT, CF_LOCK, CF_WRITE};
+    use storage::mvcc::{Lock, LockType, Write, WriteType};
+    use util::rocksdb::{self as rocksdb_util, CFOptions};
+    use super::*;
+
+    fn open_db(path: &str, ctx: &GcContext) -> Arc<DB> {
+        let cfs_opts = ALL_CFS.iter()
+            .map(|cf| {
+                let opts = CFOptions::new(cf, ColumnFamilyOptions::new());
+                if *cf == CF_WRITE || *cf == CF_DEFAULT {
+                    opts.with_gc(Some(ctx.clone()))
+                } else {
+                    opts
+                }
+            })
+            .collect();
+        let db = Arc::new(rocksdb_util::new_engine_opt(path, DBOptions::new(), cfs_opts).unwrap());
+        ctx.set_db(&db);
+        db
+    }
+
+    fn write_key(key: &[u8], ts: u64) -> Vec<u8> {
+        keys::data_key(make_key(key).append_ts(ts).encoded())
+    }
+
+    fn must_write(db: &DB, key: &[u8], write_type: WriteType, start_ts: u64, commit_ts: u64) {
+        let handle = rocksdb_util::get_cf_handle(db, CF_WRITE).unwrap();
+        let write = Write::new(write_type, start_ts, None);
+        db.put_cf(handle, &write_key(key, commit_ts), &write.to_bytes()).unwrap();
+        if write_type == WriteType::Put {
+            let handle = rocksdb_util::get_cf_handle(db, CF_DEFAULT).unwrap();
+            db.put_cf(handle, &write_key(key, start_ts), b"value").unwrap();
+        }
+    }
+
+    fn must_lock(db: &DB, key: &[u8], start_ts: u64) {
+        let handle = rocksdb_util::get_cf_handle(db, CF_LOCK).unwrap();
+        let lock = Lock::new(LockType::Put, key.to_vec(), start_ts, 0, None, 0);
+        db.put_cf(handle, &keys::data_key(make_key(key).encoded()), &lock.to_bytes()).unwrap();
+        let handle = rocksdb_util::get_cf_handle(db, CF_DEFAULT).unwrap();
+        db.put_cf(handle, &write_key(key, start_ts), b"value").unwrap();
+    }
+
+    fn must_exist(db: &DB, cf: &str, key: &[u8], ts: u64, exist: bool) {
+        let handle = rocksdb_util::get_cf_handle(db, cf).unwrap();
+        assert_eq!(db.get_cf(handle, &write_key(key, ts)).unwrap().is_some(), exist);
+    }
+
+    fn compact(ctx: &GcContext) {
+        ctx.compact_range(keys::DATA_MIN_KEY, keys::DATA_MAX_KEY).unwrap();
+    }
+
+    #[test]
+    fn test_gc_context() {
+        let path = TempDir::new("test_gc_context").unwrap();
+        let ctx = GcContext::new();
+        assert_eq!(ctx.safe_point(), 0);
+        assert!(ctx.db().is_none());
+        assert!(ctx.compact_range(keys::DATA_MIN_KEY, keys::DATA_MAX_KEY).is_err());
+
+        let _db = open_db(path.path().to_str().unwrap(), &ctx);
+        assert!(ctx.db().is_some());
+        assert!(ctx.update_safe_point(10));
+        assert!(!ctx.update_safe_point(5));
+        assert!(!ctx.update_safe_point(10));
+        assert_eq!(ctx.safe_point(), 10);
+    }
+
+    #[test]
+    fn test_gc_filter_per_compaction() {
+        let path = TempDir::new("test_gc_filter_per_compaction").unwrap();
+        let ctx = GcContext::new();
+        let _db = open_db(path.path().to_str().unwrap(), &ctx);
+        ctx.update_safe_point(10);
+
+        let put = |start_ts| Write::new(WriteType::Put, start_ts, None).to_bytes();
+        let mut f1 = WriteCompactionFilter::new(ctx.clone());
+        let mut f2 = WriteCompactionFilter::new(ctx.clone());
+        assert!(!f1.filter(0, &write_key(b"k1", 9), &put(8)));
+        assert!(f1.filter(0, &write_key(b"k1", 4), &put(3)));
+        // Another compaction hasn't seen the newer version.
+        assert!(!f2.filter(0, &write_key(b"k1", 2), &put(1)));
+        assert!(!f1.filter(0, &write_key(b"k1", 9), &put(8)));
+        assert!(!f1.filter(0, &write_key(b"k2", 9), &put(8)));
+        // Neither has a new compaction of the same filter, which starts from a smaller key.
+        assert!(!f1.filter(0, &write_key(b"k1", 4), &put(3)));
+    }
+
+    #[test]
+    fn test_gc_compaction_filter() {
+        let path = TempDir::new("test_gc_compaction_filter").unwrap();
+        let ctx = GcContext::new();
+        let db = open_db(path.path().to_str().unwrap(), &ctx);
+
+        must_write(&db, b"k1", WriteType::Put, 1, 2);
+        must_write(&db, b"k1", WriteType::Put, 3, 4);
+        must_write(&db, b"k1", WriteType::Rollback, 5, 5);
+        must_write(&db, b"k1", WriteType::Lock, 6, 7);
+        must_write(&db, b"k1", WriteType::Put, 8, 9);
+        must_write(&db, b"k2", WriteType::Put, 1, 2);
+        must_write(&db, b"k2", WriteType::Delete, 3, 4);
+
+        // Nothing is collected before the safe point is pushed.
+        compact(&ctx);
+        must_exist(&db, CF_WRITE, b"k1", 2, true);
+        must_exist(&db, CF_DEFAULT, b"k1", 1, true);
+
+        ctx.update_safe_point(7);
+        compact(&ctx);
+        must_exist(&db, CF_WRITE, b"k1", 2, false);
+        must_exist(&db, CF_DEFAULT, b"k1", 1, false);
+        must_exist(&db, CF_WRITE, b"k1", 4, true);
+        must_exist(&db, CF_DEFAULT, b"k1", 3, true);
+        must_exist(&db, CF_WRITE, b"k1", 5, false);
+        must_exist(&db, CF_WRITE, b"k1", 7, false);
+        must_exist(&db, CF_WRITE, b"k1", 9, true);
+        must_exist(&db, CF_DEFAULT, b"k1", 8, true);
+        must_exist(&db, CF_WRITE, b"k2", 2, false);
+        must_exist(&db, CF_DEFAULT, b"k2", 1, false);
+        // The older version was still in the engine during the compaction.
+        must_exist(&db, CF_WRITE, b"k2", 4, true);
+
+        // The safe point never goes back.
+        assert!(!ctx.update_safe_point(3));
+        assert!(ctx.update_safe_point(10));
+        compact(&ctx);
+        must_exist(&db, CF_WRITE, b"k1", 4, false);
+        must_exist(&db, CF_DEFAULT, b"k1", 3, false);
+        must_exist(&db, CF_WRITE, b"k1", 9, true);
+        must_exist(&db, CF_DEFAULT, b"k1", 8, true);
+        must_exist(&db, CF_WRITE, b"k2", 4, false);
+    }
+
+    #[test]
+    fn test_gc_compaction_filter_locks() {
+        let path = TempDir::new("test_gc_compaction_filter_locks").unwrap();
+        let ctx = GcContext::new();
+        let db = open_db(path.path().to_str().unwrap(), &ctx);
+
+        must_write(&db, b"k1", WriteType::Put, 1, 2);
+        must_write(&db, b"k1", WriteType::Put, 3, 4);
+        must_lock(&db, b"k1", 5);
+        must_write(&db, b"k2", WriteType::Put, 1, 2);
+        must_write(&db, b"k2", WriteType::Put, 3, 4);
+        must_lock(&db, b"k2", 15);
+        // The value of a rolled back transaction.
+        let handle = rocksdb_util::get_cf_handle(&db, CF_DEFAULT).unwrap();
+        db.put_cf(handle, &write_key(b"k3", 6), b"value").unwrap();
+
+        // A lock only holds back its own key.
+        ctx.update_safe_point(10);
+        compact(&ctx);
+        must_exist(&db, CF_WRITE, b"k1", 2, true);
+        must_exist(&db, CF_DEFAULT, b"k1", 1, true);
+        must_exist(&db, CF_DEFAULT, b"k1", 5, true);
+        must_exist(&db, CF_WRITE, b"k2", 2, false);
+        must_exist(&db, CF_DEFAULT, b"k2", 1, false);
+        must_exist(&db, CF_WRITE, b"k2", 4, true);
+        must_exist(&db, CF_DEFAULT, b"k2", 3, true);
+        must_exist(&db, CF_DEFAULT, b"k2", 15, true);
+        must_exist(&db, CF_DEFAULT, b"k3", 6, false);
+
+        // Once the lock is committed, the older versions are collected.
+        let handle = rocksdb_util::get_cf_handle(&db, CF_LOCK).unwrap();
+        db.delete_cf(handle, &keys::data_key(make_key(b"k1").encoded())).unwrap();
+        must_write(&db, b"k1", WriteType::Put, 5, 6);
+        compact(&ctx);
+        must_exist(&db, CF_WRITE, b"k1", 2, false);
+        must_exist(&db, CF_DEFAULT, b"k1", 1, false);
+        must_exist(&db, CF_WRITE, b"k1", 4, false);
+        must_exist(&db, CF_DEFAULT, b"k1", 3, false);
+        must_exist(&db, CF_WRITE, b"k1", 6, true);
+        must_exist(&db, CF_DEFAULT, b"k1", 5, true);
+    }
+
+    #[test]
+    fn test_gc_garbage_after_restart() {
+        let path = TempDir::new("test_gc_garbage_after_restart").unwrap();
+        let path_str = path.path().to_str().unwrap();
+        let ctx = GcContext::new();
+        let db = open_db(path_str, &ctx);
+        must_write(&db, b"k1", WriteType::Put, 1, 2);
+        must_write(&db, b"k1", WriteType::Put, 3, 4);
+
+        // Only `CF_WRITE` is compacted before the restart.
+        ctx.update_safe_point(10);
+        let handle = rocksdb_util::get_cf_handle(&db, CF_WRITE).unwrap();
+        db.compact_range_cf(handle, None, None);
+        must_exist(&db, CF_WRITE, b"k1", 2, false);
+        must_exist(&db, CF_DEFAULT, b"k1", 1, true);
+        drop(db);
+
+        let ctx = GcContext::new();
+        let db = open_db(path_str, &ctx);
+        ctx.update_safe_point(10);
+        compact(&ctx);
+        must_exist(&db, CF_DEFAULT, b"k1", 1, false);
+        must_exist(&db, CF_WRITE, b"k1", 4, true);
+        must_exist(&db, CF_DEFAULT, b"k1", 3, true);
+    }
+}
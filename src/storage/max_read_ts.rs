@@ -0,0 +1,340 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The max read ts protects the transactions committed in one phase.
+//!
+//! A transaction committed in two phases locks its keys before it gets the commit ts, so a
+//! reader after the commit ts either meets the lock or sees the commit. A transaction committed
+//! in one phase writes no locks, so it's only committed if its commit ts is larger than every
+//! ts the data has been read at, which is the max read ts.
+//!
+//! - The keys of a one-phase commit are guarded from the check of the max read ts until the
+//!   write is applied. A reader updates the max read ts, then waits for the guards of its keys
+//!   at or before its ts, so either the commit fails the check or the reader sees it.
+//! - The max read ts is kept in memory, so it's lost on restart and doesn't move with the
+//!   leader. When the local peer of a region becomes the leader, the region can't commit in one
+//!   phase until the max read ts is updated with a timestamp from pd, which is larger than the
+//!   ts of any read served before.
+//! - The reads served by followers update the max read ts of the followers, so one-phase commit
+//!   can't be used with follower read.
+
+use std::collections::BTreeMap;
+use std::collections::Bound::{Included, Unbounded};
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use std::u64;
+
+use futures::Future;
+use tokio_core::reactor::Handle;
+
+use pd::PdClient;
+use raft::StateRole;
+use raftstore::coprocessor::{Coprocessor, RegionObserver, ObserverContext, RegionChangeEvent};
+use util::collections::HashMap;
+use util::worker::{FutureRunnable, FutureScheduler, FutureWorker, Stopped};
+
+#[derive(Default)]
+struct Inner {
+    ts: AtomicUsize,
+    // The encoded keys being committed in one phase, with their commit ts.
+    guards: Mutex<BTreeMap<Vec<u8>, u64>>,
+    released: Condvar,
+    // The regions led by the local store whose max read ts isn't synced with pd yet, with the id
+    // of the sync.
+    unsynced: Mutex<HashMap<u64, usize>>,
+    sync_id_alloc: AtomicUsize,
+}
+
+/// The largest timestamp the data has been read at, see the module document.
+#[derive(Clone, Default)]
+pub struct MaxReadTs(Arc<Inner>);
+
+impl MaxReadTs {
+    pub fn update(&self, ts: u64) {
+        // Some reads just want the latest data, they don't need to be protected.
+        if ts == u64::MAX {
+            return;
+        }
+        // TiKV only runs on 64-bit platforms, so the timestamps fit in an usize.
+        let mut current = self.0.ts.load(Ordering::SeqCst);
+        while (current as u64) < ts {
+            let prev = self.0.ts.compare_and_swap(current, ts as usize, Ordering::SeqCst);
+            if prev == current {
+                return;
+            }
+            current = prev;
+        }
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.ts.load(Ordering::SeqCst) as u64
+    }
+
+    /// Updates the max read ts for a read of the encoded keys at `ts`. It must be called before
+    /// the snapshot of the read is taken, and returns once the one-phase commits of the keys at
+    /// or before `ts` are applied.
+    pub fn read_keys(&self, ts: u64, keys: &[&[u8]]) {
+        self.update(ts);
+        self.wait_guards(ts, |guards| {
+            keys.iter().any(|k| guards.get(*k).map_or(false, |&c| c <= ts))
+        })
+    }
+
+    /// Like `read_keys`, for a read of the encoded keys in [`start`, `end`), `None` means
+    /// unbounded.
+    pub fn read_range(&self, ts: u64, start: &[u8], end: Option<&[u8]>) {
+        self.update(ts);
+        self.wait_guards(ts, |guards| {
+            guards.range((Included(start.to_vec()), Unbounded::<Vec<u8>>))
+                .take_while(|&(k, _)| end.map_or(true, |e| k.as_slice() < e))
+                .any(|(_, &c)| c <= ts)
+        })
+    }
+
+    fn wait_guards<F>(&self, ts: u64, conflict: F)
+        where F: Fn(&BTreeMap<Vec<u8>, u64>) -> bool
+    {
+        if ts == u64::MAX {
+            return;
+        }
+        let mut guards = self.0.guards.lock().unwrap();
+        while conflict(&guards) {
+            guards = self.0.released.wait(guards).unwrap();
+        }
+    }
+
+    /// Guards the encoded keys committed at `commit_ts` in one phase. It must be called before
+    /// the max read ts is checked, and the keys must be latched until they are released.
+    pub fn guard(&self, keys: &[Vec<u8>], commit_ts: u64) {
+        let mut guards = self.0.guards.lock().unwrap();
+        for k in keys {
+            guards.insert(k.clone(), commit_ts);
+        }
+    }
+
+    /// Releases the guards of the keys once the commit is applied or fails.
+    pub fn release(&self, keys: &[Vec<u8>]) {
+        let mut guards = self.0.guards.lock().unwrap();
+        for k in keys {
+            guards.remove(k);
+        }
+        self.0.released.notify_all();
+    }
+
+    /// Updates the max read ts to `ts` for a resolved ts, and returns the largest ts no
+    /// one-phase commit in progress can be committed at or before.
+    pub fn resolve(&self, ts: u64) -> u64 {
+        self.update(ts);
+        let guards = self.0.guards.lock().unwrap();
+        match guards.values().min() {
+            Some(&c) if c <= ts => c - 1,
+            _ => ts,
+        }
+    }
+
+    /// Whether one-phase commit can be used in the region, that is, the max read ts has been
+    /// synced with pd since the local peer became the leader.
+    pub fn is_synced(&self, region_id: u64) -> bool {
+        !self.0.unsynced.lock().unwrap().contains_key(&region_id)
+    }
+
+    fn on_leader(&self, region_id: u64) -> usize {
+        let id = self.0.sync_id_alloc.fetch_add(1, Ordering::SeqCst);
+        self.0.unsynced.lock().unwrap().insert(region_id, id);
+        id
+    }
+
+    fn on_not_leader(&self, region_id: u64) {
+        self.0.unsynced.lock().unwrap().remove(&region_id);
+    }
+
+    fn on_synced(&self, region_id: u64, id: usize, ts: u64) {
+        self.update(ts);
+        let mut unsynced = self.0.unsynced.lock().unwrap();
+        // The leadership may change again before the timestamp is got.
+        if unsynced.get(&region_id) == Some(&id) {
+            unsynced.remove(&region_id);
+        }
+    }
+}
+
+pub struct SyncTask {
+    region_id: u64,
+    id: usize,
+}
+
+impl Display for SyncTask {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "sync max read ts of region {}", self.region_id)
+    }
+}
+
+struct SyncRunner<C: PdClient> {
+    pd_client: Arc<C>,
+    max_read_ts: MaxReadTs,
+}
+
+impl<C: PdClient> FutureRunnable<SyncTask> for SyncRunner<C> {
+    fn run(&mut self, t: SyncTask, handle: &Handle) {
+        let max_read_ts = self.max_read_ts.clone();
+        let f = self.pd_client.get_timestamp().then(move |res| {
+            match res {
+                Ok(ts) => max_read_ts.on_synced(t.region_id, t.id, ts),
+                // One-phase commit stays disabled in the region until it's the leader again.
+                Err(e) => warn!("[region {}] failed to sync max read ts: {:?}", t.region_id, e),
+            }
+            Ok(())
+        });
+        handle.spawn(f);
+    }
+}
+
+/// `MaxReadTsObserver` syncs the max read ts with pd when the local peer of a region becomes the
+/// leader. It's registered in the coprocessor host of the raftstore.
+#[derive(Clone)]
+pub struct MaxReadTsObserver {
+    max_read_ts: MaxReadTs,
+    // The observer is shared by the apply worker, so the scheduler has to be `Sync`.
+    scheduler: Arc<Mutex<FutureScheduler<SyncTask>>>,
+}
+
+impl MaxReadTsObserver {
+    fn update_role(&self, region_id: u64, role: Option<StateRole>) {
+        if role != Some(StateRole::Leader) {
+            return self.max_read_ts.on_not_leader(region_id);
+        }
+        let task = SyncTask {
+            region_id: region_id,
+            id: self.max_read_ts.on_leader(region_id),
+        };
+        if let Err(Stopped(t)) = self.scheduler.lock().unwrap().schedule(task) {
+            warn!("max read ts syncer is stopped, drop {}", t);
+        }
+    }
+}
+
+impl Coprocessor for MaxReadTsObserver {}
+
+impl RegionObserver for MaxReadTsObserver {
+    fn on_role_change(&self, ctx: &mut ObserverContext, role: StateRole) {
+        self.update_role(ctx.region().get_id(), Some(role));
+    }
+
+    fn on_region_changed(&self, ctx: &mut ObserverContext, event: RegionChangeEvent, _: StateRole) {
+        if event == RegionChangeEvent::Destroy {
+            self.update_role(ctx.region().get_id(), None);
+        }
+    }
+}
+
+/// `MaxReadTsSyncer` runs the worker which gets the timestamps for `MaxReadTsObserver`.
+pub struct MaxReadTsSyncer<C: PdClient + 'static> {
+    worker: FutureWorker<SyncTask>,
+    observer: MaxReadTsObserver,
+    runner: Option<SyncRunner<C>>,
+}
+
+impl<C: PdClient + 'static> MaxReadTsSyncer<C> {
+    pub fn new(pd_client: Arc<C>, max_read_ts: MaxReadTs) -> MaxReadTsSyncer<C> {
+        let worker = FutureWorker::new("max-read-ts-syncer");
+        let observer = MaxReadTsObserver {
+            max_read_ts: max_read_ts.clone(),
+            scheduler: Arc::new(Mutex::new(worker.scheduler())),
+        };
+        MaxReadTsSyncer {
+            worker: worker,
+            observer: observer,
+            runner: Some(SyncRunner {
+                pd_client: pd_client,
+                max_read_ts: max_read_ts,
+            }),
+        }
+    }
+
+    pub fn observer(&self) -> MaxReadTsObserver {
+        self.observer.clone()
+    }
+
+    pub fn start(&mut self) -> io::Result<()> {
+        match self.runner.take() {
+            Some(runner) => self.worker.start(runner),
+            None => Err(io::Error::new(io::ErrorKind::Other, "syncer is already started")),
+        }
+    }
+
+    pub fn stop(&mut self) -> Option<JoinHandle<()>> {
+        self.worker.stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_max_read_ts_guards() {
+        let max_read_ts = MaxReadTs::default();
+        max_read_ts.update(10);
+        max_read_ts.update(5);
+        assert_eq!(max_read_ts.get(), 10);
+
+        let keys = vec![b"k2".to_vec(), b"k4".to_vec()];
+        max_read_ts.guard(&keys, 20);
+        // The reads before the commit ts and of the other keys don't wait.
+        max_read_ts.read_keys(15, &[&b"k2"[..]]);
+        max_read_ts.read_keys(30, &[&b"k3"[..]]);
+        max_read_ts.read_range(30, b"k5", None);
+        max_read_ts.read_range(30, b"k0", Some(&b"k2"[..]));
+        assert_eq!(max_read_ts.get(), 30);
+        assert_eq!(max_read_ts.resolve(40), 19);
+
+        let (tx, rx) = mpsc::channel();
+        let reader = max_read_ts.clone();
+        let h = thread::spawn(move || {
+            reader.read_range(50, b"k3", Some(&b"k5"[..]));
+            tx.send(()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        max_read_ts.release(&keys);
+        rx.recv_timeout(Duration::from_secs(3)).unwrap();
+        h.join().unwrap();
+        assert_eq!(max_read_ts.resolve(60), 60);
+    }
+
+    #[test]
+    fn test_max_read_ts_sync() {
+        let max_read_ts = MaxReadTs::default();
+        assert!(max_read_ts.is_synced(1));
+        let id1 = max_read_ts.on_leader(1);
+        assert!(!max_read_ts.is_synced(1));
+        // A stale sync doesn't enable it.
+        let id2 = max_read_ts.on_leader(1);
+        max_read_ts.on_synced(1, id1, 100);
+        assert!(!max_read_ts.is_synced(1));
+        assert_eq!(max_read_ts.get(), 100);
+        max_read_ts.on_synced(1, id2, 200);
+        assert!(max_read_ts.is_synced(1));
+        assert_eq!(max_read_ts.get(), 200);
+
+        max_read_ts.on_leader(2);
+        max_read_ts.on_not_leader(2);
+        assert!(max_read_ts.is_synced(2));
+    }
+}
@@ -71,6 +71,13 @@ lazy_static! {
             &["priority"]
         ).unwrap();
 
+    pub static ref SCHED_LOCK_WAIT_COUNTER_VEC: CounterVec =
+        register_counter_vec!(
+            "tikv_scheduler_lock_wait_total",
+            "Total count of commands waiting for locks",
+            &["type"]
+        ).unwrap();
+
     pub static ref KV_COMMAND_KEYREAD_HISTOGRAM_VEC: HistogramVec =
         register_histogram_vec!(
             "tikv_scheduler_kv_command_key_read",
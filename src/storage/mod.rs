@@ -22,12 +22,17 @@ use std::u64;
 use kvproto::kvrpcpb::{LockInfo, CommandPri};
 use kvproto::errorpb;
 use self::metrics::*;
+use util::escape;
+use raftstore::store::LoadRecorder;
 
 pub mod engine;
 pub mod mvcc;
 pub mod txn;
 pub mod config;
 pub mod types;
+pub mod ttl;
+pub mod gc_filter;
+pub mod max_read_ts;
 mod metrics;
 
 pub use self::config::{Config, DEFAULT_DATA_DIR};
@@ -35,7 +40,9 @@ pub use self::engine::{Engine, Snapshot, TEMP_DIR, new_local_engine, Modify, Cur
                        Error as EngineError, ScanMode, Statistics, CFStatistics};
 pub use self::engine::raftkv::RaftKv;
 pub use self::txn::{SnapshotStore, StoreScanner, Scheduler, Msg};
-pub use self::types::{Key, Value, KvPair, MvccInfo, make_key};
+pub use self::types::{Key, Value, KvPair, MvccInfo, TxnStatus, RangeChecksum, make_key};
+pub use self::max_read_ts::MaxReadTs;
+use self::gc_filter::GcContext;
 pub type Callback<T> = Box<FnBox(Result<T>) + Send>;
 
 pub type CfName = &'static str;
@@ -43,9 +50,12 @@ pub const CF_DEFAULT: CfName = "default";
 pub const CF_LOCK: CfName = "lock";
 pub const CF_WRITE: CfName = "write";
 pub const CF_RAFT: CfName = "raft";
+// The cf dedicated to raw data, which transactions never read or write.
+pub const CF_RAW: CfName = "raw";
 // Cfs that should be very large generally.
-pub const LARGE_CFS: &'static [CfName] = &[CF_DEFAULT, CF_WRITE];
-pub const ALL_CFS: &'static [CfName] = &[CF_DEFAULT, CF_LOCK, CF_WRITE, CF_RAFT];
+pub const LARGE_CFS: &'static [CfName] = &[CF_DEFAULT, CF_WRITE, CF_RAW];
+pub const ALL_CFS: &'static [CfName] = &[CF_DEFAULT, CF_LOCK, CF_WRITE, CF_RAFT, CF_RAW];
+// Cfs of transactional data.
 pub const DATA_CFS: &'static [CfName] = &[CF_DEFAULT, CF_LOCK, CF_WRITE];
 
 // Short value max len must <= 255.
@@ -84,6 +94,10 @@ pub enum StorageCb {
     MvccInfoByKey(Callback<MvccInfo>),
     MvccInfoByStartTs(Callback<Option<(Key, MvccInfo)>>),
     Locks(Callback<Vec<LockInfo>>),
+    Ttl(Callback<u64>),
+    TxnStatus(Callback<TxnStatus>),
+    CompareAndSwap(Callback<(Option<Value>, bool)>),
+    Checksum(Callback<RangeChecksum>),
 }
 
 pub enum Command {
@@ -100,6 +114,9 @@ pub enum Command {
     Scan {
         ctx: Context,
         start_key: Key,
+        // The exclusive upper bound of a forward scan, or the inclusive lower bound of a
+        // reverse scan.
+        end_key: Option<Key>,
         limit: usize,
         start_ts: u64,
         options: Options,
@@ -111,6 +128,13 @@ pub enum Command {
         start_ts: u64,
         options: Options,
     },
+    AcquirePessimisticLock {
+        ctx: Context,
+        keys: Vec<Key>,
+        primary: Vec<u8>,
+        start_ts: u64,
+        options: Options,
+    },
     Commit {
         ctx: Context,
         keys: Vec<Key>,
@@ -127,7 +151,26 @@ pub enum Command {
         keys: Vec<Key>,
         start_ts: u64,
     },
+    TxnHeartBeat {
+        ctx: Context,
+        primary_key: Key,
+        start_ts: u64,
+        advise_ttl: u64,
+    },
+    CheckTxnStatus {
+        ctx: Context,
+        primary_key: Key,
+        lock_ts: u64,
+        current_ts: u64,
+    },
     ScanLock { ctx: Context, max_ts: u64 },
+    Checksum {
+        ctx: Context,
+        start_key: Key,
+        // The exclusive upper bound of the range, `None` means unbounded.
+        end_key: Option<Key>,
+        start_ts: u64,
+    },
     ResolveLock {
         ctx: Context,
         start_ts: u64,
@@ -142,12 +185,40 @@ pub enum Command {
         scan_key: Option<Key>,
         keys: Vec<Key>,
     },
-    RawGet { ctx: Context, key: Key },
+    RawGet { ctx: Context, cf: CfName, key: Key },
+    RawBatchGet {
+        ctx: Context,
+        cf: CfName,
+        keys: Vec<Key>,
+    },
     RawScan {
         ctx: Context,
+        cf: CfName,
         start_key: Key,
+        // The exclusive upper bound of the scan.
+        end_key: Option<Key>,
         limit: usize,
     },
+    RawCompareAndSwap {
+        ctx: Context,
+        cf: CfName,
+        key: Key,
+        // The expected current value, `None` means the key should not exist.
+        previous_value: Option<Value>,
+        // The value to store, already encoded with the expire time if TTL is enabled.
+        value: Value,
+    },
+    RawPut {
+        ctx: Context,
+        cf: CfName,
+        // The values are already encoded with the expire time if TTL is enabled.
+        pairs: Vec<(Key, Value)>,
+    },
+    RawDelete {
+        ctx: Context,
+        cf: CfName,
+        keys: Vec<Key>,
+    },
     DeleteRange {
         ctx: Context,
         start_key: Key,
@@ -186,6 +257,14 @@ impl Display for Command {
                        start_ts,
                        ctx)
             }
+            Command::AcquirePessimisticLock { ref ctx, ref keys, start_ts, ref options, .. } => {
+                write!(f,
+                       "kv::command::acquirepessimisticlock keys({}) @ {} {} | {:?}",
+                       keys.len(),
+                       start_ts,
+                       options.for_update_ts,
+                       ctx)
+            }
             Command::Commit { ref ctx, ref keys, lock_ts, commit_ts, .. } => {
                 write!(f,
                        "kv::command::commit {} {} -> {} | {:?}",
@@ -204,6 +283,30 @@ impl Display for Command {
                        start_ts,
                        ctx)
             }
+            Command::TxnHeartBeat { ref ctx, ref primary_key, start_ts, advise_ttl } => {
+                write!(f,
+                       "kv::command::txn_heart_beat {} @ {} ttl {} | {:?}",
+                       primary_key,
+                       start_ts,
+                       advise_ttl,
+                       ctx)
+            }
+            Command::CheckTxnStatus { ref ctx, ref primary_key, lock_ts, current_ts } => {
+                write!(f,
+                       "kv::command::check_txn_status {} @ {} curr({}) | {:?}",
+                       primary_key,
+                       lock_ts,
+                       current_ts,
+                       ctx)
+            }
+            Command::Checksum { ref ctx, ref start_key, ref end_key, start_ts } => {
+                write!(f,
+                       "kv::command::checksum [{}, {:?}) @ {} | {:?}",
+                       start_key,
+                       end_key,
+                       start_ts,
+                       ctx)
+            }
             Command::ScanLock { ref ctx, max_ts, .. } => {
                 write!(f, "kv::scan_lock {} | {:?}", max_ts, ctx)
             }
@@ -221,16 +324,34 @@ impl Display for Command {
                        safe_point,
                        ctx)
             }
-            Command::RawGet { ref ctx, ref key } => {
-                write!(f, "kv::command::rawget {:?} | {:?}", key, ctx)
+            Command::RawGet { ref ctx, cf, ref key } => {
+                write!(f, "kv::command::rawget {}:{:?} | {:?}", cf, key, ctx)
             }
-            Command::RawScan { ref ctx, ref start_key, limit } => {
+            Command::RawBatchGet { ref ctx, cf, ref keys } => {
                 write!(f,
-                       "kv::command::rawscan {:?} {} | {:?}",
+                       "kv::command::raw_batch_get {}:{} keys | {:?}",
+                       cf,
+                       keys.len(),
+                       ctx)
+            }
+            Command::RawScan { ref ctx, cf, ref start_key, ref end_key, limit } => {
+                write!(f,
+                       "kv::command::rawscan {}:{:?}-{:?} {} | {:?}",
+                       cf,
                        start_key,
+                       end_key,
                        limit,
                        ctx)
             }
+            Command::RawCompareAndSwap { ref ctx, cf, ref key, .. } => {
+                write!(f, "kv::command::raw_compare_and_swap {}:{:?} | {:?}", cf, key, ctx)
+            }
+            Command::RawPut { ref ctx, cf, ref pairs } => {
+                write!(f, "kv::command::raw_put {}:({} keys) | {:?}", cf, pairs.len(), ctx)
+            }
+            Command::RawDelete { ref ctx, cf, ref keys } => {
+                write!(f, "kv::command::raw_delete {}:({} keys) | {:?}", cf, keys.len(), ctx)
+            }
             Command::DeleteRange { ref ctx, ref start_key, ref end_key } => {
                 write!(f,
                        "kv::command::delete range [{:?}, {:?}) | {:?}",
@@ -266,7 +387,9 @@ impl Command {
             Command::BatchGet { .. } |
             Command::Scan { .. } |
             Command::ScanLock { .. } |
+            Command::Checksum { .. } |
             Command::RawGet { .. } |
+            Command::RawBatchGet { .. } |
             Command::RawScan { .. } |
             // DeleteRange only called by DDL bg thread after table is dropped and
             // must guarantee that there is no other read or write on these keys, so
@@ -281,6 +404,17 @@ impl Command {
         }
     }
 
+    /// Returns whether the command can be served by a follower which has applied up to the read
+    /// index of the leader.
+    pub fn allow_follower_read(&self) -> bool {
+        match *self {
+            Command::Get { .. } |
+            Command::BatchGet { .. } |
+            Command::Scan { .. } => true,
+            _ => false,
+        }
+    }
+
     pub fn priority(&self) -> CommandPri {
         self.get_context().get_priority()
     }
@@ -303,14 +437,22 @@ impl Command {
             Command::BatchGet { .. } => "batch_get",
             Command::Scan { .. } => "scan",
             Command::Prewrite { .. } => "prewrite",
+            Command::AcquirePessimisticLock { .. } => "acquire_pessimistic_lock",
             Command::Commit { .. } => "commit",
             Command::Cleanup { .. } => "cleanup",
             Command::Rollback { .. } => "rollback",
+            Command::TxnHeartBeat { .. } => "txn_heart_beat",
+            Command::CheckTxnStatus { .. } => "check_txn_status",
             Command::ScanLock { .. } => "scan_lock",
+            Command::Checksum { .. } => "checksum",
             Command::ResolveLock { .. } => "resolve_lock",
             Command::Gc { .. } => CMD_TAG_GC,
             Command::RawGet { .. } => "raw_get",
+            Command::RawBatchGet { .. } => "raw_batch_get",
             Command::RawScan { .. } => "raw_scan",
+            Command::RawCompareAndSwap { .. } => "raw_compare_and_swap",
+            Command::RawPut { .. } => "raw_put",
+            Command::RawDelete { .. } => "raw_delete",
             Command::DeleteRange { .. } => "delete_range",
             Command::Pause { .. } => "pause",
             Command::MvccByKey { .. } => "key_mvcc",
@@ -324,15 +466,23 @@ impl Command {
             Command::BatchGet { start_ts, .. } |
             Command::Scan { start_ts, .. } |
             Command::Prewrite { start_ts, .. } |
+            Command::AcquirePessimisticLock { start_ts, .. } |
             Command::Cleanup { start_ts, .. } |
             Command::Rollback { start_ts, .. } |
+            Command::TxnHeartBeat { start_ts, .. } |
+            Command::Checksum { start_ts, .. } |
             Command::ResolveLock { start_ts, .. } |
             Command::MvccByStartTs { start_ts, .. } => start_ts,
-            Command::Commit { lock_ts, .. } => lock_ts,
+            Command::Commit { lock_ts, .. } |
+            Command::CheckTxnStatus { lock_ts, .. } => lock_ts,
             Command::ScanLock { max_ts, .. } => max_ts,
             Command::Gc { safe_point, .. } => safe_point,
             Command::RawGet { .. } |
+            Command::RawBatchGet { .. } |
             Command::RawScan { .. } |
+            Command::RawCompareAndSwap { .. } |
+            Command::RawPut { .. } |
+            Command::RawDelete { .. } |
             Command::DeleteRange { .. } |
             Command::Pause { .. } |
             Command::MvccByKey { .. } => 0,
@@ -345,14 +495,22 @@ impl Command {
             Command::BatchGet { ref ctx, .. } |
             Command::Scan { ref ctx, .. } |
             Command::Prewrite { ref ctx, .. } |
+            Command::AcquirePessimisticLock { ref ctx, .. } |
             Command::Commit { ref ctx, .. } |
             Command::Cleanup { ref ctx, .. } |
             Command::Rollback { ref ctx, .. } |
+            Command::TxnHeartBeat { ref ctx, .. } |
+            Command::CheckTxnStatus { ref ctx, .. } |
             Command::ScanLock { ref ctx, .. } |
+            Command::Checksum { ref ctx, .. } |
             Command::ResolveLock { ref ctx, .. } |
             Command::Gc { ref ctx, .. } |
             Command::RawGet { ref ctx, .. } |
+            Command::RawBatchGet { ref ctx, .. } |
             Command::RawScan { ref ctx, .. } |
+            Command::RawCompareAndSwap { ref ctx, .. } |
+            Command::RawPut { ref ctx, .. } |
+            Command::RawDelete { ref ctx, .. } |
             Command::DeleteRange { ref ctx, .. } |
             Command::Pause { ref ctx, .. } |
             Command::MvccByKey { ref ctx, .. } |
@@ -366,14 +524,22 @@ impl Command {
             Command::BatchGet { ref mut ctx, .. } |
             Command::Scan { ref mut ctx, .. } |
             Command::Prewrite { ref mut ctx, .. } |
+            Command::AcquirePessimisticLock { ref mut ctx, .. } |
             Command::Commit { ref mut ctx, .. } |
             Command::Cleanup { ref mut ctx, .. } |
             Command::Rollback { ref mut ctx, .. } |
+            Command::TxnHeartBeat { ref mut ctx, .. } |
+            Command::CheckTxnStatus { ref mut ctx, .. } |
             Command::ScanLock { ref mut ctx, .. } |
+            Command::Checksum { ref mut ctx, .. } |
             Command::ResolveLock { ref mut ctx, .. } |
             Command::Gc { ref mut ctx, .. } |
             Command::RawGet { ref mut ctx, .. } |
+            Command::RawBatchGet { ref mut ctx, .. } |
             Command::RawScan { ref mut ctx, .. } |
+            Command::RawCompareAndSwap { ref mut ctx, .. } |
+            Command::RawPut { ref mut ctx, .. } |
+            Command::RawDelete { ref mut ctx, .. } |
             Command::DeleteRange { ref mut ctx, .. } |
             Command::Pause { ref mut ctx, .. } |
             Command::MvccByKey { ref mut ctx, .. } |
@@ -389,6 +555,21 @@ pub struct Options {
     pub lock_ttl: u64,
     pub skip_constraint_check: bool,
     pub key_only: bool,
+    pub reverse_scan: bool,
+    // Only used by pessimistic transactions.
+    pub for_update_ts: u64,
+    // Whether each mutation of a prewrite holds a pessimistic lock. It is empty for
+    // optimistic transactions.
+    pub is_pessimistic_lock: Vec<bool>,
+    // How long in milliseconds a pessimistic lock request waits for conflicting locks
+    // to be released, 0 means returning `KeyIsLocked` immediately.
+    pub wait_timeout: u64,
+    // Commits the transaction in prewrite at `commit_ts` without writing locks. It can only be
+    // used when all the mutations of the transaction are in one region. A prewrite without
+    // errors means the transaction is committed, it fails with `CommitTsTooOld` if `commit_ts`
+    // isn't larger than the max read ts, then the transaction can be prewritten in two phases.
+    pub try_one_pc: bool,
+    pub commit_ts: u64,
 }
 
 impl Options {
@@ -397,6 +578,12 @@ impl Options {
             lock_ttl: lock_ttl,
             skip_constraint_check: skip_constraint_check,
             key_only: key_only,
+            reverse_scan: false,
+            for_update_ts: 0,
+            is_pessimistic_lock: vec![],
+            wait_timeout: 0,
+            try_one_pc: false,
+            commit_ts: 0,
         }
     }
 }
@@ -413,6 +600,11 @@ pub struct Storage {
 
     // Storage configurations.
     gc_ratio_threshold: f64,
+    enable_ttl: bool,
+    enable_follower_read: bool,
+    gc_context: Option<GcContext>,
+    load_recorder: Option<LoadRecorder>,
+    max_read_ts: MaxReadTs,
 }
 
 impl Storage {
@@ -429,6 +621,11 @@ impl Storage {
                 receiver: Some(rx),
             })),
             gc_ratio_threshold: config.gc_ratio_threshold,
+            enable_ttl: config.enable_ttl,
+            enable_follower_read: config.enable_follower_read,
+            gc_context: None,
+            load_recorder: None,
+            max_read_ts: MaxReadTs::default(),
         })
     }
 
@@ -437,6 +634,19 @@ impl Storage {
         Storage::from_engine(engine, config)
     }
 
+    /// Makes GC commands push their safe points to the compaction filters of the engine, and
+    /// compact the regions with enough old versions, instead of deleting the versions through
+    /// the engine. It must be called before `start`.
+    pub fn set_gc_context(&mut self, ctx: GcContext) {
+        self.gc_context = Some(ctx);
+    }
+
+    /// Records the keys accessed by the requests in `recorder`, so that the hot regions are split
+    /// by their load. The clones made before it's called don't record.
+    pub fn set_load_recorder(&mut self, recorder: LoadRecorder) {
+        self.load_recorder = Some(recorder);
+    }
+
     pub fn start(&mut self, config: &Config) -> Result<()> {
         let mut handle = self.handle.lock().unwrap();
         if handle.handle.is_some() {
@@ -449,13 +659,21 @@ impl Storage {
         let sched_concurrency = config.scheduler_concurrency;
         let sched_worker_pool_size = config.scheduler_worker_pool_size;
         let sched_too_busy_threshold = config.scheduler_too_busy_threshold;
+        let enable_ttl = self.enable_ttl;
+        let enable_follower_read = self.enable_follower_read;
+        let gc_context = self.gc_context.clone();
+        let max_read_ts = self.max_read_ts.clone();
         let ch = self.sendch.clone();
         let h = try!(builder.spawn(move || {
             let mut sched = Scheduler::new(engine,
                                            ch,
                                            sched_concurrency,
                                            sched_worker_pool_size,
-                                           sched_too_busy_threshold);
+                                           sched_too_busy_threshold,
+                                           enable_ttl,
+                                           enable_follower_read,
+                                           gc_context,
+                                           max_read_ts);
             if let Err(e) = sched.run(rx) {
                 panic!("scheduler run err:{:?}", e);
             }
@@ -490,7 +708,81 @@ impl Storage {
         self.engine.clone()
     }
 
+    /// The max read ts shared by the storage, the readers outside of the storage, like the
+    /// coprocessor and cdc, should update it too.
+    pub fn get_max_read_ts(&self) -> MaxReadTs {
+        self.max_read_ts.clone()
+    }
+
+    fn record_load<'a, I>(&self, ctx: &Context, keys: I)
+        where I: IntoIterator<Item = &'a [u8]>
+    {
+        if let Some(ref recorder) = self.load_recorder {
+            recorder.record_keys(ctx.get_region_id(), keys);
+        }
+    }
+
+    fn record_command_load(&self, cmd: &Command) {
+        let ctx = cmd.get_context();
+        match *cmd {
+            Command::Get { ref key, .. } |
+            Command::Cleanup { ref key, .. } |
+            Command::RawGet { ref key, .. } |
+            Command::RawCompareAndSwap { ref key, .. } |
+            Command::Scan { start_key: ref key, .. } |
+            Command::RawScan { start_key: ref key, .. } => {
+                self.record_load(ctx, Some(key.encoded().as_slice()))
+            }
+            Command::BatchGet { ref keys, .. } |
+            Command::AcquirePessimisticLock { ref keys, .. } |
+            Command::Commit { ref keys, .. } |
+            Command::Rollback { ref keys, .. } |
+            Command::RawBatchGet { ref keys, .. } |
+            Command::RawDelete { ref keys, .. } => {
+                self.record_load(ctx, keys.iter().map(|k| k.encoded().as_slice()))
+            }
+            Command::RawPut { ref pairs, .. } => {
+                self.record_load(ctx, pairs.iter().map(|&(ref k, _)| k.encoded().as_slice()))
+            }
+            Command::Prewrite { ref mutations, .. } => {
+                self.record_load(ctx, mutations.iter().map(|m| m.key().encoded().as_slice()))
+            }
+            _ => {}
+        }
+    }
+
     fn send(&self, cmd: Command, cb: StorageCb) -> Result<()> {
+        self.record_command_load(&cmd);
+        // Updates the max read ts before the snapshot of the read is taken. It may wait for the
+        // one-phase commits of the keys being applied, which are released by the scheduler.
+        match cmd {
+            Command::Get { start_ts, ref key, .. } => {
+                self.max_read_ts.read_keys(start_ts, &[key.encoded().as_slice()])
+            }
+            Command::BatchGet { start_ts, ref keys, .. } => {
+                let keys: Vec<_> = keys.iter().map(|k| k.encoded().as_slice()).collect();
+                self.max_read_ts.read_keys(start_ts, &keys)
+            }
+            Command::Scan { start_ts, ref start_key, ref end_key, ref options, .. } => {
+                if options.reverse_scan {
+                    // The start key is the inclusive upper bound of a reverse scan.
+                    let mut upper = start_key.encoded().clone();
+                    upper.push(0);
+                    let lower = end_key.as_ref().map_or(&[][..], |k| k.encoded().as_slice());
+                    self.max_read_ts.read_range(start_ts, lower, Some(upper.as_slice()))
+                } else {
+                    self.max_read_ts.read_range(start_ts,
+                                                start_key.encoded(),
+                                                end_key.as_ref().map(|k| k.encoded().as_slice()))
+                }
+            }
+            Command::Checksum { start_ts, ref start_key, ref end_key, .. } => {
+                self.max_read_ts.read_range(start_ts,
+                                            start_key.encoded(),
+                                            end_key.as_ref().map(|k| k.encoded().as_slice()))
+            }
+            _ => {}
+        }
         box_try!(self.sendch.try_send(Msg::RawCmd { cmd: cmd, cb: cb }));
         Ok(())
     }
@@ -529,9 +821,13 @@ impl Storage {
         Ok(())
     }
 
+    /// Scans at most `limit` keys in [`start_key`, `end_key`). If `options.reverse_scan` is set,
+    /// keys are scanned in descending order from `start_key`(exclusive) to `end_key`(inclusive).
+    /// The scan is unbounded if `end_key` is `None`.
     pub fn async_scan(&self,
                       ctx: Context,
                       start_key: Key,
+                      end_key: Option<Key>,
                       limit: usize,
                       start_ts: u64,
                       options: Options,
@@ -540,6 +836,7 @@ impl Storage {
         let cmd = Command::Scan {
             ctx: ctx,
             start_key: start_key,
+            end_key: end_key,
             limit: limit,
             start_ts: start_ts,
             options: options,
@@ -567,6 +864,19 @@ impl Storage {
                           options: Options,
                           callback: Callback<Vec<Result<()>>>)
                           -> Result<()> {
+        // The reads served by followers don't update the max read ts of the leader.
+        if options.try_one_pc && self.enable_follower_read {
+            return Err(Error::InvalidArgument("one-phase commit can't be used with follower read"
+                .to_owned()));
+        }
+        // An empty `is_pessimistic_lock` means an optimistic transaction.
+        if !options.is_pessimistic_lock.is_empty() &&
+           options.is_pessimistic_lock.len() != mutations.len() {
+            let msg = format!("{} is_pessimistic_lock flags for {} mutations",
+                              options.is_pessimistic_lock.len(),
+                              mutations.len());
+            return Err(Error::InvalidArgument(msg));
+        }
         let cmd = Command::Prewrite {
             ctx: ctx,
             mutations: mutations,
@@ -580,6 +890,27 @@ impl Storage {
         Ok(())
     }
 
+    pub fn async_acquire_pessimistic_lock(&self,
+                                          ctx: Context,
+                                          keys: Vec<Key>,
+                                          primary: Vec<u8>,
+                                          start_ts: u64,
+                                          options: Options,
+                                          callback: Callback<Vec<Result<()>>>)
+                                          -> Result<()> {
+        let cmd = Command::AcquirePessimisticLock {
+            ctx: ctx,
+            keys: keys,
+            primary: primary,
+            start_ts: start_ts,
+            options: options,
+        };
+        let tag = cmd.tag();
+        try!(self.send(cmd, StorageCb::Booleans(callback)));
+        KV_COMMAND_COUNTER_VEC.with_label_values(&[tag]).inc();
+        Ok(())
+    }
+
     pub fn async_commit(&self,
                         ctx: Context,
                         keys: Vec<Key>,
@@ -646,6 +977,48 @@ impl Storage {
         Ok(())
     }
 
+    /// Extends the TTL of the primary lock of transaction `start_ts` to `advise_ttl`, and
+    /// returns the TTL of the lock after updating.
+    pub fn async_txn_heart_beat(&self,
+                                ctx: Context,
+                                primary_key: Key,
+                                start_ts: u64,
+                                advise_ttl: u64,
+                                callback: Callback<u64>)
+                                -> Result<()> {
+        let cmd = Command::TxnHeartBeat {
+            ctx: ctx,
+            primary_key: primary_key,
+            start_ts: start_ts,
+            advise_ttl: advise_ttl,
+        };
+        let tag = cmd.tag();
+        try!(self.send(cmd, StorageCb::Ttl(callback)));
+        KV_COMMAND_COUNTER_VEC.with_label_values(&[tag]).inc();
+        Ok(())
+    }
+
+    /// Checks the status of transaction `lock_ts` on its primary key, rolls back the primary
+    /// lock if it has expired at `current_ts`.
+    pub fn async_check_txn_status(&self,
+                                  ctx: Context,
+                                  primary_key: Key,
+                                  lock_ts: u64,
+                                  current_ts: u64,
+                                  callback: Callback<TxnStatus>)
+                                  -> Result<()> {
+        let cmd = Command::CheckTxnStatus {
+            ctx: ctx,
+            primary_key: primary_key,
+            lock_ts: lock_ts,
+            current_ts: current_ts,
+        };
+        let tag = cmd.tag();
+        try!(self.send(cmd, StorageCb::TxnStatus(callback)));
+        KV_COMMAND_COUNTER_VEC.with_label_values(&[tag]).inc();
+        Ok(())
+    }
+
     pub fn async_rollback(&self,
                           ctx: Context,
                           keys: Vec<Key>,
@@ -678,6 +1051,27 @@ impl Storage {
         Ok(())
     }
 
+    /// Computes the checksum of the range [`start_key`, `end_key`) in the snapshot of
+    /// `start_ts`. It fails if any key in the range is locked.
+    pub fn async_checksum(&self,
+                          ctx: Context,
+                          start_key: Key,
+                          end_key: Option<Key>,
+                          start_ts: u64,
+                          callback: Callback<RangeChecksum>)
+                          -> Result<()> {
+        let cmd = Command::Checksum {
+            ctx: ctx,
+            start_key: start_key,
+            end_key: end_key,
+            start_ts: start_ts,
+        };
+        let tag = cmd.tag();
+        try!(self.send(cmd, StorageCb::Checksum(callback)));
+        KV_COMMAND_COUNTER_VEC.with_label_values(&[tag]).inc();
+        Ok(())
+    }
+
     pub fn async_resolve_lock(&self,
                               ctx: Context,
                               start_ts: u64,
@@ -711,13 +1105,26 @@ impl Storage {
         Ok(())
     }
 
+    fn rawkv_cf(&self, cf: &str) -> Result<CfName> {
+        // The gc compaction filter of `CF_DEFAULT` would take raw values for garbage.
+        if (cf.is_empty() || cf == CF_DEFAULT) && self.gc_context.is_none() {
+            return Ok(CF_DEFAULT);
+        }
+        if cf == CF_RAW {
+            return Ok(CF_RAW);
+        }
+        Err(Error::InvalidCf(cf.to_owned()))
+    }
+
     pub fn async_raw_get(&self,
                          ctx: Context,
+                         cf: &str,
                          key: Vec<u8>,
                          callback: Callback<Option<Vec<u8>>>)
                          -> Result<()> {
         let cmd = Command::RawGet {
             ctx: ctx,
+            cf: try!(self.rawkv_cf(cf)),
             key: Key::from_encoded(key),
         };
         try!(self.send(cmd, StorageCb::SingleValue(callback)));
@@ -725,46 +1132,182 @@ impl Storage {
         Ok(())
     }
 
+    /// Gets the values of `keys` from a single snapshot, keys that don't exist are skipped.
+    pub fn async_raw_batch_get(&self,
+                               ctx: Context,
+                               cf: &str,
+                               keys: Vec<Vec<u8>>,
+                               callback: Callback<Vec<Result<KvPair>>>)
+                               -> Result<()> {
+        let cmd = Command::RawBatchGet {
+            ctx: ctx,
+            cf: try!(self.rawkv_cf(cf)),
+            keys: keys.into_iter().map(Key::from_encoded).collect(),
+        };
+        try!(self.send(cmd, StorageCb::KvPairs(callback)));
+        RAWKV_COMMAND_COUNTER_VEC.with_label_values(&["batch_get"]).inc();
+        Ok(())
+    }
+
+    /// Puts a raw key, the value expires after `ttl` seconds if `ttl` isn't 0. A TTL can only be
+    /// set in `CF_RAW` if `enable_ttl` is configured.
     pub fn async_raw_put(&self,
                          ctx: Context,
+                         cf: &str,
                          key: Vec<u8>,
                          value: Vec<u8>,
+                         ttl: u64,
                          callback: Callback<()>)
                          -> Result<()> {
-        try!(self.engine
-            .async_write(&ctx,
-                         vec![Modify::Put(CF_DEFAULT, Key::from_encoded(key), value)],
-                         box |(_, res): (_, engine::Result<_>)| {
-                             callback(res.map_err(Error::from))
-                         }));
+        let cf = try!(self.rawkv_cf(cf));
+        let cmd = Command::RawPut {
+            ctx: ctx,
+            cf: cf,
+            pairs: vec![(Key::from_encoded(key), try!(self.raw_value(cf, value, ttl)))],
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
         RAWKV_COMMAND_COUNTER_VEC.with_label_values(&["put"]).inc();
         Ok(())
     }
 
+    /// Writes all `pairs` in one batch with the same `ttl`. All keys should belong to the region
+    /// of `ctx`, otherwise the whole batch fails with a key not in region error.
+    pub fn async_raw_batch_put(&self,
+                               ctx: Context,
+                               cf: &str,
+                               pairs: Vec<KvPair>,
+                               ttl: u64,
+                               callback: Callback<()>)
+                               -> Result<()> {
+        let cf = try!(self.rawkv_cf(cf));
+        let mut encoded_pairs = Vec::with_capacity(pairs.len());
+        for (k, v) in pairs {
+            encoded_pairs.push((Key::from_encoded(k), try!(self.raw_value(cf, v, ttl))));
+        }
+        let cmd = Command::RawPut {
+            ctx: ctx,
+            cf: cf,
+            pairs: encoded_pairs,
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        RAWKV_COMMAND_COUNTER_VEC.with_label_values(&["batch_put"]).inc();
+        Ok(())
+    }
+
     pub fn async_raw_delete(&self,
                             ctx: Context,
+                            cf: &str,
                             key: Vec<u8>,
                             callback: Callback<()>)
                             -> Result<()> {
+        let cmd = Command::RawDelete {
+            ctx: ctx,
+            cf: try!(self.rawkv_cf(cf)),
+            keys: vec![Key::from_encoded(key)],
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        RAWKV_COMMAND_COUNTER_VEC.with_label_values(&["delete"]).inc();
+        Ok(())
+    }
+
+    /// Deletes all `keys` in one batch. All keys should belong to the region of `ctx`, otherwise
+    /// the whole batch fails with a key not in region error.
+    pub fn async_raw_batch_delete(&self,
+                                  ctx: Context,
+                                  cf: &str,
+                                  keys: Vec<Vec<u8>>,
+                                  callback: Callback<()>)
+                                  -> Result<()> {
+        let cmd = Command::RawDelete {
+            ctx: ctx,
+            cf: try!(self.rawkv_cf(cf)),
+            keys: keys.into_iter().map(Key::from_encoded).collect(),
+        };
+        try!(self.send(cmd, StorageCb::Boolean(callback)));
+        RAWKV_COMMAND_COUNTER_VEC.with_label_values(&["batch_delete"]).inc();
+        Ok(())
+    }
+
+    /// Sets `key` to `value` if its current value equals `previous_value`, where `None` means the
+    /// key doesn't exist. The callback gets the current value before the swap and whether the
+    /// swap succeeded. Raw writes on the same key are serialized by the scheduler latches, except
+    /// raw delete range, which shouldn't be mixed with compare-and-swaps in its range.
+    pub fn async_raw_compare_and_swap(&self,
+                                      ctx: Context,
+                                      cf: &str,
+                                      key: Vec<u8>,
+                                      previous_value: Option<Vec<u8>>,
+                                      value: Vec<u8>,
+                                      ttl: u64,
+                                      callback: Callback<(Option<Value>, bool)>)
+                                      -> Result<()> {
+        let cf = try!(self.rawkv_cf(cf));
+        let cmd = Command::RawCompareAndSwap {
+            ctx: ctx,
+            cf: cf,
+            key: Key::from_encoded(key),
+            previous_value: previous_value,
+            value: try!(self.raw_value(cf, value, ttl)),
+        };
+        try!(self.send(cmd, StorageCb::CompareAndSwap(callback)));
+        RAWKV_COMMAND_COUNTER_VEC.with_label_values(&["compare_and_swap"]).inc();
+        Ok(())
+    }
+
+    /// Encodes a raw value to store, the expire time is appended if TTL is enabled for the cf.
+    fn raw_value(&self, cf: CfName, value: Vec<u8>, ttl: u64) -> Result<Vec<u8>> {
+        if !ttl::is_ttl_enabled(self.enable_ttl, cf) {
+            if ttl != 0 {
+                return Err(box_err!("can't set ttl {} since ttl is not enabled in cf {}",
+                                    ttl,
+                                    cf));
+            }
+            return Ok(value);
+        }
+        Ok(ttl::append_expire_ts(value, ttl::convert_to_expire_ts(ttl)))
+    }
+
+    /// Deletes all raw keys in [`start_key`, `end_key`) of the cf.
+    pub fn async_raw_delete_range(&self,
+                                  ctx: Context,
+                                  cf: &str,
+                                  start_key: Vec<u8>,
+                                  end_key: Vec<u8>,
+                                  callback: Callback<()>)
+                                  -> Result<()> {
+        let cf = try!(self.rawkv_cf(cf));
+        if start_key >= end_key {
+            return Err(box_err!("invalid delete range [{}, {})",
+                                escape(&start_key),
+                                escape(&end_key)));
+        }
         try!(self.engine
             .async_write(&ctx,
-                         vec![Modify::Delete(CF_DEFAULT, Key::from_encoded(key))],
+                         vec![Modify::DeleteRange(cf,
+                                                  Key::from_encoded(start_key),
+                                                  Key::from_encoded(end_key))],
                          box |(_, res): (_, engine::Result<_>)| {
                              callback(res.map_err(Error::from))
                          }));
-        RAWKV_COMMAND_COUNTER_VEC.with_label_values(&["delete"]).inc();
+        RAWKV_COMMAND_COUNTER_VEC.with_label_values(&["delete_range"]).inc();
         Ok(())
     }
 
+    /// Scans at most `limit` raw keys in [`key`, `end_key`), the scan is unbounded if `end_key`
+    /// is `None`.
     pub fn async_raw_scan(&self,
                           ctx: Context,
+                          cf: &str,
                           key: Vec<u8>,
+                          end_key: Option<Vec<u8>>,
                           limit: usize,
                           callback: Callback<Vec<Result<KvPair>>>)
                           -> Result<()> {
         let cmd = Command::RawScan {
             ctx: ctx,
+            cf: try!(self.rawkv_cf(cf)),
             start_key: Key::from_encoded(key),
+            end_key: end_key.map(Key::from_encoded),
             limit: limit,
         };
         try!(self.send(cmd, StorageCb::KvPairs(callback)));
@@ -810,6 +1353,11 @@ impl Clone for Storage {
             sendch: self.sendch.clone(),
             handle: self.handle.clone(),
             gc_ratio_threshold: self.gc_ratio_threshold,
+            enable_ttl: self.enable_ttl,
+            enable_follower_read: self.enable_follower_read,
+            gc_context: self.gc_context.clone(),
+            load_recorder: self.load_recorder.clone(),
+            max_read_ts: self.max_read_ts.clone(),
         }
     }
 }
@@ -848,11 +1396,23 @@ quick_error! {
         SchedTooBusy {
             description("scheduler is too busy")
         }
+        InvalidCf(cf_name: String) {
+            description("invalid cf name")
+            display("invalid cf name: {}", cf_name)
+        }
+        InvalidArgument(msg: String) {
+            description("invalid argument")
+            display("invalid argument: {}", msg)
+        }
     }
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// Resolves the cf of a raw request, an empty name means `CF_DEFAULT`. Raw data can be kept
+/// apart from transactional data in `CF_RAW`. `CF_LOCK` and `CF_WRITE` can't be used, they are
+/// parsed as locks and writes by transactions and GC, and `CF_RAFT` is not replicated by region
+/// snapshots.
 pub fn get_tag_from_header(header: &errorpb::Error) -> &'static str {
     if header.has_not_leader() {
         "not_leader"
@@ -873,7 +1433,10 @@ pub fn get_tag_from_header(header: &errorpb::Error) -> &'static str {
 mod tests {
     use super::*;
     use std::sync::mpsc::{channel, Sender};
+    use std::time::Duration;
     use kvproto::kvrpcpb::Context;
+    use storage::txn::Error as TxnError;
+    use storage::mvcc::Error as MvccError;
 
     fn expect_get_none(done: Sender<i32>, id: i32) -> Callback<Option<Value>> {
         Box::new(move |x: Result<Option<Value>>| {
@@ -903,6 +1466,64 @@ mod tests {
         })
     }
 
+    fn expect_multi_fail(done: Sender<i32>, id: i32) -> Callback<Vec<Result<()>>> {
+        Box::new(move |x: Result<Vec<Result<()>>>| {
+            assert!(x.unwrap().into_iter().any(|r| r.is_err()));
+            done.send(id).unwrap();
+        })
+    }
+
+    fn acquire_pessimistic_lock(storage: &Storage,
+                                key: &[u8],
+                                start_ts: u64,
+                                for_update_ts: u64,
+                                wait_timeout: u64,
+                                cb: Callback<Vec<Result<()>>>) {
+        let mut options = Options::default();
+        options.for_update_ts = for_update_ts;
+        options.wait_timeout = wait_timeout;
+        storage.async_acquire_pessimistic_lock(Context::new(),
+                                            vec![make_key(key)],
+                                            key.to_vec(),
+                                            start_ts,
+                                            options,
+                                            cb)
+            .unwrap();
+    }
+
+    fn expect_ttl(done: Sender<i32>, ttl: u64, id: i32) -> Callback<u64> {
+        Box::new(move |x: Result<u64>| {
+            assert_eq!(x.unwrap(), ttl);
+            done.send(id).unwrap();
+        })
+    }
+
+    fn expect_txn_status(done: Sender<i32>, status: TxnStatus, id: i32) -> Callback<TxnStatus> {
+        Box::new(move |x: Result<TxnStatus>| {
+            assert_eq!(x.unwrap(), status);
+            done.send(id).unwrap();
+        })
+    }
+
+    fn expect_multi_ok(done: Sender<i32>, id: i32) -> Callback<Vec<Result<()>>> {
+        Box::new(move |x: Result<Vec<Result<()>>>| {
+            assert!(x.unwrap().into_iter().all(|r| r.is_ok()));
+            done.send(id).unwrap();
+        })
+    }
+
+    fn expect_deadlock(done: Sender<i32>, id: i32) -> Callback<Vec<Result<()>>> {
+        Box::new(move |x: Result<Vec<Result<()>>>| {
+            let res = x.unwrap();
+            assert_eq!(res.len(), 1);
+            match res[0] {
+                Err(Error::Txn(TxnError::Mvcc(MvccError::Deadlock { .. }))) => {}
+                _ => panic!("expect deadlock"),
+            }
+            done.send(id).unwrap();
+        })
+    }
+
     fn expect_too_busy<T>(done: Sender<i32>, id: i32) -> Callback<T> {
         Box::new(move |x: Result<T>| {
             assert!(x.is_err());
@@ -1034,6 +1655,7 @@ mod tests {
         rx.recv().unwrap();
         storage.async_scan(Context::new(),
                         make_key(b"\x00"),
+                        None,
                         1000,
                         5,
                         Options::default(),
@@ -1089,6 +1711,66 @@ mod tests {
         storage.stop().unwrap();
     }
 
+    #[test]
+    fn test_reverse_scan() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        let keys = vec!["a", "b", "c", "d"];
+        storage.async_prewrite(Context::new(),
+                            keys.iter()
+                                .map(|k| {
+                                    Mutation::Put((make_key(k.as_bytes()), k.as_bytes().to_vec()))
+                                })
+                                .collect(),
+                            b"a".to_vec(),
+                            1,
+                            Options::default(),
+                            expect_ok(tx.clone(), 0))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_commit(Context::new(),
+                          keys.iter().map(|k| make_key(k.as_bytes())).collect(),
+                          1,
+                          2,
+                          expect_ok(tx.clone(), 1))
+            .unwrap();
+        rx.recv().unwrap();
+        // A pending lock below all the keys.
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"0"), b"0".to_vec()))],
+                            b"0".to_vec(),
+                            3,
+                            Options::default(),
+                            expect_ok(tx.clone(), 2))
+            .unwrap();
+        rx.recv().unwrap();
+
+        let mut options = Options::default();
+        options.reverse_scan = true;
+        let pair = |k: &str| Some((k.as_bytes().to_vec(), k.as_bytes().to_vec()));
+        let cases = vec![
+            ("d", Some("b"), 10, vec![pair("c"), pair("b")]),
+            ("z", None, 2, vec![pair("d"), pair("c")]),
+            // The lock below the end key isn't checked.
+            ("b", Some("a"), 10, vec![pair("a")]),
+            ("b", None, 10, vec![pair("a"), None]),
+        ];
+        for (i, (start_key, end_key, limit, expect)) in cases.into_iter().enumerate() {
+            storage.async_scan(Context::new(),
+                            make_key(start_key.as_bytes()),
+                            end_key.map(|k| make_key(k.as_bytes())),
+                            limit,
+                            5,
+                            options.clone(),
+                            expect_scan(tx.clone(), expect, 3 + i as i32))
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        storage.stop().unwrap();
+    }
+
     #[test]
     fn test_txn() {
         let config = Config::default();
@@ -1148,6 +1830,175 @@ mod tests {
         storage.stop().unwrap();
     }
 
+    #[test]
+    fn test_pessimistic_lock() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        let mut options = Options::default();
+        options.for_update_ts = 100;
+        storage.async_acquire_pessimistic_lock(Context::new(),
+                                            vec![make_key(b"x")],
+                                            b"x".to_vec(),
+                                            100,
+                                            options.clone(),
+                                            expect_ok(tx.clone(), 0))
+            .unwrap();
+        rx.recv().unwrap();
+        // The key is locked by another pessimistic transaction.
+        storage.async_acquire_pessimistic_lock(Context::new(),
+                                            vec![make_key(b"x")],
+                                            b"x".to_vec(),
+                                            101,
+                                            options.clone(),
+                                            expect_multi_fail(tx.clone(), 1))
+            .unwrap();
+        rx.recv().unwrap();
+        // Every mutation needs a flag.
+        options.is_pessimistic_lock = vec![true];
+        match storage.async_prewrite(Context::new(),
+                                     vec![Mutation::Put((make_key(b"x"), b"100".to_vec())),
+                                          Mutation::Put((make_key(b"y"), b"100".to_vec()))],
+                                     b"x".to_vec(),
+                                     100,
+                                     options.clone(),
+                                     expect_ok(tx.clone(), 2)) {
+            Err(Error::InvalidArgument(_)) => {}
+            res => panic!("expect invalid argument, got {:?}", res),
+        }
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
+                            b"x".to_vec(),
+                            100,
+                            options,
+                            expect_ok(tx.clone(), 2))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_commit(Context::new(),
+                          vec![make_key(b"x")],
+                          100,
+                          110,
+                          expect_ok(tx.clone(), 3))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_get(Context::new(),
+                       make_key(b"x"),
+                       120,
+                       expect_get_val(tx.clone(), b"100".to_vec(), 4))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_one_pc() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        let mut options = Options::default();
+        options.try_one_pc = true;
+        options.commit_ts = 101;
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"100".to_vec())),
+                                 Mutation::Put((make_key(b"y"), b"100".to_vec()))],
+                            b"x".to_vec(),
+                            100,
+                            options.clone(),
+                            expect_ok(tx.clone(), 0))
+            .unwrap();
+        rx.recv().unwrap();
+        // Committed without locks.
+        storage.async_batch_get(Context::new(),
+                             vec![make_key(b"x"), make_key(b"y")],
+                             110,
+                             expect_ok(tx.clone(), 1))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_get(Context::new(),
+                       make_key(b"y"),
+                       110,
+                       expect_get_val(tx.clone(), b"100".to_vec(), 2))
+            .unwrap();
+        rx.recv().unwrap();
+        assert!(storage.get_max_read_ts().get() >= 110);
+
+        // The commit ts must be larger than the ts the keys have been read at.
+        options.commit_ts = 110;
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"105".to_vec()))],
+                            b"x".to_vec(),
+                            105,
+                            options.clone(),
+                            expect_fail(tx.clone(), 3))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_get(Context::new(),
+                       make_key(b"x"),
+                       u64::MAX,
+                       expect_get_val(tx.clone(), b"100".to_vec(), 4))
+            .unwrap();
+        rx.recv().unwrap();
+        // The commit ts is invalid.
+        options.commit_ts = 120;
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"120".to_vec()))],
+                            b"x".to_vec(),
+                            120,
+                            options.clone(),
+                            expect_fail(tx.clone(), 5))
+            .unwrap();
+        rx.recv().unwrap();
+        options.commit_ts = 121;
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"120".to_vec()))],
+                            b"x".to_vec(),
+                            120,
+                            options,
+                            expect_ok(tx.clone(), 6))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_get(Context::new(),
+                       make_key(b"x"),
+                       u64::MAX,
+                       expect_get_val(tx.clone(), b"120".to_vec(), 7))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_pessimistic_lock_wait() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        acquire_pessimistic_lock(&storage, b"a", 10, 10, 0, expect_multi_ok(tx.clone(), 0));
+        assert_eq!(rx.recv().unwrap(), 0);
+        acquire_pessimistic_lock(&storage, b"b", 20, 20, 0, expect_multi_ok(tx.clone(), 1));
+        assert_eq!(rx.recv().unwrap(), 1);
+
+        // Txn 10 waits for txn 20.
+        acquire_pessimistic_lock(&storage, b"b", 10, 30, 10000, expect_multi_ok(tx.clone(), 2));
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+        // Txn 20 waiting for txn 10 causes a deadlock.
+        acquire_pessimistic_lock(&storage, b"a", 20, 20, 10000, expect_deadlock(tx.clone(), 3));
+        assert_eq!(rx.recv().unwrap(), 3);
+
+        // Rolling back txn 20 wakes up txn 10.
+        storage.async_rollback(Context::new(), vec![make_key(b"b")], 20, expect_ok(tx.clone(), 4))
+            .unwrap();
+        let mut ids = vec![rx.recv().unwrap(), rx.recv().unwrap()];
+        ids.sort();
+        assert_eq!(ids, vec![2, 4]);
+
+        // Waiters time out if the lock isn't released.
+        acquire_pessimistic_lock(&storage, b"a", 40, 40, 100, expect_multi_fail(tx.clone(), 5));
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 5);
+        storage.stop().unwrap();
+    }
+
     #[test]
     fn test_sched_too_busy() {
         let mut config = Config::default();
@@ -1188,6 +2039,82 @@ mod tests {
         storage.stop().unwrap();
     }
 
+    #[test]
+    fn test_txn_heart_beat() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
+                            b"x".to_vec(),
+                            100,
+                            Options::new(3000, false, false),
+                            expect_ok(tx.clone(), 0))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_txn_heart_beat(Context::new(),
+                                  make_key(b"x"),
+                                  100,
+                                  1000,
+                                  expect_ttl(tx.clone(), 3000, 1))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_txn_heart_beat(Context::new(),
+                                  make_key(b"x"),
+                                  100,
+                                  5000,
+                                  expect_ttl(tx.clone(), 5000, 2))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_txn_heart_beat(Context::new(),
+                                  make_key(b"x"),
+                                  101,
+                                  5000,
+                                  expect_fail(tx.clone(), 3))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_check_txn_status() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        let ts = |physical: u64| physical << 18;
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
+                            b"x".to_vec(),
+                            ts(10),
+                            Options::new(100, false, false),
+                            expect_ok(tx.clone(), 0))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_check_txn_status(Context::new(),
+                                    make_key(b"x"),
+                                    ts(10),
+                                    ts(30),
+                                    expect_txn_status(tx.clone(), TxnStatus::Alive { ttl: 80 }, 1))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_check_txn_status(Context::new(),
+                                    make_key(b"x"),
+                                    ts(10),
+                                    ts(110),
+                                    expect_txn_status(tx.clone(), TxnStatus::RolledBack, 2))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_get(Context::new(),
+                       make_key(b"x"),
+                       ts(120),
+                       expect_get_none(tx.clone(), 3))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
     #[test]
     fn test_cleanup() {
         let config = Config::default();
@@ -1391,4 +2318,126 @@ mod tests {
         rx.recv().unwrap();
         storage.stop().unwrap();
     }
+
+    #[test]
+    fn test_raw_invalid_cf() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        for cf in &["", CF_DEFAULT] {
+            storage.async_raw_put(Context::new(),
+                               cf,
+                               b"k".to_vec(),
+                               b"v".to_vec(),
+                               0,
+                               expect_ok(tx.clone(), 0))
+                .unwrap();
+            rx.recv().unwrap();
+        }
+        for cf in &[CF_LOCK, CF_WRITE, CF_RAFT, "unknown"] {
+            match storage.async_raw_get(Context::new(),
+                                        cf,
+                                        b"k".to_vec(),
+                                        expect_get_none(tx.clone(), 1)) {
+                Err(Error::InvalidCf(ref name)) => assert_eq!(name, cf),
+                res => panic!("expect invalid cf, got {:?}", res),
+            }
+            assert!(storage.async_raw_delete_range(Context::new(),
+                                        cf,
+                                        b"a".to_vec(),
+                                        b"z".to_vec(),
+                                        expect_ok(tx.clone(), 2))
+                .is_err());
+        }
+        // The end key must be greater than the start key.
+        assert!(storage.async_raw_delete_range(Context::new(),
+                                    "",
+                                    b"z".to_vec(),
+                                    b"a".to_vec(),
+                                    expect_ok(tx.clone(), 3))
+            .is_err());
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_raw_cf() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        storage.async_raw_put(Context::new(),
+                           CF_RAW,
+                           b"k".to_vec(),
+                           b"v".to_vec(),
+                           0,
+                           expect_ok(tx.clone(), 0))
+            .unwrap();
+        rx.recv().unwrap();
+        // The raw data in `CF_RAW` is kept apart from `CF_DEFAULT`.
+        storage.async_raw_get(Context::new(), "", b"k".to_vec(), expect_get_none(tx.clone(), 1))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_raw_get(Context::new(),
+                           CF_RAW,
+                           b"k".to_vec(),
+                           expect_get_val(tx.clone(), b"v".to_vec(), 2))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_raw_ttl_not_enabled() {
+        let config = Config::default();
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, _rx) = channel();
+        assert!(storage.async_raw_put(Context::new(),
+                           "",
+                           b"k".to_vec(),
+                           b"v".to_vec(),
+                           10,
+                           expect_ok(tx.clone(), 0))
+            .is_err());
+        assert!(storage.async_raw_batch_put(Context::new(),
+                                 "",
+                                 vec![(b"k".to_vec(), b"v".to_vec())],
+                                 10,
+                                 expect_ok(tx.clone(), 1))
+            .is_err());
+        storage.stop().unwrap();
+    }
+
+    #[test]
+    fn test_txn_with_ttl_enabled() {
+        let mut config = Config::default();
+        config.enable_ttl = true;
+        let mut storage = Storage::new(&config).unwrap();
+        storage.start(&config).unwrap();
+        let (tx, rx) = channel();
+        // TTL only applies to `CF_RAW`, so transactions can be used.
+        storage.async_prewrite(Context::new(),
+                            vec![Mutation::Put((make_key(b"x"), b"100".to_vec()))],
+                            b"x".to_vec(),
+                            101,
+                            Options::default(),
+                            expect_ok(tx.clone(), 2))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_commit(Context::new(),
+                          vec![make_key(b"x")],
+                          101,
+                          102,
+                          expect_ok(tx.clone(), 3))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.async_get(Context::new(),
+                       make_key(b"x"),
+                       103,
+                       expect_get_val(tx.clone(), b"100".to_vec(), 4))
+            .unwrap();
+        rx.recv().unwrap();
+        storage.stop().unwrap();
+    }
 }
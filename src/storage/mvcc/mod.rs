@@ -66,6 +66,22 @@ quick_error! {
             display("write conflict {} with {}, key:{:?}, primary:{:?}",
              start_ts, conflict_ts, key, primary)
         }
+        TxnNotFound { start_ts: u64, key: Vec<u8> } {
+            description("txn not found")
+            display("txn not found {} key:{:?}", start_ts, key)
+        }
+        Deadlock { start_ts: u64, lock_ts: u64, key: Vec<u8> } {
+            description("deadlock")
+            display("deadlock {} waits for {}, key:{:?}", start_ts, lock_ts, key)
+        }
+        PessimisticLockNotFound { start_ts: u64, key: Vec<u8> } {
+            description("pessimistic lock not found")
+            display("pessimistic lock not found {} key:{:?}", start_ts, key)
+        }
+        PessimisticLockRolledBack { start_ts: u64, key: Vec<u8> } {
+            description("pessimistic lock already rolled back")
+            display("pessimistic lock already rolled back {} key:{:?}", start_ts, key)
+        }
         KeyVersion {description("bad format key(version)")}
         Other(err: Box<error::Error + Sync + Send>) {
             from()
@@ -106,6 +122,31 @@ impl Error {
                     primary: primary.to_owned(),
                 })
             }
+            Error::TxnNotFound { start_ts, ref key } => {
+                Some(Error::TxnNotFound {
+                    start_ts: start_ts,
+                    key: key.to_owned(),
+                })
+            }
+            Error::Deadlock { start_ts, lock_ts, ref key } => {
+                Some(Error::Deadlock {
+                    start_ts: start_ts,
+                    lock_ts: lock_ts,
+                    key: key.to_owned(),
+                })
+            }
+            Error::PessimisticLockNotFound { start_ts, ref key } => {
+                Some(Error::PessimisticLockNotFound {
+                    start_ts: start_ts,
+                    key: key.to_owned(),
+                })
+            }
+            Error::PessimisticLockRolledBack { start_ts, ref key } => {
+                Some(Error::PessimisticLockRolledBack {
+                    start_ts: start_ts,
+                    key: key.to_owned(),
+                })
+            }
             Error::KeyVersion => Some(Error::KeyVersion),
             Error::Committed { commit_ts } => Some(Error::Committed { commit_ts: commit_ts }),
             Error::Io(_) | Error::Other(_) => None,
@@ -114,3 +155,16 @@ impl Error {
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+// The lowest 18 bits of a timestamp from PD are the logical part.
+const TSO_PHYSICAL_SHIFT_BITS: u64 = 18;
+
+/// Extracts the physical part, in milliseconds, of a timestamp.
+pub fn extract_physical(ts: u64) -> u64 {
+    ts >> TSO_PHYSICAL_SHIFT_BITS
+}
+
+/// Composes a timestamp from its physical part, in milliseconds, and its logical part.
+pub fn compose_ts(physical: u64, logical: u64) -> u64 {
+    (physical << TSO_PHYSICAL_SHIFT_BITS) + logical
+}
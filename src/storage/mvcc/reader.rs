@@ -14,7 +14,7 @@
 use storage::engine::{Snapshot, Cursor, ScanMode, Statistics};
 use storage::{Key, Value, CF_LOCK, CF_WRITE};
 use super::{Error, Result};
-use super::lock::Lock;
+use super::lock::{Lock, LockType};
 use super::write::{Write, WriteType};
 use raftstore::store::engine::IterOption;
 use std::u64;
@@ -36,6 +36,8 @@ pub struct MvccReader<'a> {
 
     fill_cache: bool,
     upper_bound: Option<Vec<u8>>,
+    // the inclusive lower bound of reverse seeks
+    lower_bound: Option<Vec<u8>>,
     isolation_level: IsolationLevel,
 }
 
@@ -58,6 +60,7 @@ impl<'a> MvccReader<'a> {
             key_only: false,
             fill_cache: fill_cache,
             upper_bound: upper_bound,
+            lower_bound: None,
         }
     }
 
@@ -69,6 +72,12 @@ impl<'a> MvccReader<'a> {
         self.key_only = key_only;
     }
 
+    /// Sets the inclusive lower bound of `reverse_seek`, keys below it are neither returned nor
+    /// checked for locks.
+    pub fn set_lower_bound(&mut self, lower_bound: Option<Vec<u8>>) {
+        self.lower_bound = lower_bound;
+    }
+
     pub fn load_data(&mut self, key: &Key, ts: u64) -> Result<Value> {
         if self.key_only {
             return Ok(vec![]);
@@ -181,6 +190,10 @@ impl<'a> MvccReader<'a> {
 
     fn check_lock(&mut self, key: &Key, mut ts: u64) -> Result<Option<u64>> {
         if let Some(lock) = try!(self.load_lock(key)) {
+            // Pessimistic locks don't block readers, their values are not prewritten yet.
+            if lock.lock_type == LockType::Pessimistic {
+                return Ok(Some(ts));
+            }
             if lock.ts <= ts {
                 if ts == u64::MAX && try!(key.raw()) == lock.primary {
                     // when ts==u64::MAX(which means to get latest committed version for
@@ -386,6 +399,9 @@ impl<'a> MvccReader<'a> {
                     }
                 }
             };
+            if self.lower_bound.as_ref().map_or(false, |b| key.encoded() < b) {
+                return Ok(None);
+            }
             if let Some(v) = try!(self.get(&key, ts)) {
                 return Ok(Some((key, v)));
             }
@@ -13,12 +13,12 @@
 
 use std::fmt;
 use storage::{Key, Value, Mutation, CF_DEFAULT, CF_LOCK, CF_WRITE, Options, is_short_value,
-              Statistics};
+              Statistics, TxnStatus};
 use storage::engine::{Snapshot, Modify, ScanMode};
 use super::reader::MvccReader;
 use super::lock::{LockType, Lock};
 use super::write::{WriteType, Write};
-use super::{Error, Result};
+use super::{Error, Result, extract_physical};
 use super::metrics::*;
 use kvproto::kvrpcpb::IsolationLevel;
 
@@ -71,8 +71,15 @@ impl<'a> MvccTxn<'a> {
                 lock_type: LockType,
                 primary: Vec<u8>,
                 ttl: u64,
-                short_value: Option<Value>) {
-        let lock = Lock::new(lock_type, primary, self.start_ts, ttl, short_value).to_bytes();
+                short_value: Option<Value>,
+                for_update_ts: u64) {
+        let lock = Lock::new(lock_type,
+                             primary,
+                             self.start_ts,
+                             ttl,
+                             short_value,
+                             for_update_ts)
+            .to_bytes();
         self.write_size += CF_LOCK.len() + key.encoded().len() + lock.len();
         self.writes.push(Modify::Put(CF_LOCK, key, lock));
     }
@@ -116,18 +123,10 @@ impl<'a> MvccTxn<'a> {
                     options: &Options)
                     -> Result<()> {
         let key = mutation.key();
-        if !options.skip_constraint_check {
-            if let Some((commit, _)) = try!(self.reader.seek_write(key, u64::max_value())) {
-                // Abort on writes after our start timestamp ...
-                if commit >= self.start_ts {
-                    return Err(Error::WriteConflict {
-                        start_ts: self.start_ts,
-                        conflict_ts: commit,
-                        key: key.encoded().to_owned(),
-                        primary: primary.to_vec(),
-                    });
-                }
-            }
+        // Abort on writes after our start timestamp ...
+        if !options.skip_constraint_check &&
+           try!(self.check_write_conflict(key, primary, options)) {
+            return Ok(());
         }
         // ... or locks at any timestamp.
         if let Some(lock) = try!(self.reader.load_lock(key)) {
@@ -139,6 +138,11 @@ impl<'a> MvccTxn<'a> {
                     ttl: lock.ttl,
                 });
             }
+            if options.try_one_pc {
+                // The key has been prewritten in two phases by a former request of the
+                // transaction, commits it with the other keys.
+                return self.commit(key, options.commit_ts);
+            }
             // No need to overwrite the lock and data.
             // If we use single delete, we can't put a key multiple times.
             info!("duplicated prewrite with start_ts {}, ignore it.",
@@ -146,7 +150,42 @@ impl<'a> MvccTxn<'a> {
             return Ok(());
         }
 
-        let short_value = if let Mutation::Put((_, ref value)) = mutation {
+        self.prewrite_key_value(&mutation, primary, options);
+        Ok(())
+    }
+
+    // Returns an error if the key has been committed at or after the start timestamp. Returns
+    // true if the key has been committed by a duplicated one-phase prewrite of the transaction.
+    fn check_write_conflict(&mut self,
+                            key: &Key,
+                            primary: &[u8],
+                            options: &Options)
+                            -> Result<bool> {
+        if let Some((commit, write)) = try!(self.reader.seek_write(key, u64::max_value())) {
+            if options.try_one_pc && write.start_ts == self.start_ts &&
+               write.write_type != WriteType::Rollback {
+                info!("duplicated one-phase prewrite with start_ts {}, ignore it.",
+                      self.start_ts);
+                return Ok(true);
+            }
+            if commit >= self.start_ts {
+                return Err(Error::WriteConflict {
+                    start_ts: self.start_ts,
+                    conflict_ts: commit,
+                    key: key.encoded().to_owned(),
+                    primary: primary.to_vec(),
+                });
+            }
+        }
+        Ok(false)
+    }
+
+    fn prewrite_key_value(&mut self, mutation: &Mutation, primary: &[u8], options: &Options) {
+        if options.try_one_pc {
+            return self.one_pc_commit_key_value(mutation, options.commit_ts);
+        }
+        let key = mutation.key();
+        let short_value = if let Mutation::Put((_, ref value)) = *mutation {
             if is_short_value(value) {
                 Some(value.clone())
             } else {
@@ -157,22 +196,168 @@ impl<'a> MvccTxn<'a> {
         };
 
         self.lock_key(key.clone(),
-                      LockType::from_mutation(&mutation),
+                      LockType::from_mutation(mutation),
                       primary.to_vec(),
                       options.lock_ttl,
-                      short_value);
+                      short_value,
+                      options.for_update_ts);
 
-        if let Mutation::Put((_, ref value)) = mutation {
+        if let Mutation::Put((_, ref value)) = *mutation {
             if !is_short_value(value) {
                 let ts = self.start_ts;
                 self.put_value(key, ts, value.clone());
             }
         }
+    }
+
+    /// Writes the mutation directly to `CF_WRITE` at `commit_ts` without locking the key, the
+    /// caller must ensure all the mutations of the transaction are written in one batch.
+    fn one_pc_commit_key_value(&mut self, mutation: &Mutation, commit_ts: u64) {
+        let key = mutation.key();
+        let short_value = match *mutation {
+            Mutation::Put((_, ref value)) if is_short_value(value) => Some(value.clone()),
+            Mutation::Put((_, ref value)) => {
+                let ts = self.start_ts;
+                self.put_value(key, ts, value.clone());
+                None
+            }
+            _ => None,
+        };
+        let write_type = WriteType::from_lock_type(LockType::from_mutation(mutation));
+        let write = Write::new(write_type, self.start_ts, short_value);
+        self.put_write(key, commit_ts, write.to_bytes());
+    }
+
+    pub fn acquire_pessimistic_lock(&mut self,
+                                    key: Key,
+                                    primary: &[u8],
+                                    options: &Options)
+                                    -> Result<()> {
+        let for_update_ts = options.for_update_ts;
+        if let Some(lock) = try!(self.reader.load_lock(&key)) {
+            if lock.ts != self.start_ts {
+                return Err(Error::KeyIsLocked {
+                    key: try!(key.raw()),
+                    primary: lock.primary,
+                    ts: lock.ts,
+                    ttl: lock.ttl,
+                });
+            }
+            if lock.lock_type != LockType::Pessimistic {
+                // The key has already been prewritten by this transaction.
+                return Ok(());
+            }
+            if lock.for_update_ts >= for_update_ts {
+                // Duplicated request, the lock is already held.
+                return Ok(());
+            }
+            // Fall through to refresh the `for_update_ts` of the lock.
+        } else {
+            if let Some((commit, _)) = try!(self.reader.seek_write(&key, u64::max_value())) {
+                // Abort on writes after our for_update timestamp ...
+                if commit > for_update_ts {
+                    return Err(Error::WriteConflict {
+                        start_ts: self.start_ts,
+                        conflict_ts: commit,
+                        key: key.encoded().to_owned(),
+                        primary: primary.to_vec(),
+                    });
+                }
+            }
+            // ... or if the transaction has been rolled back on this key.
+            if let Some((_, WriteType::Rollback)) =
+                try!(self.reader.get_txn_commit_info(&key, self.start_ts)) {
+                return Err(Error::PessimisticLockRolledBack {
+                    start_ts: self.start_ts,
+                    key: try!(key.raw()),
+                });
+            }
+        }
+
+        self.lock_key(key,
+                      LockType::Pessimistic,
+                      primary.to_vec(),
+                      options.lock_ttl,
+                      None,
+                      for_update_ts);
+        Ok(())
+    }
+
+    pub fn pessimistic_prewrite(&mut self,
+                                mutation: Mutation,
+                                primary: &[u8],
+                                is_pessimistic_lock: bool,
+                                options: &Options)
+                                -> Result<()> {
+        let key = mutation.key();
+        match try!(self.reader.load_lock(key)) {
+            Some(lock) => {
+                if lock.ts != self.start_ts {
+                    // The pessimistic lock must have been resolved by others.
+                    if is_pessimistic_lock {
+                        return Err(Error::PessimisticLockNotFound {
+                            start_ts: self.start_ts,
+                            key: try!(key.raw()),
+                        });
+                    }
+                    return Err(Error::KeyIsLocked {
+                        key: try!(key.raw()),
+                        primary: lock.primary,
+                        ts: lock.ts,
+                        ttl: lock.ttl,
+                    });
+                }
+                if lock.lock_type != LockType::Pessimistic {
+                    if options.try_one_pc {
+                        return self.commit(key, options.commit_ts);
+                    }
+                    info!("duplicated prewrite with start_ts {}, ignore it.",
+                          self.start_ts);
+                    return Ok(());
+                }
+                // Overwrite the pessimistic lock held by ourselves.
+            }
+            None => {
+                if is_pessimistic_lock {
+                    return Err(Error::PessimisticLockNotFound {
+                        start_ts: self.start_ts,
+                        key: try!(key.raw()),
+                    });
+                }
+                // The key isn't locked pessimistically, so it may have been written since the
+                // transaction started.
+                if !options.skip_constraint_check &&
+                   try!(self.check_write_conflict(key, primary, options)) {
+                    return Ok(());
+                }
+            }
+        }
+
+        // Write conflicts of the pessimistically locked keys have been checked when the locks
+        // were acquired.
+        self.prewrite_key_value(&mutation, primary, options);
+        if options.try_one_pc && is_pessimistic_lock {
+            self.unlock_key(key.clone());
+        }
         Ok(())
     }
 
     pub fn commit(&mut self, key: &Key, commit_ts: u64) -> Result<()> {
         let (lock_type, short_value) = match try!(self.reader.load_lock(key)) {
+            Some(ref lock) if lock.ts == self.start_ts &&
+                              lock.lock_type == LockType::Pessimistic => {
+                // A pessimistic lock must be prewritten before committing.
+                info!("txn conflict (pessimistic lock not prewritten), key:{}, start_ts:{}, \
+                       commit_ts:{}",
+                      key,
+                      self.start_ts,
+                      commit_ts);
+                return Err(Error::TxnLockNotFound {
+                    start_ts: self.start_ts,
+                    commit_ts: commit_ts,
+                    key: key.encoded().to_owned(),
+                });
+            }
             Some(ref mut lock) if lock.ts == self.start_ts => {
                 (lock.lock_type, lock.short_value.take())
             }
@@ -207,6 +392,78 @@ impl<'a> MvccTxn<'a> {
         Ok(())
     }
 
+    /// Commits `key` on behalf of a lock resolver. A pessimistic lock that was never prewritten
+    /// doesn't belong to the committed data of the transaction, so it is simply removed.
+    pub fn resolve_commit(&mut self, key: &Key, commit_ts: u64) -> Result<()> {
+        if let Some(lock) = try!(self.reader.load_lock(key)) {
+            if lock.ts == self.start_ts && lock.lock_type == LockType::Pessimistic {
+                self.unlock_key(key.clone());
+                return Ok(());
+            }
+        }
+        self.commit(key, commit_ts)
+    }
+
+    /// Extends the TTL of the primary lock of the transaction to `advise_ttl` if it's larger,
+    /// and returns the TTL of the lock after updating.
+    pub fn txn_heart_beat(&mut self, primary_key: Key, advise_ttl: u64) -> Result<u64> {
+        match try!(self.reader.load_lock(&primary_key)) {
+            Some(lock) if lock.ts == self.start_ts => {
+                if lock.ttl >= advise_ttl {
+                    return Ok(lock.ttl);
+                }
+                self.lock_key(primary_key,
+                              lock.lock_type,
+                              lock.primary,
+                              advise_ttl,
+                              lock.short_value,
+                              lock.for_update_ts);
+                Ok(advise_ttl)
+            }
+            _ => {
+                info!("txn heart beat on a missing lock, key:{}, start_ts:{}",
+                      primary_key,
+                      self.start_ts);
+                Err(Error::TxnNotFound {
+                    start_ts: self.start_ts,
+                    key: try!(primary_key.raw()),
+                })
+            }
+        }
+    }
+
+    /// Checks the status of the transaction on its primary key at `current_ts`. The primary lock
+    /// is rolled back if it has expired.
+    pub fn check_txn_status(&mut self, primary_key: &Key, current_ts: u64) -> Result<TxnStatus> {
+        match try!(self.reader.load_lock(primary_key)) {
+            Some(ref lock) if lock.ts == self.start_ts => {
+                let elapsed = extract_physical(current_ts)
+                    .saturating_sub(extract_physical(lock.ts));
+                if elapsed < lock.ttl {
+                    return Ok(TxnStatus::Alive { ttl: lock.ttl - elapsed });
+                }
+                info!("rollback expired lock, key:{}, start_ts:{}, ttl:{}, current_ts:{}",
+                      primary_key,
+                      self.start_ts,
+                      lock.ttl,
+                      current_ts);
+            }
+            _ => {
+                match try!(self.reader.get_txn_commit_info(primary_key, self.start_ts)) {
+                    Some((_, WriteType::Rollback)) => return Ok(TxnStatus::RolledBack),
+                    Some((commit_ts, _)) => {
+                        return Ok(TxnStatus::Committed { commit_ts: commit_ts });
+                    }
+                    // The primary lock hasn't been prewritten yet, the rollback record prevents
+                    // it from being prewritten later.
+                    None => {}
+                }
+            }
+        }
+        try!(self.rollback(primary_key));
+        Ok(TxnStatus::RolledBack)
+    }
+
     pub fn rollback(&mut self, key: &Key) -> Result<()> {
         match try!(self.reader.load_lock(key)) {
             Some(ref lock) if lock.ts == self.start_ts => {
@@ -315,8 +572,9 @@ mod tests {
     use super::MvccTxn;
     use super::super::MvccReader;
     use super::super::write::{Write, WriteType};
+    use super::super::{LockType, Result};
     use storage::{make_key, Mutation, ALL_CFS, CF_WRITE, ScanMode, Options, SHORT_VALUE_MAX_LEN,
-                  Statistics};
+                  Statistics, TxnStatus};
     use storage::engine::{self, Engine, TEMP_DIR};
 
     fn gen_value(v: u8, len: usize) -> Vec<u8> {
@@ -795,6 +1053,190 @@ mod tests {
         must_get_rc(engine.as_ref(), key, 20, v1);
     }
 
+    #[test]
+    fn test_pessimistic_lock() {
+        let engine = engine::new_local_engine(TEMP_DIR, ALL_CFS).unwrap();
+        let (k, v) = (b"k1", b"v1");
+
+        // Normal: acquire, prewrite and commit.
+        must_acquire_pessimistic_lock(engine.as_ref(), k, k, 1, 1);
+        must_pessimistic_locked(engine.as_ref(), k, 1, 1);
+        // Pessimistic locks don't block readers.
+        must_get_none(engine.as_ref(), k, 2);
+        // Can't commit before prewrite.
+        must_commit_err(engine.as_ref(), k, 1, 2);
+        must_pessimistic_prewrite_put(engine.as_ref(), k, v, k, 1, 1, true);
+        must_locked(engine.as_ref(), k, 1);
+        // Duplicated requests after prewrite are ignored.
+        must_acquire_pessimistic_lock(engine.as_ref(), k, k, 1, 1);
+        must_pessimistic_prewrite_put(engine.as_ref(), k, v, k, 1, 1, true);
+        must_commit(engine.as_ref(), k, 1, 2);
+        must_unlocked(engine.as_ref(), k);
+        must_get(engine.as_ref(), k, 3, v);
+
+        // Lock conflict.
+        must_acquire_pessimistic_lock(engine.as_ref(), k, k, 3, 3);
+        must_acquire_pessimistic_lock_err(engine.as_ref(), k, k, 4, 4);
+        must_prewrite_lock_err(engine.as_ref(), k, k, 4);
+        // Refresh for_update_ts.
+        must_acquire_pessimistic_lock(engine.as_ref(), k, k, 3, 5);
+        must_pessimistic_locked(engine.as_ref(), k, 3, 5);
+        must_rollback(engine.as_ref(), k, 3);
+        must_unlocked(engine.as_ref(), k);
+        // Can't lock again after rollback.
+        must_acquire_pessimistic_lock_err(engine.as_ref(), k, k, 3, 6);
+
+        // Write conflict.
+        must_prewrite_put(engine.as_ref(), k, v, k, 4);
+        must_commit(engine.as_ref(), k, 4, 6);
+        must_acquire_pessimistic_lock_err(engine.as_ref(), k, k, 5, 5);
+        must_acquire_pessimistic_lock(engine.as_ref(), k, k, 5, 7);
+        must_pessimistic_locked(engine.as_ref(), k, 5, 7);
+        must_rollback(engine.as_ref(), k, 5);
+        must_unlocked(engine.as_ref(), k);
+
+        // Prewrite fails if the pessimistic lock is lost.
+        must_pessimistic_prewrite_put_err(engine.as_ref(), k, v, k, 8, 8, true);
+        // Keys without pessimistic locks can be prewritten directly.
+        must_pessimistic_prewrite_put(engine.as_ref(), k, v, k, 8, 8, false);
+        must_locked(engine.as_ref(), k, 8);
+        must_pessimistic_prewrite_put_err(engine.as_ref(), k, v, k, 9, 9, true);
+        must_rollback(engine.as_ref(), k, 8);
+
+        // Resolving a committed transaction removes its pessimistic locks.
+        must_acquire_pessimistic_lock(engine.as_ref(), k, k, 10, 10);
+        must_resolve_commit(engine.as_ref(), k, 10, 11);
+        must_unlocked(engine.as_ref(), k);
+        must_get_commit_ts_none(engine.as_ref(), k, 10);
+    }
+
+    #[test]
+    fn test_pessimistic_prewrite_write_conflict() {
+        let engine = engine::new_local_engine(TEMP_DIR, ALL_CFS).unwrap();
+        let (k1, k2, v) = (b"k1", b"k2", b"v");
+
+        // Txn 1 locks k1 pessimistically, while another txn commits k2 after txn 1 starts.
+        must_acquire_pessimistic_lock(engine.as_ref(), k1, k1, 1, 1);
+        must_prewrite_put(engine.as_ref(), k2, v, k2, 2);
+        must_commit(engine.as_ref(), k2, 2, 3);
+        // k2 isn't locked pessimistically, so prewriting it conflicts with the commit.
+        must_pessimistic_prewrite_put_err(engine.as_ref(), k2, v, k1, 1, 1, false);
+        must_unlocked(engine.as_ref(), k2);
+        // k1 is protected by its pessimistic lock.
+        must_pessimistic_prewrite_put(engine.as_ref(), k1, v, k1, 1, 1, true);
+        must_locked(engine.as_ref(), k1, 1);
+        must_rollback(engine.as_ref(), k1, 1);
+
+        // Keys committed before the start of the txn don't conflict.
+        must_pessimistic_prewrite_put(engine.as_ref(), k2, v, k2, 4, 4, false);
+        must_locked(engine.as_ref(), k2, 4);
+    }
+
+    #[test]
+    fn test_txn_heart_beat() {
+        let engine = engine::new_local_engine(TEMP_DIR, ALL_CFS).unwrap();
+        let (k, v) = (b"k1", b"v1");
+
+        // The lock is prewritten with a zero TTL.
+        must_prewrite_put(engine.as_ref(), k, v, k, 5);
+        must_txn_heart_beat(engine.as_ref(), k, 5, 100, 100);
+        // The TTL is never shortened.
+        must_txn_heart_beat(engine.as_ref(), k, 5, 90, 100);
+        must_txn_heart_beat(engine.as_ref(), k, 5, 110, 110);
+        must_locked(engine.as_ref(), k, 5);
+        // The lock doesn't belong to the transaction.
+        must_txn_heart_beat_err(engine.as_ref(), k, 6, 100);
+        must_commit(engine.as_ref(), k, 5, 10);
+        must_get(engine.as_ref(), k, 10, v);
+        // The transaction has been committed.
+        must_txn_heart_beat_err(engine.as_ref(), k, 5, 100);
+
+        // Pessimistic locks can be kept alive too.
+        must_acquire_pessimistic_lock(engine.as_ref(), k, k, 11, 11);
+        must_txn_heart_beat(engine.as_ref(), k, 11, 100, 100);
+        must_pessimistic_locked(engine.as_ref(), k, 11, 11);
+        must_rollback(engine.as_ref(), k, 11);
+        must_txn_heart_beat_err(engine.as_ref(), k, 11, 100);
+    }
+
+    #[test]
+    fn test_check_txn_status() {
+        let engine = engine::new_local_engine(TEMP_DIR, ALL_CFS).unwrap();
+        let (k, v) = (b"k1", b"v1");
+        let ts = |physical: u64| physical << 18;
+
+        // Alive until the TTL expires.
+        must_prewrite_put(engine.as_ref(), k, v, k, ts(10));
+        must_txn_heart_beat(engine.as_ref(), k, ts(10), 100, 100);
+        must_check_txn_status(engine.as_ref(), k, ts(10), ts(50), TxnStatus::Alive { ttl: 60 });
+        must_locked(engine.as_ref(), k, ts(10));
+        // The expired lock is rolled back.
+        must_check_txn_status(engine.as_ref(), k, ts(10), ts(110), TxnStatus::RolledBack);
+        must_unlocked(engine.as_ref(), k);
+        must_written(engine.as_ref(), k, ts(10), ts(10), WriteType::Rollback);
+        must_check_txn_status(engine.as_ref(), k, ts(10), ts(120), TxnStatus::RolledBack);
+
+        // Committed.
+        must_prewrite_put(engine.as_ref(), k, v, k, ts(120));
+        must_commit(engine.as_ref(), k, ts(120), ts(130));
+        must_check_txn_status(engine.as_ref(),
+                              k,
+                              ts(120),
+                              ts(140),
+                              TxnStatus::Committed { commit_ts: ts(130) });
+
+        // The primary lock isn't prewritten yet, it can't be prewritten after the check.
+        must_check_txn_status(engine.as_ref(), k, ts(140), ts(150), TxnStatus::RolledBack);
+        must_prewrite_lock_err(engine.as_ref(), k, k, ts(140));
+    }
+
+    #[test]
+    fn test_one_pc() {
+        let engine = engine::new_local_engine(TEMP_DIR, ALL_CFS).unwrap();
+        let (k, v) = (b"k1", b"v1");
+        let put = |value: &[u8]| Mutation::Put((make_key(k), value.to_vec()));
+
+        must_one_pc_prewrite(engine.as_ref(), put(v), k, 2, 3, false);
+        must_unlocked(engine.as_ref(), k);
+        must_written(engine.as_ref(), k, 2, 3, WriteType::Put);
+        must_get_none(engine.as_ref(), k, 2);
+        must_get(engine.as_ref(), k, 3, v);
+        // Duplicated requests are ignored.
+        must_one_pc_prewrite(engine.as_ref(), put(v), k, 2, 3, false);
+        // Write conflict.
+        must_one_pc_prewrite_err(engine.as_ref(), put(v), k, 1, 4);
+
+        // Long values are written to CF_DEFAULT.
+        let long_value = gen_value(b'v', SHORT_VALUE_MAX_LEN + 1);
+        must_one_pc_prewrite(engine.as_ref(), put(&long_value), k, 5, 6, false);
+        must_get(engine.as_ref(), k, 6, &long_value);
+        must_one_pc_prewrite(engine.as_ref(), Mutation::Delete(make_key(k)), k, 7, 8, false);
+        must_written(engine.as_ref(), k, 7, 8, WriteType::Delete);
+        must_get_none(engine.as_ref(), k, 9);
+
+        // Locked by another transaction.
+        must_prewrite_put(engine.as_ref(), k, v, k, 10);
+        must_one_pc_prewrite_err(engine.as_ref(), put(v), k, 11, 12);
+        must_rollback(engine.as_ref(), k, 10);
+
+        // Pessimistic locks are removed by one-phase commit.
+        must_acquire_pessimistic_lock(engine.as_ref(), k, k, 13, 13);
+        must_one_pc_prewrite(engine.as_ref(), put(v), k, 13, 14, true);
+        must_unlocked(engine.as_ref(), k);
+        must_written(engine.as_ref(), k, 13, 14, WriteType::Put);
+        must_get(engine.as_ref(), k, 15, v);
+
+        // The keys prewritten in two phases by the transaction are committed.
+        must_prewrite_put(engine.as_ref(), k, v, k, 16);
+        must_one_pc_prewrite(engine.as_ref(), put(v), k, 16, 17, false);
+        must_unlocked(engine.as_ref(), k);
+        must_written(engine.as_ref(), k, 16, 17, WriteType::Put);
+        must_pessimistic_prewrite_put(engine.as_ref(), k, v, k, 18, 18, false);
+        must_one_pc_prewrite(engine.as_ref(), put(v), k, 18, 19, true);
+        must_unlocked(engine.as_ref(), k);
+        must_written(engine.as_ref(), k, 18, 19, WriteType::Put);
+    }
+
     fn must_get(engine: &Engine, key: &[u8], ts: u64, expect: &[u8]) {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
@@ -897,6 +1339,193 @@ mod tests {
         assert!(txn.prewrite(Mutation::Lock(make_key(key)), pk, &Options::default()).is_err());
     }
 
+    fn must_acquire_pessimistic_lock(engine: &Engine,
+                                     key: &[u8],
+                                     pk: &[u8],
+                                     start_ts: u64,
+                                     for_update_ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut statistics = Statistics::default();
+        let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                   &mut statistics,
+                                   start_ts,
+                                   None,
+                                   IsolationLevel::SI);
+        let mut options = Options::default();
+        options.for_update_ts = for_update_ts;
+        txn.acquire_pessimistic_lock(make_key(key), pk, &options).unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
+    fn must_acquire_pessimistic_lock_err(engine: &Engine,
+                                         key: &[u8],
+                                         pk: &[u8],
+                                         start_ts: u64,
+                                         for_update_ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut statistics = Statistics::default();
+        let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                   &mut statistics,
+                                   start_ts,
+                                   None,
+                                   IsolationLevel::SI);
+        let mut options = Options::default();
+        options.for_update_ts = for_update_ts;
+        assert!(txn.acquire_pessimistic_lock(make_key(key), pk, &options).is_err());
+    }
+
+    fn txn_heart_beat(engine: &Engine, key: &[u8], start_ts: u64, advise_ttl: u64) -> Result<u64> {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut statistics = Statistics::default();
+        let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                   &mut statistics,
+                                   start_ts,
+                                   None,
+                                   IsolationLevel::SI);
+        let ttl = try!(txn.txn_heart_beat(make_key(key), advise_ttl));
+        engine.write(&ctx, txn.modifies()).unwrap();
+        Ok(ttl)
+    }
+
+    fn must_txn_heart_beat(engine: &Engine,
+                           key: &[u8],
+                           start_ts: u64,
+                           advise_ttl: u64,
+                           expect_ttl: u64) {
+        let ttl = txn_heart_beat(engine, key, start_ts, advise_ttl).unwrap();
+        assert_eq!(ttl, expect_ttl);
+        let snapshot = engine.snapshot(&Context::new()).unwrap();
+        let mut statistics = Statistics::default();
+        let mut reader = MvccReader::new(snapshot.as_ref(),
+                                         &mut statistics,
+                                         None,
+                                         true,
+                                         None,
+                                         IsolationLevel::SI);
+        let lock = reader.load_lock(&make_key(key)).unwrap().unwrap();
+        assert_eq!(lock.ts, start_ts);
+        assert_eq!(lock.ttl, expect_ttl);
+    }
+
+    fn must_txn_heart_beat_err(engine: &Engine, key: &[u8], start_ts: u64, advise_ttl: u64) {
+        assert!(txn_heart_beat(engine, key, start_ts, advise_ttl).is_err());
+    }
+
+    fn must_check_txn_status(engine: &Engine,
+                             key: &[u8],
+                             lock_ts: u64,
+                             current_ts: u64,
+                             expect: TxnStatus) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut statistics = Statistics::default();
+        let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                   &mut statistics,
+                                   lock_ts,
+                                   None,
+                                   IsolationLevel::SI);
+        let status = txn.check_txn_status(&make_key(key), current_ts).unwrap();
+        assert_eq!(status, expect);
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
+    fn one_pc_prewrite(engine: &Engine,
+                       mutation: Mutation,
+                       pk: &[u8],
+                       start_ts: u64,
+                       commit_ts: u64,
+                       is_pessimistic_lock: bool)
+                       -> Result<()> {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut statistics = Statistics::default();
+        let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                   &mut statistics,
+                                   start_ts,
+                                   None,
+                                   IsolationLevel::SI);
+        let mut options = Options::default();
+        options.try_one_pc = true;
+        options.commit_ts = commit_ts;
+        if is_pessimistic_lock {
+            options.for_update_ts = start_ts;
+            try!(txn.pessimistic_prewrite(mutation, pk, true, &options));
+        } else {
+            try!(txn.prewrite(mutation, pk, &options));
+        }
+        engine.write(&ctx, txn.modifies()).unwrap();
+        Ok(())
+    }
+
+    fn must_one_pc_prewrite(engine: &Engine,
+                            mutation: Mutation,
+                            pk: &[u8],
+                            start_ts: u64,
+                            commit_ts: u64,
+                            is_pessimistic_lock: bool) {
+        one_pc_prewrite(engine, mutation, pk, start_ts, commit_ts, is_pessimistic_lock).unwrap();
+    }
+
+    fn must_one_pc_prewrite_err(engine: &Engine,
+                                mutation: Mutation,
+                                pk: &[u8],
+                                start_ts: u64,
+                                commit_ts: u64) {
+        assert!(one_pc_prewrite(engine, mutation, pk, start_ts, commit_ts, false).is_err());
+    }
+
+    fn must_pessimistic_prewrite_put(engine: &Engine,
+                                     key: &[u8],
+                                     value: &[u8],
+                                     pk: &[u8],
+                                     start_ts: u64,
+                                     for_update_ts: u64,
+                                     is_pessimistic_lock: bool) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut statistics = Statistics::default();
+        let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                   &mut statistics,
+                                   start_ts,
+                                   None,
+                                   IsolationLevel::SI);
+        let mut options = Options::default();
+        options.for_update_ts = for_update_ts;
+        txn.pessimistic_prewrite(Mutation::Put((make_key(key), value.to_vec())),
+                                  pk,
+                                  is_pessimistic_lock,
+                                  &options)
+            .unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
+    fn must_pessimistic_prewrite_put_err(engine: &Engine,
+                                         key: &[u8],
+                                         value: &[u8],
+                                         pk: &[u8],
+                                         start_ts: u64,
+                                         for_update_ts: u64,
+                                         is_pessimistic_lock: bool) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut statistics = Statistics::default();
+        let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                   &mut statistics,
+                                   start_ts,
+                                   None,
+                                   IsolationLevel::SI);
+        let mut options = Options::default();
+        options.for_update_ts = for_update_ts;
+        assert!(txn.pessimistic_prewrite(Mutation::Put((make_key(key), value.to_vec())),
+                                  pk,
+                                  is_pessimistic_lock,
+                                  &options)
+            .is_err());
+    }
+
     fn must_commit(engine: &Engine, key: &[u8], start_ts: u64, commit_ts: u64) {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
@@ -922,6 +1551,19 @@ mod tests {
         assert!(txn.commit(&make_key(key), commit_ts).is_err());
     }
 
+    fn must_resolve_commit(engine: &Engine, key: &[u8], start_ts: u64, commit_ts: u64) {
+        let ctx = Context::new();
+        let snapshot = engine.snapshot(&ctx).unwrap();
+        let mut statistics = Statistics::default();
+        let mut txn = MvccTxn::new(snapshot.as_ref(),
+                                   &mut statistics,
+                                   start_ts,
+                                   None,
+                                   IsolationLevel::SI);
+        txn.resolve_commit(&make_key(key), commit_ts).unwrap();
+        engine.write(&ctx, txn.modifies()).unwrap();
+    }
+
     fn must_rollback(engine: &Engine, key: &[u8], start_ts: u64) {
         let ctx = Context::new();
         let snapshot = engine.snapshot(&ctx).unwrap();
@@ -973,6 +1615,21 @@ mod tests {
         assert_eq!(lock.ts, start_ts);
     }
 
+    fn must_pessimistic_locked(engine: &Engine, key: &[u8], start_ts: u64, for_update_ts: u64) {
+        let snapshot = engine.snapshot(&Context::new()).unwrap();
+        let mut statistics = Statistics::default();
+        let mut reader = MvccReader::new(snapshot.as_ref(),
+                                         &mut statistics,
+                                         None,
+                                         true,
+                                         None,
+                                         IsolationLevel::SI);
+        let lock = reader.load_lock(&make_key(key)).unwrap().unwrap();
+        assert_eq!(lock.ts, start_ts);
+        assert_eq!(lock.for_update_ts, for_update_ts);
+        assert_eq!(lock.lock_type, LockType::Pessimistic);
+    }
+
     fn must_unlocked(engine: &Engine, key: &[u8]) {
         let snapshot = engine.snapshot(&Context::new()).unwrap();
         let mut statistics = Statistics::default();
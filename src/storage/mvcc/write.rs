@@ -37,6 +37,7 @@ impl WriteType {
             LockType::Put => WriteType::Put,
             LockType::Delete => WriteType::Delete,
             LockType::Lock => WriteType::Lock,
+            LockType::Pessimistic => panic!("pessimistic lock can't be committed directly"),
         }
     }
 
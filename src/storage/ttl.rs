@@ -0,0 +1,154 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-key TTL of raw values.
+//!
+//! When TTL is enabled, every raw value is stored with its expire time, in seconds since the
+//! unix epoch, appended as a big-endian u64 and followed by a flag byte. An expire time of 0
+//! means the value never expires. Keeping the expire time inside the value lets it survive
+//! replication and snapshot transfer as is.
+//!
+//! TTL only applies to the raw values in `CF_RAW`, which transactions never use, so the
+//! compaction filter can't drop transactional data. The raw values in `CF_DEFAULT` never expire.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use byteorder::{BigEndian, ByteOrder};
+use rocksdb::CompactionFilter;
+
+use raftstore::store::keys;
+use super::{Value, CfName, CF_RAW};
+
+pub const RAW_TTL_COMPACTION_FILTER: &'static str = "tikv.raw_ttl";
+
+const EXPIRE_TS_LEN: usize = 8;
+// Marks the values carrying an expire time, so the values without one are never parsed.
+const TTL_FLAG: u8 = b'T';
+const TTL_META_LEN: usize = EXPIRE_TS_LEN + 1;
+const NO_EXPIRE_TS: u64 = 0;
+
+/// Whether the raw values in `cf` are stored with an expire time.
+pub fn is_ttl_enabled(enable_ttl: bool, cf: CfName) -> bool {
+    enable_ttl && cf == CF_RAW
+}
+
+/// Returns the current time in seconds since the unix epoch.
+pub fn current_ts() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Converts a TTL in seconds to the expire time of a value written now, a TTL of 0 means the
+/// value never expires.
+pub fn convert_to_expire_ts(ttl: u64) -> u64 {
+    if ttl == 0 {
+        return NO_EXPIRE_TS;
+    }
+    current_ts().saturating_add(ttl)
+}
+
+pub fn append_expire_ts(mut value: Value, expire_ts: u64) -> Value {
+    let mut buf = [0; TTL_META_LEN];
+    BigEndian::write_u64(&mut buf[..EXPIRE_TS_LEN], expire_ts);
+    buf[EXPIRE_TS_LEN] = TTL_FLAG;
+    value.extend_from_slice(&buf);
+    value
+}
+
+/// Splits a stored value into the user value and its expire time. Returns `None` if the value
+/// doesn't carry an expire time.
+pub fn split_expire_ts(value: &[u8]) -> Option<(&[u8], u64)> {
+    if value.len() < TTL_META_LEN || value[value.len() - 1] != TTL_FLAG {
+        return None;
+    }
+    let (user_value, meta) = value.split_at(value.len() - TTL_META_LEN);
+    Some((user_value, BigEndian::read_u64(&meta[..EXPIRE_TS_LEN])))
+}
+
+pub fn is_expired(expire_ts: u64, now: u64) -> bool {
+    expire_ts != NO_EXPIRE_TS && expire_ts <= now
+}
+
+/// Strips the expire time of a stored value, returns `None` if the value has expired.
+pub fn strip_expire_ts(mut value: Value, now: u64) -> Option<Value> {
+    let user_value_len = match split_expire_ts(&value) {
+        Some((_, expire_ts)) if is_expired(expire_ts, now) => return None,
+        Some((user_value, _)) => user_value.len(),
+        None => {
+            warn!("raw value {:?} has no expire ts", value);
+            return Some(value);
+        }
+    };
+    value.truncate(user_value_len);
+    Some(value)
+}
+
+/// Drops expired raw values during compaction. Keys outside the data range are never touched.
+/// It should only be set on `CF_RAW`.
+pub struct RawTtlCompactionFilter;
+
+impl CompactionFilter for RawTtlCompactionFilter {
+    fn filter(&mut self, _: usize, key: &[u8], value: &[u8]) -> bool {
+        if !key.starts_with(keys::DATA_PREFIX_KEY) {
+            return false;
+        }
+        match split_expire_ts(value) {
+            Some((_, expire_ts)) => is_expired(expire_ts, current_ts()),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::CF_DEFAULT;
+
+    #[test]
+    fn test_expire_ts() {
+        let value = append_expire_ts(b"value".to_vec(), 10);
+        assert_eq!(split_expire_ts(&value), Some((&b"value"[..], 10)));
+        assert_eq!(strip_expire_ts(value.clone(), 9), Some(b"value".to_vec()));
+        assert_eq!(strip_expire_ts(value.clone(), 10), None);
+        assert_eq!(strip_expire_ts(value, 11), None);
+
+        let value = append_expire_ts(vec![], convert_to_expire_ts(0));
+        assert_eq!(split_expire_ts(&value), Some((&b""[..], NO_EXPIRE_TS)));
+        assert_eq!(strip_expire_ts(value, u64::max_value()), Some(vec![]));
+
+        assert!(convert_to_expire_ts(100) >= current_ts() + 99);
+        assert_eq!(split_expire_ts(b"short"), None);
+        // values without the flag don't carry an expire time.
+        assert_eq!(split_expire_ts(b"\x00\x00\x00\x00\x00\x00\x00\x01v"), None);
+
+        assert!(is_ttl_enabled(true, CF_RAW));
+        assert!(!is_ttl_enabled(true, CF_DEFAULT));
+        assert!(!is_ttl_enabled(false, CF_RAW));
+    }
+
+    #[test]
+    fn test_compaction_filter() {
+        let mut filter = RawTtlCompactionFilter;
+        let expired = append_expire_ts(b"v".to_vec(), 1);
+        let alive = append_expire_ts(b"v".to_vec(), convert_to_expire_ts(1000));
+        let no_ttl = append_expire_ts(b"v".to_vec(), 0);
+        assert!(filter.filter(0, &keys::data_key(b"k"), &expired));
+        assert!(!filter.filter(0, &keys::data_key(b"k"), &alive));
+        assert!(!filter.filter(0, &keys::data_key(b"k"), &no_ttl));
+        // local keys are kept.
+        assert!(!filter.filter(0, b"\x01k", &expired));
+        // so are the values without an expire time.
+        let mut no_flag = expired.clone();
+        no_flag.pop();
+        assert!(!filter.filter(0, &keys::data_key(b"k"), &no_flag));
+    }
+}
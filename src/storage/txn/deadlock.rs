@@ -0,0 +1,123 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use util::collections::{HashMap, HashSet};
+
+/// Wait-for graph of the transactions which are waiting for locks.
+///
+/// Transactions are identified by their start_ts. An edge `A -> B` means transaction A is waiting
+/// for a lock held by transaction B. A transaction may wait for the same lock more than once, so
+/// edges are reference counted.
+#[derive(Default)]
+pub struct DetectTable {
+    wait_for_map: HashMap<u64, HashMap<u64, usize>>,
+}
+
+impl DetectTable {
+    pub fn new() -> DetectTable {
+        DetectTable::default()
+    }
+
+    /// Tries to add the edge `txn_ts -> lock_ts` to the graph.
+    ///
+    /// Returns true if the edge would close a cycle, in which case the edge is not added. The
+    /// requesting transaction is chosen as the victim of the deadlock because it hasn't started
+    /// waiting yet, so it's the cheapest one to abort.
+    pub fn detect(&mut self, txn_ts: u64, lock_ts: u64) -> bool {
+        if self.has_path(lock_ts, txn_ts) {
+            return true;
+        }
+        let wait_for = self.wait_for_map.entry(txn_ts).or_insert_with(HashMap::default);
+        *wait_for.entry(lock_ts).or_insert(0) += 1;
+        false
+    }
+
+    /// Removes one edge `txn_ts -> lock_ts` from the graph.
+    pub fn clean_up_wait_for(&mut self, txn_ts: u64, lock_ts: u64) {
+        let empty = match self.wait_for_map.get_mut(&txn_ts) {
+            Some(wait_for) => {
+                let remove = match wait_for.get_mut(&lock_ts) {
+                    Some(count) => {
+                        *count -= 1;
+                        *count == 0
+                    }
+                    None => false,
+                };
+                if remove {
+                    wait_for.remove(&lock_ts);
+                }
+                wait_for.is_empty()
+            }
+            None => false,
+        };
+        if empty {
+            self.wait_for_map.remove(&txn_ts);
+        }
+    }
+
+    fn has_path(&self, from: u64, to: u64) -> bool {
+        let mut visited = HashSet::default();
+        let mut stack = vec![from];
+        while let Some(ts) = stack.pop() {
+            if ts == to {
+                return true;
+            }
+            if !visited.insert(ts) {
+                continue;
+            }
+            if let Some(wait_for) = self.wait_for_map.get(&ts) {
+                stack.extend(wait_for.keys());
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let mut table = DetectTable::new();
+        // 1 -> 2 -> 3
+        assert!(!table.detect(1, 2));
+        assert!(!table.detect(2, 3));
+        // 3 -> 1 closes the cycle.
+        assert!(table.detect(3, 1));
+        // 3 -> 2 closes the cycle too.
+        assert!(table.detect(3, 2));
+        // 1 -> 3 doesn't.
+        assert!(!table.detect(1, 3));
+
+        table.clean_up_wait_for(2, 3);
+        assert!(!table.detect(3, 2));
+        // 2 -> 1 closes the cycle 1 -> 2 -> 1.
+        assert!(table.detect(2, 1));
+        assert!(table.detect(3, 1));
+
+        // Edges are reference counted.
+        assert!(!table.detect(1, 2));
+        table.clean_up_wait_for(1, 2);
+        assert!(table.detect(2, 1));
+        table.clean_up_wait_for(1, 2);
+        table.clean_up_wait_for(1, 3);
+        assert!(!table.detect(2, 1));
+        assert!(table.wait_for_map.get(&1).is_none());
+
+        // Cleaning up nonexistent edges is a no-op.
+        table.clean_up_wait_for(4, 5);
+        table.clean_up_wait_for(2, 5);
+        assert!(table.detect(1, 3));
+    }
+}
@@ -14,6 +14,8 @@
 mod store;
 mod scheduler;
 mod latch;
+mod waiter;
+mod deadlock;
 
 use std::error;
 use std::io::Error as IoError;
@@ -61,6 +63,14 @@ quick_error! {
                         start_ts,
                         commit_ts)
         }
+        CommitTsTooOld {start_ts: u64, commit_ts: u64, max_read_ts: u64} {
+            description("commit ts of one-phase commit is too old")
+            display("commit_ts:{} of one-phase commit with start_ts:{} is not larger than \
+                     max_read_ts:{}",
+                    commit_ts,
+                    start_ts,
+                    max_read_ts)
+        }
     }
 }
 
@@ -76,6 +86,13 @@ impl Error {
                     commit_ts: commit_ts,
                 })
             }
+            Error::CommitTsTooOld { start_ts, commit_ts, max_read_ts } => {
+                Some(Error::CommitTsTooOld {
+                    start_ts: start_ts,
+                    commit_ts: commit_ts,
+                    max_read_ts: max_read_ts,
+                })
+            }
             Error::Other(_) |
             Error::ProtoBuf(_) |
             Error::Io(_) => None,
@@ -32,8 +32,9 @@
 //! to the scheduler.
 
 use std::fmt::{self, Formatter, Debug};
-use std::sync::mpsc::Receiver;
-use std::time::Duration;
+use std::mem;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
 use std::thread;
 use std::u64;
 
@@ -45,9 +46,13 @@ use storage::{Engine, Command, Snapshot, StorageCb, Result as StorageResult,
               Error as StorageError, ScanMode, Statistics};
 use storage::mvcc::{MvccTxn, MvccReader, Error as MvccError, MAX_TXN_WRITE_SIZE, Write,
                     Lock as MvccLock, WriteType};
-use storage::{Key, Value, KvPair, MvccInfo, CMD_TAG_GC};
+use storage::{Key, Value, KvPair, MvccInfo, TxnStatus, RangeChecksum, CfName, MaxReadTs,
+              CMD_TAG_GC, CF_LOCK, CF_WRITE};
+use storage::ttl;
+use storage::gc_filter::GcContext;
 use storage::engine::{CbContext, Result as EngineResult, Callback as EngineCallback, Modify};
 use raftstore::store::engine::IterOption;
+use raftstore::store::keys;
 use util::transport::{SyncSendCh, Error as TransportError};
 use util::time::SlowTimer;
 use util::collections::HashMap;
@@ -56,6 +61,8 @@ use super::Result;
 use super::Error;
 use super::store::SnapshotStore;
 use super::latch::{Latches, Lock};
+use super::waiter::{Waiter, WaitTable};
+use super::deadlock::DetectTable;
 use super::super::metrics::*;
 
 // TODO: make it configurable.
@@ -63,6 +70,9 @@ pub const GC_BATCH_SIZE: usize = 512;
 
 pub const RESOLVE_LOCK_BATCH_SIZE: usize = 512;
 
+// Interval to check the lock waiters which have timed out.
+const LOCK_WAIT_CHECK_INTERVAL_MS: u64 = 100;
+
 /// Process result of a command.
 pub enum ProcessResult {
     Res,
@@ -72,6 +82,13 @@ pub enum ProcessResult {
     MvccStartTs { mvcc: Option<(Key, MvccInfo)> },
     Value { value: Option<Value> },
     Locks { locks: Vec<LockInfo> },
+    Ttl { ttl: u64 },
+    TxnStatus { txn_status: TxnStatus },
+    CompareAndSwap {
+        previous_value: Option<Value>,
+        succeed: bool,
+    },
+    Checksum { checksum: RangeChecksum },
     NextCommand { cmd: Command },
     Failed { err: StorageError },
 }
@@ -172,6 +189,36 @@ fn execute_callback(callback: StorageCb, pr: ProcessResult) {
                 _ => panic!("process result mismatch"),
             }
         }
+        StorageCb::Ttl(cb) => {
+            match pr {
+                ProcessResult::Ttl { ttl } => cb(Ok(ttl)),
+                ProcessResult::Failed { err } => cb(Err(err)),
+                _ => panic!("process result mismatch"),
+            }
+        }
+        StorageCb::TxnStatus(cb) => {
+            match pr {
+                ProcessResult::TxnStatus { txn_status } => cb(Ok(txn_status)),
+                ProcessResult::Failed { err } => cb(Err(err)),
+                _ => panic!("process result mismatch"),
+            }
+        }
+        StorageCb::Checksum(cb) => {
+            match pr {
+                ProcessResult::Checksum { checksum } => cb(Ok(checksum)),
+                ProcessResult::Failed { err } => cb(Err(err)),
+                _ => panic!("process result mismatch"),
+            }
+        }
+        StorageCb::CompareAndSwap(cb) => {
+            match pr {
+                ProcessResult::CompareAndSwap { previous_value, succeed } => {
+                    cb(Ok((previous_value, succeed)))
+                }
+                ProcessResult::Failed { err } => cb(Err(err)),
+                _ => panic!("process result mismatch"),
+            }
+        }
     }
 }
 
@@ -184,6 +231,12 @@ pub struct RunningCtx {
     tag: &'static str,
     ts: u64,
     region_id: u64,
+    // The start_ts of the transaction whose locks may be released by the command, it's cleared if
+    // the command turns out to remove no lock.
+    released_lock_ts: Option<u64>,
+    // The encoded keys committed in one phase by the command, they are guarded in the max read
+    // ts until the command finishes.
+    one_pc_keys: Vec<Vec<u8>>,
     latch_timer: Option<HistogramTimer>,
     _timer: HistogramTimer,
     slow_timer: SlowTimer,
@@ -195,6 +248,13 @@ impl RunningCtx {
         let tag = cmd.tag();
         let ts = cmd.ts();
         let region_id = cmd.get_context().get_region_id();
+        let released_lock_ts = released_lock_ts(&cmd);
+        let one_pc_keys = match cmd {
+            Command::Prewrite { ref mutations, ref options, .. } if options.try_one_pc => {
+                mutations.iter().map(|m| m.key().encoded().clone()).collect()
+            }
+            _ => vec![],
+        };
         RunningCtx {
             cid: cid,
             cmd: Some(cmd),
@@ -203,6 +263,8 @@ impl RunningCtx {
             tag: tag,
             ts: ts,
             region_id: region_id,
+            released_lock_ts: released_lock_ts,
+            one_pc_keys: one_pc_keys,
             latch_timer: Some(SCHED_LATCH_HISTOGRAM_VEC.with_label_values(&[tag]).start_timer()),
             _timer: SCHED_HISTOGRAM_VEC.with_label_values(&[tag]).start_timer(),
             slow_timer: SlowTimer::new(),
@@ -220,6 +282,49 @@ impl Drop for RunningCtx {
     }
 }
 
+/// Returns the start_ts of the transaction whose locks may be released by the command, if any.
+fn released_lock_ts(cmd: &Command) -> Option<u64> {
+    match *cmd {
+        // One-phase commit releases the pessimistic locks of the transaction.
+        Command::Prewrite { start_ts, ref options, .. } if options.try_one_pc => Some(start_ts),
+        Command::Commit { lock_ts, .. } |
+        // The primary lock is rolled back if it has expired.
+        Command::CheckTxnStatus { lock_ts, .. } => Some(lock_ts),
+        Command::Cleanup { start_ts, .. } |
+        Command::Rollback { start_ts, .. } |
+        Command::ResolveLock { start_ts, .. } => Some(start_ts),
+        _ => None,
+    }
+}
+
+fn is_lock_delete(m: &Modify) -> bool {
+    match *m {
+        Modify::Delete(cf, _) => cf == CF_LOCK,
+        _ => false,
+    }
+}
+
+/// Returns the lock a command should wait for, which is the first lock it meets if the
+/// command is allowed to wait.
+fn lock_to_wait(cmd: &Command, pr: &ProcessResult) -> Option<(u64, Vec<u8>)> {
+    match *cmd {
+        Command::AcquirePessimisticLock { ref options, .. } if options.wait_timeout > 0 => {}
+        _ => return None,
+    }
+    let results = match *pr {
+        ProcessResult::MultiRes { ref results } => results,
+        _ => return None,
+    };
+    for res in results {
+        if let Err(StorageError::Txn(Error::Mvcc(ref e))) = *res {
+            if let MvccError::KeyIsLocked { ts, ref key, .. } = *e {
+                return Some((ts, key.clone()));
+            }
+        }
+    }
+    None
+}
+
 /// Creates a callback to receive async results of write prepare from the storage engine.
 fn make_engine_cb(cid: u64, pr: ProcessResult, ch: SyncSendCh<Msg>) -> EngineCallback<()> {
     Box::new(move |(cb_ctx, result)| {
@@ -267,6 +372,25 @@ pub struct Scheduler {
 
     // used to control write flow
     running_write_count: usize,
+
+    // commands waiting for locks to be released, along with their callbacks and the results
+    // to return when they time out
+    wait_table: WaitTable<(Command, StorageCb, ProcessResult)>,
+
+    // wait-for graph of the waiting transactions
+    detect_table: DetectTable,
+
+    // whether raw values are stored with an expire time
+    enable_ttl: bool,
+
+    // whether Get, BatchGet and Scan may be served by followers
+    enable_follower_read: bool,
+
+    // set if old versions are collected by compaction filters
+    gc_context: Option<GcContext>,
+
+    // the max ts of the reads, one-phase commits must be committed after it
+    max_read_ts: MaxReadTs,
 }
 
 // Make clippy happy.
@@ -311,7 +435,11 @@ impl Scheduler {
                schedch: SyncSendCh<Msg>,
                concurrency: usize,
                worker_pool_size: usize,
-               sched_too_busy_threshold: usize)
+               sched_too_busy_threshold: usize,
+               enable_ttl: bool,
+               enable_follower_read: bool,
+               gc_context: Option<GcContext>,
+               max_read_ts: MaxReadTs)
                -> Scheduler {
         Scheduler {
             engine: engine,
@@ -325,13 +453,24 @@ impl Scheduler {
             high_priority_pool: ThreadPool::new_with_name(thd_name!("sched-high-pri-pool"), 1),
             has_gc_command: false,
             running_write_count: 0,
+            wait_table: WaitTable::new(),
+            detect_table: DetectTable::new(),
+            enable_ttl: enable_ttl,
+            enable_follower_read: enable_follower_read,
+            gc_context: gc_context,
+            max_read_ts: max_read_ts,
         }
     }
 }
 
 /// Processes a read command within a worker thread, then posts `ReadFinished` message back to the
 /// event loop.
-fn process_read(cid: u64, mut cmd: Command, ch: SyncSendCh<Msg>, snapshot: Box<Snapshot>) {
+fn process_read(cid: u64,
+                mut cmd: Command,
+                ch: SyncSendCh<Msg>,
+                snapshot: Box<Snapshot>,
+                enable_ttl: bool,
+                gc_context: Option<GcContext>) {
     debug!("process read cmd(cid={}) in worker pool.", cid);
     SCHED_WORKER_COUNTER_VEC.with_label_values(&[cmd.tag(), "read"]).inc();
     let tag = cmd.tag();
@@ -372,11 +511,21 @@ fn process_read(cid: u64, mut cmd: Command, ch: SyncSendCh<Msg>, snapshot: Box<S
             }
         }
         // Scans a range starting with `start_key` up to `limit` rows from the snapshot.
-        Command::Scan { ref ctx, ref start_key, limit, start_ts, ref options, .. } => {
+        Command::Scan { ref ctx, ref start_key, ref end_key, limit, start_ts, ref options } => {
             let snap_store =
                 SnapshotStore::new(snapshot.as_ref(), start_ts, ctx.get_isolation_level());
-            let res = snap_store.scanner(ScanMode::Forward, options.key_only, None, &mut statistics)
-                .and_then(|mut scanner| scanner.scan(start_key.clone(), limit))
+            let (mode, upper_bound) = if options.reverse_scan {
+                (ScanMode::Backward, None)
+            } else {
+                (ScanMode::Forward, end_key.as_ref().map(|k| k.encoded().to_owned()))
+            };
+            let res = snap_store.scanner(mode, options.key_only, upper_bound, &mut statistics)
+                .and_then(|mut scanner| if options.reverse_scan {
+                    scanner.set_lower_bound(end_key.clone());
+                    scanner.reverse_scan(start_key.clone(), limit)
+                } else {
+                    scanner.scan(start_key.clone(), limit)
+                })
                 .and_then(|mut results| {
                     KV_COMMAND_KEYREAD_HISTOGRAM_VEC.with_label_values(&[tag])
                         .observe(results.len() as f64);
@@ -474,6 +623,22 @@ fn process_read(cid: u64, mut cmd: Command, ch: SyncSendCh<Msg>, snapshot: Box<S
                 Err(e) => ProcessResult::Failed { err: e.into() },
             }
         }
+        // Computes the checksum of a range from the snapshot.
+        Command::Checksum { ref ctx, ref start_key, ref end_key, start_ts } => {
+            let snap_store =
+                SnapshotStore::new(snapshot.as_ref(), start_ts, ctx.get_isolation_level());
+            let upper_bound = end_key.as_ref().map(|k| k.encoded().to_owned());
+            let res = snap_store.scanner(ScanMode::Forward, false, upper_bound, &mut statistics)
+                .and_then(|mut scanner| scanner.checksum(start_key.clone()));
+            match res {
+                Ok(checksum) => {
+                    KV_COMMAND_KEYREAD_HISTOGRAM_VEC.with_label_values(&[tag])
+                        .observe(checksum.total_kvs as f64);
+                    ProcessResult::Checksum { checksum: checksum }
+                }
+                Err(e) => ProcessResult::Failed { err: e.into() },
+            }
+        }
         // Scan the locks with timestamp `start_ts`, then either commit them if the command has
         // commit timestamp populated or rollback otherwise.
         Command::ResolveLock { ref ctx, start_ts, commit_ts, ref mut scan_key, .. } => {
@@ -509,6 +674,18 @@ fn process_read(cid: u64, mut cmd: Command, ch: SyncSendCh<Msg>, snapshot: Box<S
                 Err(e) => ProcessResult::Failed { err: e.into() },
             }
         }
+        // Collects garbage in compaction.
+        Command::Gc { ref ctx, safe_point, ratio_threshold, .. } if gc_context.is_some() => {
+            match gc_by_compaction(gc_context.as_ref().unwrap(),
+                                   snapshot.as_ref(),
+                                   ctx,
+                                   safe_point,
+                                   ratio_threshold,
+                                   &mut statistics) {
+                Ok(()) => ProcessResult::Res,
+                Err(e) => ProcessResult::Failed { err: e.into() },
+            }
+        }
         // Collects garbage.
         Command::Gc { ref ctx, safe_point, ratio_threshold, ref mut scan_key, .. } => {
             let mut reader = MvccReader::new(snapshot.as_ref(),
@@ -557,15 +734,34 @@ fn process_read(cid: u64, mut cmd: Command, ch: SyncSendCh<Msg>, snapshot: Box<S
                 Err(e) => ProcessResult::Failed { err: e.into() },
             }
         }
-        Command::RawGet { ref key, .. } => {
+        Command::RawGet { cf, ref key, .. } => {
             KV_COMMAND_KEYREAD_HISTOGRAM_VEC.with_label_values(&[tag]).observe(1f64);
-            match snapshot.get(key) {
+            match raw_get(snapshot.as_ref(), cf, key, enable_ttl) {
                 Ok(val) => ProcessResult::Value { value: val },
                 Err(e) => ProcessResult::Failed { err: StorageError::from(e) },
             }
         }
-        Command::RawScan { ref start_key, limit, .. } => {
-            match process_rawscan(snapshot, start_key, limit, &mut statistics) {
+        Command::RawBatchGet { cf, ref keys, .. } => {
+            KV_COMMAND_KEYREAD_HISTOGRAM_VEC.with_label_values(&[tag])
+                .observe(keys.len() as f64);
+            let mut pairs = vec![];
+            for key in keys {
+                match raw_get(snapshot.as_ref(), cf, key, enable_ttl) {
+                    Ok(Some(value)) => pairs.push(Ok((key.encoded().to_owned(), value))),
+                    Ok(None) => {}
+                    Err(e) => pairs.push(Err(StorageError::from(e))),
+                }
+            }
+            ProcessResult::MultiKvpairs { pairs: pairs }
+        }
+        Command::RawScan { cf, ref start_key, ref end_key, limit, .. } => {
+            match process_rawscan(snapshot,
+                                  cf,
+                                  start_key,
+                                  end_key,
+                                  limit,
+                                  enable_ttl,
+                                  &mut statistics) {
                 Ok(val) => ProcessResult::MultiKvpairs { pairs: val },
                 Err(e) => ProcessResult::Failed { err: StorageError::from(e) },
             }
@@ -583,18 +779,81 @@ fn process_read(cid: u64, mut cmd: Command, ch: SyncSendCh<Msg>, snapshot: Box<S
     }
 }
 
+/// Pushes the safe point to the compaction filters, then compacts the data of the region if its
+/// MVCC properties show there are enough old versions to collect.
+fn gc_by_compaction(gc_context: &GcContext,
+                    snapshot: &Snapshot,
+                    ctx: &Context,
+                    safe_point: u64,
+                    ratio_threshold: f64,
+                    statistics: &mut Statistics)
+                    -> Result<()> {
+    gc_context.update_safe_point(safe_point);
+    {
+        let reader = MvccReader::new(snapshot,
+                                     statistics,
+                                     Some(ScanMode::Forward),
+                                     false,
+                                     None,
+                                     ctx.get_isolation_level());
+        if !reader.need_gc(safe_point, ratio_threshold) {
+            KV_COMMAND_GC_SKIPPED_COUNTER.inc();
+            return Ok(());
+        }
+    }
+    let mut cursor = try!(snapshot.iter_cf(CF_WRITE, IterOption::default(), ScanMode::Mixed));
+    if !cursor.seek_to_first(&mut statistics.write) {
+        KV_COMMAND_GC_EMPTY_RANGE_COUNTER.inc();
+        return Ok(());
+    }
+    let start_key = keys::data_key(cursor.key());
+    cursor.seek_to_last(&mut statistics.write);
+    let end_key = keys::data_key(cursor.key());
+    box_try!(gc_context.compact_range(&start_key, &end_key));
+    Ok(())
+}
+
+/// Gets a raw value, an expired value is treated as not found.
+fn raw_get(snapshot: &Snapshot,
+           cf: CfName,
+           key: &Key,
+           enable_ttl: bool)
+           -> EngineResult<Option<Value>> {
+    let value = try!(snapshot.get_cf(cf, key));
+    if !ttl::is_ttl_enabled(enable_ttl, cf) {
+        return Ok(value);
+    }
+    Ok(value.and_then(|v| ttl::strip_expire_ts(v, ttl::current_ts())))
+}
+
 fn process_rawscan(snapshot: Box<Snapshot>,
+                   cf: CfName,
                    start_key: &Key,
+                   end_key: &Option<Key>,
                    limit: usize,
+                   enable_ttl: bool,
                    stats: &mut Statistics)
                    -> Result<Vec<StorageResult<KvPair>>> {
-    let mut cursor = try!(snapshot.iter(IterOption::default(), ScanMode::Forward));
+    let mut iter_opt = IterOption::default();
+    if let Some(ref end_key) = *end_key {
+        iter_opt = iter_opt.set_upper_bound(end_key.encoded().to_owned());
+    }
+    let mut cursor = try!(snapshot.iter_cf(cf, iter_opt, ScanMode::Forward));
     if !try!(cursor.seek(start_key, &mut stats.data)) {
         return Ok(vec![]);
     }
+    let now = ttl::current_ts();
+    let enable_ttl = ttl::is_ttl_enabled(enable_ttl, cf);
     let mut pairs = vec![];
     while cursor.valid() && pairs.len() < limit {
-        pairs.push(Ok((cursor.key().to_owned(), cursor.value().to_owned())));
+        let mut value = Some(cursor.value().to_owned());
+        if enable_ttl {
+            value = value.and_then(|v| ttl::strip_expire_ts(v, now));
+        }
+        // Expired values are skipped.
+        if let Some(value) = value {
+            pairs.push(Ok((cursor.key().to_owned(), value)));
+        }
         cursor.next(&mut stats.data);
     }
     Ok(pairs)
@@ -602,9 +861,19 @@ fn process_rawscan(snapshot: Box<Snapshot>,
 
 /// Processes a write command within a worker thread, then posts either a `WritePrepareFinished`
 /// message if successful or a `WritePrepareFailed` message back to the event loop.
-fn process_write(cid: u64, cmd: Command, ch: SyncSendCh<Msg>, snapshot: Box<Snapshot>) {
+fn process_write(cid: u64,
+                 cmd: Command,
+                 ch: SyncSendCh<Msg>,
+                 snapshot: Box<Snapshot>,
+                 enable_ttl: bool,
+                 max_read_ts: MaxReadTs) {
     SCHED_WORKER_COUNTER_VEC.with_label_values(&[cmd.tag(), "write"]).inc();
-    if let Err(e) = process_write_impl(cid, cmd, ch.clone(), snapshot.as_ref()) {
+    if let Err(e) = process_write_impl(cid,
+                                       cmd,
+                                       ch.clone(),
+                                       snapshot.as_ref(),
+                                       enable_ttl,
+                                       max_read_ts) {
         if let Err(err) = ch.send(Msg::WritePrepareFailed { cid: cid, err: e }) {
             // Todo: if this happens, lock will hold for ever
             panic!("send WritePrepareFailed message to channel failed. cid={}, err={:?}",
@@ -617,19 +886,83 @@ fn process_write(cid: u64, cmd: Command, ch: SyncSendCh<Msg>, snapshot: Box<Snap
 fn process_write_impl(cid: u64,
                       mut cmd: Command,
                       ch: SyncSendCh<Msg>,
-                      snapshot: &Snapshot)
+                      snapshot: &Snapshot,
+                      enable_ttl: bool,
+                      max_read_ts: MaxReadTs)
                       -> Result<()> {
     let mut statistics = Statistics::default();
     let (pr, modifies) = match cmd {
         Command::Prewrite { ref ctx, ref mutations, ref primary, start_ts, ref options, .. } => {
+            if options.try_one_pc && options.commit_ts <= start_ts {
+                return Err(Error::InvalidTxnTso {
+                    start_ts: start_ts,
+                    commit_ts: options.commit_ts,
+                });
+            }
             let mut txn = MvccTxn::new(snapshot,
                                        &mut statistics,
                                        start_ts,
                                        None,
                                        ctx.get_isolation_level());
             let mut locks = vec![];
-            for m in mutations {
-                match txn.prewrite(m.clone(), primary, options) {
+            for (i, m) in mutations.iter().enumerate() {
+                let res = if options.is_pessimistic_lock.is_empty() {
+                    txn.prewrite(m.clone(), primary, options)
+                } else {
+                    txn.pessimistic_prewrite(m.clone(),
+                                             primary,
+                                             options.is_pessimistic_lock[i],
+                                             options)
+                };
+                match res {
+                    Ok(_) => {}
+                    e @ Err(MvccError::KeyIsLocked { .. }) => {
+                        locks.push(e.map_err(Error::from).map_err(StorageError::from));
+                    }
+                    Err(e) => return Err(Error::from(e)),
+                }
+            }
+            if locks.is_empty() {
+                // Checks the max read ts after the mutations are checked, as late as possible
+                // before they are written. The keys are guarded first, so the reads updating the
+                // max read ts after the check wait until the commit is applied. The guards are
+                // released when the command is removed.
+                if options.try_one_pc {
+                    let keys: Vec<_> =
+                        mutations.iter().map(|m| m.key().encoded().clone()).collect();
+                    max_read_ts.guard(&keys, options.commit_ts);
+                }
+                if options.try_one_pc &&
+                   (!max_read_ts.is_synced(ctx.get_region_id()) ||
+                    options.commit_ts <= max_read_ts.get()) {
+                    return Err(Error::CommitTsTooOld {
+                        start_ts: start_ts,
+                        commit_ts: options.commit_ts,
+                        max_read_ts: max_read_ts.get(),
+                    });
+                }
+                let pr = ProcessResult::MultiRes { results: vec![] };
+                (pr, txn.modifies())
+            } else {
+                // Skip write stage if some keys are locked.
+                let pr = ProcessResult::MultiRes { results: locks };
+                (pr, vec![])
+            }
+        }
+        Command::AcquirePessimisticLock { ref ctx,
+                                          ref keys,
+                                          ref primary,
+                                          start_ts,
+                                          ref options,
+                                          .. } => {
+            let mut txn = MvccTxn::new(snapshot,
+                                       &mut statistics,
+                                       start_ts,
+                                       None,
+                                       ctx.get_isolation_level());
+            let mut locks = vec![];
+            for k in keys {
+                match txn.acquire_pessimistic_lock(k.clone(), primary, options) {
                     Ok(_) => {}
                     e @ Err(MvccError::KeyIsLocked { .. }) => {
                         locks.push(e.map_err(Error::from).map_err(StorageError::from));
@@ -689,6 +1022,28 @@ fn process_write_impl(cid: u64,
             let pr = ProcessResult::Res;
             (pr, txn.modifies())
         }
+        Command::TxnHeartBeat { ref ctx, ref primary_key, start_ts, advise_ttl } => {
+            let mut txn = MvccTxn::new(snapshot,
+                                       &mut statistics,
+                                       start_ts,
+                                       None,
+                                       ctx.get_isolation_level());
+            let ttl = try!(txn.txn_heart_beat(primary_key.clone(), advise_ttl));
+
+            let pr = ProcessResult::Ttl { ttl: ttl };
+            (pr, txn.modifies())
+        }
+        Command::CheckTxnStatus { ref ctx, ref primary_key, lock_ts, current_ts } => {
+            let mut txn = MvccTxn::new(snapshot,
+                                       &mut statistics,
+                                       lock_ts,
+                                       None,
+                                       ctx.get_isolation_level());
+            let txn_status = try!(txn.check_txn_status(primary_key, current_ts));
+
+            let pr = ProcessResult::TxnStatus { txn_status: txn_status };
+            (pr, txn.modifies())
+        }
         Command::ResolveLock { ref ctx, start_ts, commit_ts, ref mut scan_key, ref keys } => {
             if let Some(cts) = commit_ts {
                 if cts <= start_ts {
@@ -706,7 +1061,7 @@ fn process_write_impl(cid: u64,
                                        ctx.get_isolation_level());
             for k in keys {
                 match commit_ts {
-                    Some(ts) => try!(txn.commit(k, ts)),
+                    Some(ts) => try!(txn.resolve_commit(k, ts)),
                     None => try!(txn.rollback(k)),
                 }
                 if txn.write_size() >= MAX_TXN_WRITE_SIZE {
@@ -758,6 +1113,37 @@ fn process_write_impl(cid: u64,
                 (pr, txn.modifies())
             }
         }
+        Command::RawCompareAndSwap { cf, ref key, ref previous_value, ref mut value, .. } => {
+            let current_value = try!(raw_get(snapshot, cf, key, enable_ttl));
+            if current_value == *previous_value {
+                let value = mem::replace(value, vec![]);
+                let pr = ProcessResult::CompareAndSwap {
+                    previous_value: current_value,
+                    succeed: true,
+                };
+                (pr, vec![Modify::Put(cf, key.clone(), value)])
+            } else {
+                let pr = ProcessResult::CompareAndSwap {
+                    previous_value: current_value,
+                    succeed: false,
+                };
+                (pr, vec![])
+            }
+        }
+        Command::RawPut { cf, ref mut pairs, .. } => {
+            let modifies = mem::replace(pairs, vec![])
+                .into_iter()
+                .map(|(k, v)| Modify::Put(cf, k, v))
+                .collect();
+            (ProcessResult::Res, modifies)
+        }
+        Command::RawDelete { cf, ref mut keys, .. } => {
+            let modifies = mem::replace(keys, vec![])
+                .into_iter()
+                .map(|k| Modify::Delete(cf, k))
+                .collect();
+            (ProcessResult::Res, modifies)
+        }
         _ => panic!("unsupported write command"),
     };
 
@@ -801,6 +1187,10 @@ impl Scheduler {
         if ctx.tag == CMD_TAG_GC {
             self.has_gc_command = false;
         }
+        // The keys are still latched, so the guards belong to the command.
+        if !ctx.one_pc_keys.is_empty() {
+            self.max_read_ts.release(&ctx.one_pc_keys);
+        }
         SCHED_CONTEX_GAUGE.set(self.cmd_ctxs.len() as f64);
         ctx
     }
@@ -831,11 +1221,18 @@ impl Scheduler {
         }
         let ch = self.schedch.clone();
         let readcmd = cmd.readonly();
+        let enable_ttl = self.enable_ttl;
+        let gc_context = self.gc_context.clone();
+        let max_read_ts = self.max_read_ts.clone();
         let worker_pool = self.fetch_worker_pool(cmd.priority());
         if readcmd {
-            worker_pool.execute(move || process_read(cid, cmd, ch, snapshot));
+            worker_pool.execute(move || {
+                process_read(cid, cmd, ch, snapshot, enable_ttl, gc_context)
+            });
         } else {
-            worker_pool.execute(move || process_write(cid, cmd, ch, snapshot));
+            worker_pool.execute(move || {
+                process_write(cid, cmd, ch, snapshot, enable_ttl, max_read_ts)
+            });
         }
     }
 
@@ -933,7 +1330,16 @@ impl Scheduler {
             }
         };
 
-        if let Err(e) = self.engine.async_snapshot(self.extract_context(cid), cb) {
+        let res = {
+            let ctx = &self.cmd_ctxs[&cid];
+            let cmd = ctx.cmd.as_ref().unwrap();
+            if self.enable_follower_read && cmd.allow_follower_read() {
+                self.engine.async_follower_snapshot(cmd.get_context(), cb)
+            } else {
+                self.engine.async_snapshot(cmd.get_context(), cb)
+            }
+        };
+        if let Err(e) = res {
             SCHED_STAGE_COUNTER_VEC.with_label_values(&[self.get_ctx_tag(cid), "async_snap_err"])
                 .inc();
             self.finish_with_err(cid, Error::from(e));
@@ -1002,6 +1408,16 @@ impl Scheduler {
                                  pr: ProcessResult,
                                  to_be_write: Vec<Modify>) {
         SCHED_STAGE_COUNTER_VEC.with_label_values(&[self.get_ctx_tag(cid), "write"]).inc();
+        if let Some((lock_ts, key)) = lock_to_wait(&cmd, &pr) {
+            return self.wait_for_lock(cid, cmd, pr, lock_ts, key);
+        }
+        // Only the commands which actually remove locks wake up the waiters, e.g. checking the
+        // status of an alive transaction doesn't.
+        if !to_be_write.iter().any(is_lock_delete) {
+            if let Some(ctx) = self.cmd_ctxs.get_mut(&cid) {
+                ctx.released_lock_ts = None;
+            }
+        }
         if to_be_write.is_empty() {
             return self.on_write_finished(cid, pr, Ok(()));
         }
@@ -1023,6 +1439,10 @@ impl Scheduler {
             Ok(()) => pr,
             Err(e) => ProcessResult::Failed { err: ::storage::Error::from(e) },
         };
+        let succeeded = match pr {
+            ProcessResult::Failed { .. } => false,
+            _ => true,
+        };
         if let ProcessResult::NextCommand { cmd } = pr {
             SCHED_STAGE_COUNTER_VEC.with_label_values(&[ctx.tag, "next_cmd"]).inc();
             self.schedule_command(cmd, cb);
@@ -1031,6 +1451,73 @@ impl Scheduler {
         }
 
         self.release_lock(&ctx.lock, cid);
+        if let (true, Some(lock_ts)) = (succeeded, ctx.released_lock_ts) {
+            self.wake_up_waiters(lock_ts);
+        }
+    }
+
+    /// Puts a command which meets a lock into the wait table, and releases its latches.
+    ///
+    /// If waiting for the lock would cause a deadlock, the command fails with `Deadlock`
+    /// immediately instead.
+    fn wait_for_lock(&mut self,
+                     cid: u64,
+                     cmd: Command,
+                     pr: ProcessResult,
+                     lock_ts: u64,
+                     key: Vec<u8>) {
+        let start_ts = cmd.ts();
+        if self.detect_table.detect(start_ts, lock_ts) {
+            SCHED_LOCK_WAIT_COUNTER_VEC.with_label_values(&["deadlock"]).inc();
+            info!("deadlock detected, txn {} waits for {}, key {:?}",
+                  start_ts,
+                  lock_ts,
+                  key);
+            let err = MvccError::Deadlock {
+                start_ts: start_ts,
+                lock_ts: lock_ts,
+                key: key,
+            };
+            let pr = ProcessResult::MultiRes {
+                results: vec![Err(StorageError::from(Error::from(err)))],
+            };
+            return self.on_write_finished(cid, pr, Ok(()));
+        }
+        SCHED_LOCK_WAIT_COUNTER_VEC.with_label_values(&["wait"]).inc();
+        let timeout = match cmd {
+            Command::AcquirePessimisticLock { ref options, .. } => options.wait_timeout,
+            _ => unreachable!(),
+        };
+        let mut ctx = self.remove_ctx(cid);
+        let cb = ctx.callback.take().unwrap();
+        let waiter = Waiter::new(start_ts, lock_ts, Duration::from_millis(timeout), (cmd, cb, pr));
+        self.wait_table.add_waiter(waiter);
+
+        self.release_lock(&ctx.lock, cid);
+    }
+
+    /// Reschedules the commands waiting for the locks of transaction `lock_ts`.
+    fn wake_up_waiters(&mut self, lock_ts: u64) {
+        for waiter in self.wait_table.remove_waiters(lock_ts) {
+            SCHED_LOCK_WAIT_COUNTER_VEC.with_label_values(&["wake_up"]).inc();
+            self.detect_table.clean_up_wait_for(waiter.start_ts, waiter.lock_ts);
+            let (cmd, cb, _) = waiter.payload;
+            self.schedule_command(cmd, cb);
+        }
+    }
+
+    /// Returns the original results, which contain the locks, to the waiters which have timed
+    /// out.
+    fn on_wait_timeout(&mut self) {
+        if self.wait_table.is_empty() {
+            return;
+        }
+        for waiter in self.wait_table.remove_expired(Instant::now()) {
+            SCHED_LOCK_WAIT_COUNTER_VEC.with_label_values(&["timeout"]).inc();
+            self.detect_table.clean_up_wait_for(waiter.start_ts, waiter.lock_ts);
+            let (_, cb, pr) = waiter.payload;
+            execute_callback(cb, pr);
+        }
     }
 
     /// Releases all the latches held by a command.
@@ -1050,8 +1537,16 @@ impl Scheduler {
     }
 
     pub fn run(&mut self, receiver: Receiver<Msg>) -> Result<()> {
+        let check_interval = Duration::from_millis(LOCK_WAIT_CHECK_INTERVAL_MS);
         loop {
-            let msg = box_try!(receiver.recv());
+            let msg = match receiver.recv_timeout(check_interval) {
+                Ok(msg) => msg,
+                Err(RecvTimeoutError::Timeout) => {
+                    self.on_wait_timeout();
+                    continue;
+                }
+                Err(e) => return Err(box_err!(e)),
+            };
             match msg {
                 Msg::Quit => return Ok(()),
                 Msg::RawCmd { cmd, cb } => self.on_receive_new_cmd(cmd, cb),
@@ -1067,6 +1562,7 @@ impl Scheduler {
                     self.on_write_finished(cid, pr, result)
                 }
             }
+            self.on_wait_timeout();
         }
     }
 }
@@ -1081,10 +1577,19 @@ pub fn gen_command_lock(latches: &Latches, cmd: &Command) -> Lock {
             let keys: Vec<&Key> = mutations.iter().map(|x| x.key()).collect();
             latches.gen_lock(&keys)
         }
+        Command::AcquirePessimisticLock { ref keys, .. } |
         Command::Commit { ref keys, .. } |
         Command::Rollback { ref keys, .. } |
-        Command::ResolveLock { ref keys, .. } => latches.gen_lock(keys),
-        Command::Cleanup { ref key, .. } => latches.gen_lock(&[key]),
+        Command::ResolveLock { ref keys, .. } |
+        Command::RawDelete { ref keys, .. } => latches.gen_lock(keys),
+        Command::RawPut { ref pairs, .. } => {
+            let keys: Vec<&Key> = pairs.iter().map(|&(ref k, _)| k).collect();
+            latches.gen_lock(&keys)
+        }
+        Command::Cleanup { ref key, .. } |
+        Command::TxnHeartBeat { primary_key: ref key, .. } |
+        Command::CheckTxnStatus { primary_key: ref key, .. } |
+        Command::RawCompareAndSwap { ref key, .. } => latches.gen_lock(&[key]),
         _ => Lock::new(vec![]),
     }
 }
@@ -1094,7 +1599,7 @@ mod tests {
     use super::*;
     use kvproto::kvrpcpb::Context;
     use storage::txn::latch::*;
-    use storage::{Command, make_key, Options, Mutation};
+    use storage::{Command, make_key, Options, Mutation, CF_DEFAULT};
 
     #[test]
     fn test_command_latches() {
@@ -1111,6 +1616,7 @@ mod tests {
                                  Command::Scan {
                                      ctx: Context::new(),
                                      start_key: make_key(b"k"),
+                                     end_key: None,
                                      limit: 100,
                                      start_ts: 25,
                                      options: Options::default(),
@@ -1119,6 +1625,12 @@ mod tests {
                                      ctx: Context::new(),
                                      max_ts: 5,
                                  },
+                                 Command::Checksum {
+                                     ctx: Context::new(),
+                                     start_key: make_key(b"k"),
+                                     end_key: None,
+                                     start_ts: 25,
+                                 },
                                  Command::ResolveLock {
                                      ctx: Context::new(),
                                      start_ts: 10,
@@ -1148,6 +1660,13 @@ mod tests {
                                   start_ts: 10,
                                   options: Options::default(),
                               },
+                              Command::AcquirePessimisticLock {
+                                  ctx: Context::new(),
+                                  keys: vec![make_key(b"k")],
+                                  primary: b"k".to_vec(),
+                                  start_ts: 10,
+                                  options: Options::default(),
+                              },
                               Command::Commit {
                                   ctx: Context::new(),
                                   keys: vec![make_key(b"k")],
@@ -1164,6 +1683,35 @@ mod tests {
                                   keys: vec![make_key(b"k")],
                                   start_ts: 10,
                               },
+                              Command::TxnHeartBeat {
+                                  ctx: Context::new(),
+                                  primary_key: make_key(b"k"),
+                                  start_ts: 10,
+                                  advise_ttl: 100,
+                              },
+                              Command::CheckTxnStatus {
+                                  ctx: Context::new(),
+                                  primary_key: make_key(b"k"),
+                                  lock_ts: 10,
+                                  current_ts: 20,
+                              },
+                              Command::RawCompareAndSwap {
+                                  ctx: Context::new(),
+                                  cf: CF_DEFAULT,
+                                  key: make_key(b"k"),
+                                  previous_value: None,
+                                  value: b"v".to_vec(),
+                              },
+                              Command::RawPut {
+                                  ctx: Context::new(),
+                                  cf: CF_DEFAULT,
+                                  pairs: vec![(make_key(b"k"), b"v".to_vec())],
+                              },
+                              Command::RawDelete {
+                                  ctx: Context::new(),
+                                  cf: CF_DEFAULT,
+                                  keys: vec![make_key(b"k")],
+                              },
                               Command::ResolveLock {
                                   ctx: Context::new(),
                                   start_ts: 10,
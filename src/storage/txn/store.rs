@@ -11,7 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use storage::{Key, Value, KvPair, Snapshot, ScanMode, Statistics};
+use storage::{Key, Value, KvPair, RangeChecksum, Snapshot, ScanMode, Statistics};
 use storage::mvcc::{MvccReader, Error as MvccError};
 use super::{Error, Result};
 use kvproto::kvrpcpb::IsolationLevel;
@@ -99,6 +99,11 @@ impl<'a> StoreScanner<'a> {
         Ok(try!(self.reader.reverse_seek(key, self.start_ts)))
     }
 
+    /// Sets the inclusive lower bound of reverse seeks and scans.
+    pub fn set_lower_bound(&mut self, lower_bound: Option<Key>) {
+        self.reader.set_lower_bound(lower_bound.map(|k| k.encoded().to_owned()));
+    }
+
     #[inline]
     fn handle_mvcc_err(e: MvccError, result: &mut Vec<Result<KvPair>>) -> Result<Key> {
         let key = if let MvccError::KeyIsLocked { key: ref k, .. } = e {
@@ -148,6 +153,17 @@ impl<'a> StoreScanner<'a> {
         Ok(results)
     }
 
+    /// Computes the checksum of the keys from `key` to the upper bound of the scanner. Unlike
+    /// scans, it fails on the first lock it meets.
+    pub fn checksum(&mut self, mut key: Key) -> Result<RangeChecksum> {
+        let mut checksum = RangeChecksum::default();
+        while let Some((k, v)) = try!(self.seek(key)) {
+            checksum.update(&try!(k.raw()), &v);
+            key = k.append_ts(0);
+        }
+        Ok(checksum)
+    }
+
     pub fn close(self) -> &'a mut Statistics {
         self.reader.close()
     }
@@ -158,7 +174,8 @@ mod test {
     use kvproto::kvrpcpb::{Context, IsolationLevel};
     use super::SnapshotStore;
     use storage::mvcc::MvccTxn;
-    use storage::{make_key, Mutation, ALL_CFS, Options, Statistics, ScanMode, KvPair, Value};
+    use storage::{make_key, Mutation, ALL_CFS, Options, Statistics, ScanMode, KvPair, Value,
+                  RangeChecksum};
     use storage::engine::{self, Engine, TEMP_DIR, Snapshot};
 
     const KEY_PREFIX: &str = "key_prefix";
@@ -295,6 +312,38 @@ mod test {
     }
 
 
+    #[test]
+    fn test_snapshot_store_checksum() {
+        let key_num = 100;
+        let store = TestStore::new(key_num);
+        let snapshot_store = store.store();
+        let mut statistics = Statistics::default();
+
+        let half = (key_num / 2) as usize;
+        let end_key = make_key(store.keys[half].as_bytes());
+        let mut scanner = snapshot_store.scanner(ScanMode::Forward,
+                     false,
+                     Some(end_key.encoded().to_owned()),
+                     &mut statistics)
+            .unwrap();
+        let checksum = scanner.checksum(make_key(b"")).unwrap();
+
+        let mut expect = RangeChecksum::default();
+        for k in &store.keys[0..half] {
+            expect.update(k.as_bytes(), k.as_bytes());
+        }
+        assert_eq!(checksum, expect);
+        assert_eq!(checksum.total_kvs, half as u64);
+
+        // Nothing was written before START_TS.
+        let snapshot_store =
+            SnapshotStore::new(store.snapshot.as_ref(), START_TS, IsolationLevel::SI);
+        let mut statistics = Statistics::default();
+        let mut scanner = snapshot_store.scanner(ScanMode::Forward, false, None, &mut statistics)
+            .unwrap();
+        assert_eq!(scanner.checksum(make_key(b"")).unwrap(), RangeChecksum::default());
+    }
+
     #[test]
     fn test_snapshot_store_reverse_scan() {
         let key_num = 100;
@@ -0,0 +1,130 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::mem;
+use std::time::{Duration, Instant};
+
+use util::collections::HashMap;
+
+/// A command which is waiting for a lock to be released.
+pub struct Waiter<T> {
+    /// The start_ts of the waiting transaction.
+    pub start_ts: u64,
+    /// The start_ts of the transaction which holds the lock.
+    pub lock_ts: u64,
+    pub payload: T,
+    deadline: Instant,
+}
+
+impl<T> Waiter<T> {
+    pub fn new(start_ts: u64, lock_ts: u64, timeout: Duration, payload: T) -> Waiter<T> {
+        Waiter {
+            start_ts: start_ts,
+            lock_ts: lock_ts,
+            payload: payload,
+            deadline: Instant::now() + timeout,
+        }
+    }
+}
+
+/// Waiting queues of the commands blocked by locks, keyed by the start_ts of the lock holders.
+///
+/// Waiters are woken up when the lock holder commits or rolls back, or when they time out.
+pub struct WaitTable<T> {
+    waiters: HashMap<u64, Vec<Waiter<T>>>,
+    count: usize,
+}
+
+impl<T> WaitTable<T> {
+    pub fn new() -> WaitTable<T> {
+        WaitTable {
+            waiters: HashMap::default(),
+            count: 0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn add_waiter(&mut self, waiter: Waiter<T>) {
+        self.waiters.entry(waiter.lock_ts).or_insert_with(Vec::new).push(waiter);
+        self.count += 1;
+    }
+
+    /// Removes all the waiters of the locks held by transaction `lock_ts`, in the order they
+    /// started waiting.
+    pub fn remove_waiters(&mut self, lock_ts: u64) -> Vec<Waiter<T>> {
+        let waiters = self.waiters.remove(&lock_ts).unwrap_or_else(Vec::new);
+        self.count -= waiters.len();
+        waiters
+    }
+
+    /// Removes all the waiters whose deadline is before `now`.
+    pub fn remove_expired(&mut self, now: Instant) -> Vec<Waiter<T>> {
+        let mut expired = vec![];
+        if self.is_empty() {
+            return expired;
+        }
+        for (lock_ts, waiters) in mem::replace(&mut self.waiters, HashMap::default()) {
+            let (timeout, waiting): (Vec<_>, Vec<_>) =
+                waiters.into_iter().partition(|w| w.deadline <= now);
+            if !waiting.is_empty() {
+                self.waiters.insert(lock_ts, waiting);
+            }
+            expired.extend(timeout);
+        }
+        self.count -= expired.len();
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    #[test]
+    fn test_wait_table() {
+        let mut table = WaitTable::new();
+        assert!(table.is_empty());
+        let long = Duration::from_secs(3600);
+        table.add_waiter(Waiter::new(10, 1, long, "a"));
+        table.add_waiter(Waiter::new(11, 1, Duration::from_millis(0), "b"));
+        table.add_waiter(Waiter::new(12, 2, long, "c"));
+        table.add_waiter(Waiter::new(13, 1, long, "d"));
+        assert_eq!(table.len(), 4);
+
+        let expired = table.remove_expired(Instant::now());
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].start_ts, 11);
+        assert_eq!(expired[0].payload, "b");
+        assert_eq!(table.len(), 3);
+
+        let woken: Vec<_> = table.remove_waiters(1).into_iter().map(|w| w.payload).collect();
+        assert_eq!(woken, vec!["a", "d"]);
+        assert_eq!(table.len(), 1);
+        assert!(table.remove_waiters(1).is_empty());
+
+        let woken = table.remove_waiters(2);
+        assert_eq!(woken.len(), 1);
+        assert_eq!(woken[0].start_ts, 12);
+        assert!(table.is_empty());
+        assert!(table.remove_expired(Instant::now() + long * 2).is_empty());
+    }
+}
@@ -17,6 +17,7 @@ use std::hash::{Hash, Hasher};
 use std::fmt::{self, Formatter, Display};
 use std::u64;
 
+use crc::crc64::{self, Digest, Hasher64};
 use util::{escape, codec};
 use util::codec::number::{self, NumberEncoder, NumberDecoder};
 use util::codec::bytes::BytesDecoder;
@@ -43,6 +44,44 @@ pub struct MvccInfo {
     pub values: Vec<(u64, bool, Value)>,
 }
 
+/// Status of a transaction, checked on its primary key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TxnStatus {
+    /// The transaction has been committed at `commit_ts`.
+    Committed { commit_ts: u64 },
+    /// The transaction has been rolled back, either before or by the check.
+    RolledBack,
+    /// The transaction is alive, and its primary lock expires after `ttl` milliseconds.
+    Alive { ttl: u64 },
+}
+
+/// Checksum of the key-value pairs in a range.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RangeChecksum {
+    /// XOR of the CRC64 of every key-value pair, so that checksums of sub ranges can be merged
+    /// in any order.
+    pub checksum: u64,
+    pub total_kvs: u64,
+    pub total_bytes: u64,
+}
+
+impl RangeChecksum {
+    pub fn update(&mut self, key: &[u8], value: &[u8]) {
+        let mut digest = Digest::new(crc64::ECMA);
+        digest.write(key);
+        digest.write(value);
+        self.checksum ^= digest.sum64();
+        self.total_kvs += 1;
+        self.total_bytes += (key.len() + value.len()) as u64;
+    }
+
+    pub fn merge(&mut self, other: &RangeChecksum) {
+        self.checksum ^= other.checksum;
+        self.total_kvs += other.total_kvs;
+        self.total_bytes += other.total_bytes;
+    }
+}
+
 /// Key type.
 ///
 /// Keys have 2 types of binary representation - raw and encoded. The raw
@@ -173,4 +212,24 @@ mod tests {
         let res = split_encoded_key_on_ts(enc.encoded()).unwrap();
         assert_eq!(res, (k.as_ref(), ts));
     }
+
+    #[test]
+    fn test_range_checksum() {
+        let pairs: Vec<(&[u8], &[u8])> = vec![(b"k1", b"v1"), (b"k2", b"v22"), (b"k3", b"")];
+        let mut total = RangeChecksum::default();
+        for &(k, v) in &pairs {
+            total.update(k, v);
+        }
+        assert_eq!(total.total_kvs, 3);
+        assert_eq!(total.total_bytes, 9);
+
+        // The checksum doesn't depend on the order of pairs and sub ranges can be merged.
+        let mut left = RangeChecksum::default();
+        left.update(pairs[2].0, pairs[2].1);
+        let mut right = RangeChecksum::default();
+        right.update(pairs[1].0, pairs[1].1);
+        right.update(pairs[0].0, pairs[0].1);
+        left.merge(&right);
+        assert_eq!(left, total);
+    }
 }
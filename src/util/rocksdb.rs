@@ -14,7 +14,10 @@
 use std::fs;
 use std::path::Path;
 
-use storage::CF_DEFAULT;
+use storage::{CF_DEFAULT, CF_WRITE, CF_RAW};
+use storage::ttl::{RawTtlCompactionFilter, RAW_TTL_COMPACTION_FILTER};
+use storage::gc_filter::{GcContext, DefaultCompactionFilter, WriteCompactionFilter,
+                         GC_DEFAULT_COMPACTION_FILTER, GC_WRITE_COMPACTION_FILTER};
 use rocksdb::{DB, ColumnFamilyOptions, DBOptions, SliceTransform, DBCompressionType};
 use rocksdb::rocksdb::supported_compression;
 
@@ -59,6 +62,8 @@ pub fn open_opt(opts: DBOptions,
 pub struct CFOptions<'a> {
     cf: &'a str,
     options: ColumnFamilyOptions,
+    raw_ttl: bool,
+    gc: Option<GcContext>,
 }
 
 impl<'a> CFOptions<'a> {
@@ -66,8 +71,24 @@ impl<'a> CFOptions<'a> {
         CFOptions {
             cf: cf,
             options: options,
+            raw_ttl: false,
+            gc: None,
         }
     }
+
+    /// Drops expired raw values of the cf in compaction, see `storage::ttl`. Only `CF_RAW`
+    /// supports it.
+    pub fn with_raw_ttl(mut self, raw_ttl: bool) -> CFOptions<'a> {
+        self.raw_ttl = raw_ttl;
+        self
+    }
+
+    /// Drops old MVCC versions of the cf in compaction, see `storage::gc_filter`. Only
+    /// `CF_WRITE` and `CF_DEFAULT` support it.
+    pub fn with_gc(mut self, ctx: Option<GcContext>) -> CFOptions<'a> {
+        self.gc = ctx;
+        self
+    }
 }
 
 pub fn new_engine(path: &str, cfs: &[&str]) -> Result<DB, String> {
@@ -158,7 +179,40 @@ fn check_and_open(path: &str,
     Ok(db)
 }
 
-pub fn new_engine_opt(path: &str, opts: DBOptions, cfs_opts: Vec<CFOptions>) -> Result<DB, String> {
+pub fn new_engine_opt(path: &str,
+                      opts: DBOptions,
+                      mut cfs_opts: Vec<CFOptions>)
+                      -> Result<DB, String> {
+    for cf_opts in &mut cfs_opts {
+        if cf_opts.raw_ttl && cf_opts.gc.is_some() {
+            return Err(format!("cf {} can't enable both raw ttl and gc in compaction",
+                               cf_opts.cf));
+        }
+        if cf_opts.raw_ttl {
+            if cf_opts.cf != CF_RAW {
+                return Err(format!("cf {} doesn't support raw ttl", cf_opts.cf));
+            }
+            try!(cf_opts.options
+                .set_compaction_filter(RAW_TTL_COMPACTION_FILTER,
+                                       false,
+                                       box RawTtlCompactionFilter));
+        }
+        if let Some(ctx) = cf_opts.gc.take() {
+            if cf_opts.cf == CF_WRITE {
+                try!(cf_opts.options
+                    .set_compaction_filter(GC_WRITE_COMPACTION_FILTER,
+                                           false,
+                                           box WriteCompactionFilter::new(ctx)));
+            } else if cf_opts.cf == CF_DEFAULT {
+                try!(cf_opts.options
+                    .set_compaction_filter(GC_DEFAULT_COMPACTION_FILTER,
+                                           false,
+                                           box DefaultCompactionFilter::new(ctx)));
+            } else {
+                return Err(format!("cf {} doesn't support gc in compaction", cf_opts.cf));
+            }
+        }
+    }
     check_and_open(path, opts, cfs_opts)
 }
 
@@ -242,10 +296,13 @@ impl SliceTransform for NoopSliceTransform {
 
 #[cfg(test)]
 mod tests {
-    use rocksdb::{DB, DBOptions, ColumnFamilyOptions};
+    use rocksdb::{DB, DBOptions, ColumnFamilyOptions, Writable};
     use tempdir::TempDir;
-    use storage::CF_DEFAULT;
-    use super::{check_and_open, CFOptions};
+    use storage::{CF_DEFAULT, CF_LOCK, CF_WRITE, CF_RAW};
+    use storage::ttl;
+    use storage::gc_filter::GcContext;
+    use raftstore::store::keys;
+    use super::{check_and_open, new_engine_opt, get_cf_handle, CFOptions};
 
     #[test]
     fn test_check_and_open() {
@@ -274,6 +331,56 @@ mod tests {
         column_families_must_eq(path_str, vec![CF_DEFAULT]);
     }
 
+    #[test]
+    fn test_raw_ttl_compaction_filter() {
+        let path = TempDir::new("_util_rocksdb_test_raw_ttl_compaction_filter").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let cfs_opts = vec![CFOptions::new(CF_DEFAULT, ColumnFamilyOptions::new()),
+                            CFOptions::new(CF_RAW, ColumnFamilyOptions::new()).with_raw_ttl(true)];
+        let db = new_engine_opt(path_str, DBOptions::new(), cfs_opts).unwrap();
+
+        let expired = ttl::append_expire_ts(b"v".to_vec(), 1);
+        let alive = ttl::append_expire_ts(b"v".to_vec(), 0);
+        let raw = get_cf_handle(&db, CF_RAW).unwrap();
+        db.put_cf(raw, &keys::data_key(b"k1"), &expired).unwrap();
+        db.put_cf(raw, &keys::data_key(b"k2"), &alive).unwrap();
+        db.put(&keys::data_key(b"k1"), &expired).unwrap();
+        db.compact_range_cf(raw, None, None);
+        db.compact_range(None, None);
+
+        assert!(db.get_cf(raw, &keys::data_key(b"k1")).unwrap().is_none());
+        assert!(db.get_cf(raw, &keys::data_key(b"k2")).unwrap().is_some());
+        // the filter is only set on cfs with raw ttl enabled.
+        assert!(db.get(&keys::data_key(b"k1")).unwrap().is_some());
+        drop(db);
+
+        // transactions store values, locks and writes in the other cfs.
+        for cf in &[CF_DEFAULT, CF_LOCK, CF_WRITE] {
+            let cfs_opts = vec![CFOptions::new(CF_DEFAULT, ColumnFamilyOptions::new()),
+                                CFOptions::new(cf, ColumnFamilyOptions::new()).with_raw_ttl(true)];
+            assert!(new_engine_opt(path_str, DBOptions::new(), cfs_opts).is_err());
+        }
+    }
+
+    #[test]
+    fn test_gc_compaction_filter_options() {
+        let path = TempDir::new("_util_rocksdb_test_gc_compaction_filter_options").expect("");
+        let path_str = path.path().to_str().unwrap();
+        let ctx = Some(GcContext::new());
+
+        for cf in &[CF_LOCK, CF_RAW] {
+            let cfs_opts = vec![CFOptions::new(CF_DEFAULT, ColumnFamilyOptions::new()),
+                                CFOptions::new(cf, ColumnFamilyOptions::new())
+                                    .with_gc(ctx.clone())];
+            assert!(new_engine_opt(path_str, DBOptions::new(), cfs_opts).is_err());
+        }
+
+        let cfs_opts = vec![CFOptions::new(CF_DEFAULT, ColumnFamilyOptions::new())
+                                .with_gc(ctx.clone()),
+                            CFOptions::new(CF_WRITE, ColumnFamilyOptions::new()).with_gc(ctx)];
+        new_engine_opt(path_str, DBOptions::new(), cfs_opts).unwrap();
+    }
+
     fn column_families_must_eq(path: &str, excepted: Vec<&str>) {
         let opts = DBOptions::new();
         let cfs_list = DB::list_column_families(&opts, path).unwrap();
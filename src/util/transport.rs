@@ -117,6 +117,16 @@ impl<T: Debug, C: Sender<T>> RetryableSendCh<T, C> {
         self.send_with_try_times(t, 1)
     }
 
+    /// Like `try_send`, but gives t back if it's not sent, so that the caller can still
+    /// handle it, e.g. call the callback it carries.
+    pub fn try_send_or_return(&self, t: T) -> Result<(), NotifyError<T>> {
+        let res = self.ch.send(t);
+        if let Err(NotifyError::Full(_)) = res {
+            CHANNEL_FULL_COUNTER_VEC.with_label_values(&[self.name]).inc();
+        }
+        res
+    }
+
     fn send_with_try_times(&self, mut t: T, mut try_times: usize) -> Result<(), Error> {
         loop {
             t = match self.ch.send(t) {
@@ -256,6 +266,22 @@ mod tests {
         h.join().unwrap();
     }
 
+    #[test]
+    fn test_sendch_try_send_or_return() {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let ch = SyncSendCh::new(tx, "test");
+        ch.try_send_or_return(Msg::Stop).unwrap();
+        match ch.try_send_or_return(Msg::Sleep(1)) {
+            Err(NotifyError::Full(Msg::Sleep(1))) => {}
+            res => panic!("expect full error, but found: {:?}", res),
+        }
+        drop(rx);
+        match ch.try_send_or_return(Msg::Quit) {
+            Err(NotifyError::Closed(Some(Msg::Quit))) => {}
+            res => panic!("expect closed error, but found: {:?}", res),
+        }
+    }
+
     #[test]
     fn test_sync_sendch_full() {
         let (tx, rx) = mpsc::sync_channel(2);
@@ -22,7 +22,7 @@ use tikv::coprocessor;
 use kvproto::kvrpcpb::Context;
 use tikv::coprocessor::codec::{table, Datum, datum};
 use tikv::util::codec::number::*;
-use tikv::storage::{Mutation, Key, ALL_CFS};
+use tikv::storage::{Mutation, Key, MaxReadTs, ALL_CFS};
 use tikv::storage::engine::{self, Engine, TEMP_DIR};
 use tikv::util::worker::Worker;
 use kvproto::coprocessor::{Request, KeyRange, Response};
@@ -576,7 +576,10 @@ fn init_data_with_commit(tbl: &ProductTable,
         store.commit();
     }
     let mut end_point = Worker::new("test select worker");
-    let runner = EndPointHost::new(store.get_engine(), end_point.scheduler(), 8);
+    let runner = EndPointHost::new(store.get_engine(),
+                                   MaxReadTs::default(),
+                                   end_point.scheduler(),
+                                   8);
     end_point.start_batch(runner, 5).unwrap();
 
     (store, end_point)
@@ -105,6 +105,18 @@ pub fn new_test_raft_with_config(config: &Config, storage: MemStorage) -> Interf
     Interface::new(Raft::new(config, storage))
 }
 
+pub fn new_test_learner_raft(id: u64,
+                             peers: Vec<u64>,
+                             learners: Vec<u64>,
+                             election: usize,
+                             heartbeat: usize,
+                             storage: MemStorage)
+                             -> Interface {
+    let mut config = new_test_config(id, peers, election, heartbeat);
+    config.learners = learners;
+    new_test_raft_with_config(&config, storage)
+}
+
 
 fn read_messages<T: Storage>(raft: &mut Raft<T>) -> Vec<Message> {
     raft.msgs.drain(..).collect()
@@ -184,9 +196,14 @@ impl Interface {
     fn initial(&mut self, id: u64, ids: &[u64]) {
         if self.raft.is_some() {
             self.id = id;
+            let learners = self.learner_nodes();
             self.prs = RaftFlatMap::with_capacity(ids.len());
             for id in ids {
-                self.prs.insert(*id, Progress { ..Default::default() });
+                self.prs.insert(*id,
+                                Progress {
+                                    is_learner: learners.contains(id),
+                                    ..Default::default()
+                                });
             }
             let term = self.term;
             self.reset(term);
@@ -2420,6 +2437,19 @@ fn test_restore() {
     assert!(!sm.restore(s));
 }
 
+// test_restore_with_learner tests that the learners not in the snapshot are kept.
+#[test]
+fn test_restore_with_learner() {
+    let s = new_snapshot(11, 11, vec![1, 2]);
+
+    let mut sm = new_test_learner_raft(3, vec![1, 2], vec![3], 10, 1, new_storage());
+    assert!(!sm.promotable());
+    assert!(sm.restore(s));
+    assert_eq!(sm.nodes(), vec![1, 2]);
+    assert_eq!(sm.learner_nodes(), vec![3]);
+    assert!(!sm.promotable());
+}
+
 #[test]
 fn test_restore_ignore_snapshot() {
     let previous_ents = vec![empty_entry(1, 1), empty_entry(1, 2), empty_entry(1, 3)];
@@ -2625,6 +2655,321 @@ fn test_add_node() {
     assert_eq!(r.nodes(), vec![1, 2]);
 }
 
+// test_add_learner tests that add_learner could update pendingConf and learners, and
+// add_node promotes the learner.
+#[test]
+fn test_add_learner() {
+    let mut r = new_test_raft(1, vec![1], 10, 1, new_storage());
+    r.pending_conf = true;
+    r.add_learner(2);
+    assert!(!r.pending_conf);
+    assert_eq!(r.nodes(), vec![1]);
+    assert_eq!(r.learner_nodes(), vec![2]);
+    assert!(r.is_learner(2));
+
+    // A voter can't be demoted.
+    r.add_learner(1);
+    assert_eq!(r.nodes(), vec![1]);
+    assert_eq!(r.learner_nodes(), vec![2]);
+
+    r.add_node(2);
+    assert_eq!(r.nodes(), vec![1, 2]);
+    assert!(r.learner_nodes().is_empty());
+
+    r.remove_node(2);
+    r.add_learner(3);
+    r.remove_node(3);
+    assert_eq!(r.nodes(), vec![1]);
+    assert!(r.learner_nodes().is_empty());
+}
+
+// test_learner_election_timeout verifies that a learner never campaigns.
+#[test]
+fn test_learner_election_timeout() {
+    let mut n2 = new_test_learner_raft(2, vec![1], vec![2], 10, 1, new_storage());
+    n2.become_follower(1, INVALID_ID);
+
+    let timeout = n2.get_election_timeout();
+    n2.set_randomized_election_timeout(timeout);
+    for _ in 0..timeout {
+        n2.tick();
+    }
+    assert_eq!(n2.state, StateRole::Follower);
+
+    n2.step(new_message(2, 2, MessageType::MsgHup, 0)).expect("");
+    assert_eq!(n2.state, StateRole::Follower);
+    assert!(n2.msgs.is_empty());
+}
+
+// test_learner_promotion verifies that a learner receives the log without being counted
+// in the quorum, and it can campaign after being promoted.
+#[test]
+fn test_learner_promotion() {
+    let mut n1 = new_test_learner_raft(1, vec![1], vec![2], 10, 1, new_storage());
+    n1.become_follower(1, INVALID_ID);
+    let mut n2 = new_test_learner_raft(2, vec![1], vec![2], 10, 1, new_storage());
+    n2.become_follower(1, INVALID_ID);
+    let mut network = Network::new(vec![Some(n1), Some(n2)]);
+
+    // n1 wins the election alone, since n2 isn't counted in the quorum.
+    network.send(vec![new_message(1, 1, MessageType::MsgHup, 0)]);
+    assert_eq!(network.peers[&1].state, StateRole::Leader);
+    assert_eq!(network.peers[&2].state, StateRole::Follower);
+
+    network.send(vec![new_message(1, 1, MessageType::MsgPropose, 1)]);
+    network.send(vec![new_message(1, 1, MessageType::MsgBeat, 0)]);
+    let committed = network.peers[&1].raft_log.committed;
+    assert_eq!(network.peers[&2].raft_log.committed, committed);
+
+    // Leadership can't be transferred to a learner.
+    network.send(vec![new_message(2, 1, MessageType::MsgTransferLeader, 0)]);
+    assert_eq!(network.peers[&1].state, StateRole::Leader);
+    assert_eq!(network.peers[&1].lead_transferee, None);
+
+    network.peers.get_mut(&1).unwrap().add_node(2);
+    network.peers.get_mut(&2).unwrap().add_node(2);
+    assert_eq!(network.peers[&1].nodes(), vec![1, 2]);
+    assert!(network.peers[&2].promotable());
+
+    network.send(vec![new_message(2, 2, MessageType::MsgHup, 0)]);
+    assert_eq!(network.peers[&1].state, StateRole::Follower);
+    assert_eq!(network.peers[&2].state, StateRole::Leader);
+}
+
+// test_learner_restart tests that a restarted node knows its learners from the config only,
+// as ConfState records the voters.
+#[test]
+fn test_learner_restart() {
+    let new_restarted_raft = |id| {
+        let store = MemStorage::new();
+        store.wl().apply_snapshot(new_snapshot(1, 1, vec![1])).expect("");
+        let mut hs = HardState::new();
+        hs.set_term(1);
+        hs.set_commit(1);
+        store.wl().set_hardstate(hs);
+        new_test_learner_raft(id, vec![], vec![2], 10, 1, store)
+    };
+    let n1 = new_restarted_raft(1);
+    let n2 = new_restarted_raft(2);
+    assert_eq!(n2.nodes(), vec![1]);
+    assert_eq!(n2.learner_nodes(), vec![2]);
+    assert!(!n2.promotable());
+    let mut network = Network::new(vec![Some(n1), Some(n2)]);
+
+    // The learner still doesn't campaign.
+    network.send(vec![new_message(2, 2, MessageType::MsgHup, 0)]);
+    assert_eq!(network.peers[&2].state, StateRole::Follower);
+
+    // n1 is still the only voter, and the learner still receives its log.
+    network.send(vec![new_message(1, 1, MessageType::MsgHup, 0)]);
+    assert_eq!(network.peers[&1].state, StateRole::Leader);
+    network.send(vec![new_message(1, 1, MessageType::MsgPropose, 1)]);
+    network.send(vec![new_message(1, 1, MessageType::MsgBeat, 0)]);
+    assert_eq!(network.peers[&1].raft_log.committed, 3);
+    assert_eq!(network.peers[&2].raft_log.committed, 3);
+}
+
+// test_joint_commit tests that the log is committed by the majorities of both configurations
+// in a joint consensus, and by the new configuration after the change is finalized.
+#[test]
+fn test_joint_commit() {
+    let new_joint_raft = || {
+        let store = MemStorage::new();
+        store.wl().append(&[empty_entry(1, 1), empty_entry(1, 2)]).expect("");
+        let mut hs = HardState::new();
+        hs.set_term(1);
+        store.wl().set_hardstate(hs);
+        let mut r = new_test_raft(1, vec![1, 2, 3], 10, 1, store);
+        r.begin_membership_change(&[5, 4, 1]);
+        r
+    };
+
+    let mut r = new_joint_raft();
+    let joint = JointConfig {
+        incoming: vec![1, 4, 5],
+        outgoing: vec![1, 2, 3],
+    };
+    assert_eq!(r.joint, Some(joint));
+    assert_eq!(r.nodes(), vec![1, 2, 3, 4, 5]);
+
+    let mut tests = vec![
+        // the majority of the outgoing configuration only
+        (vec![(1, 2), (2, 2), (3, 2), (4, 0), (5, 0)], 0),
+        // the majority of the incoming configuration only
+        (vec![(1, 2), (2, 0), (3, 0), (4, 2), (5, 2)], 0),
+        // the majority of both
+        (vec![(1, 2), (2, 2), (3, 0), (4, 1), (5, 0)], 1),
+        (vec![(1, 2), (2, 2), (3, 0), (4, 2), (5, 0)], 2),
+    ];
+    for (i, (matches, w)) in tests.drain(..).enumerate() {
+        let mut r = new_joint_raft();
+        for (id, matched) in matches {
+            r.set_progress(id, matched, matched + 1);
+        }
+        r.maybe_commit();
+        if r.raft_log.committed != w {
+            panic!("#{}: committed = {}, want {}", i, r.raft_log.committed, w);
+        }
+    }
+
+    // The outgoing voters are removed once the change is finalized, and the log replicated on
+    // the new configuration is committed.
+    r.set_progress(4, 2, 3);
+    r.set_progress(5, 2, 3);
+    r.maybe_commit();
+    assert_eq!(r.raft_log.committed, 0);
+    r.finalize_membership_change();
+    assert_eq!(r.joint, None);
+    assert_eq!(r.nodes(), vec![1, 4, 5]);
+    assert_eq!(r.raft_log.committed, 2);
+}
+
+// test_joint_restart tests that a node restarted with the joint consensus in its config stays in
+// it, though the ConfState only records the voters of both configurations.
+#[test]
+fn test_joint_restart() {
+    let new_restarted_raft = |joint| {
+        let store = MemStorage::new();
+        store.wl().apply_snapshot(new_snapshot(1, 1, vec![1, 2, 3, 4, 5])).expect("");
+        store.wl().append(&[empty_entry(1, 2)]).expect("");
+        let mut hs = HardState::new();
+        hs.set_term(1);
+        hs.set_commit(1);
+        store.wl().set_hardstate(hs);
+        let mut config = new_test_config(1, vec![], 10, 1);
+        config.joint = Some(joint);
+        new_test_raft_with_config(&config, store)
+    };
+    let joint = JointConfig {
+        incoming: vec![1, 4, 5],
+        outgoing: vec![1, 2, 3],
+    };
+    let mut r = new_restarted_raft(joint.clone());
+    assert_eq!(r.joint, Some(joint.clone()));
+    assert_eq!(r.nodes(), vec![1, 2, 3, 4, 5]);
+
+    // The majority of the outgoing configuration can't commit alone.
+    r.set_progress(1, 2, 3);
+    r.set_progress(2, 2, 3);
+    r.set_progress(3, 2, 3);
+    r.maybe_commit();
+    assert_eq!(r.raft_log.committed, 1);
+    r.set_progress(4, 2, 3);
+    r.maybe_commit();
+    assert_eq!(r.raft_log.committed, 2);
+
+    r.finalize_membership_change();
+    assert_eq!(r.joint, None);
+    assert_eq!(r.nodes(), vec![1, 4, 5]);
+}
+
+#[test]
+#[should_panic]
+fn test_joint_restart_mismatch() {
+    let store = MemStorage::new();
+    store.wl().apply_snapshot(new_snapshot(1, 1, vec![1, 2, 3])).expect("");
+    let mut config = new_test_config(1, vec![], 10, 1);
+    config.joint = Some(JointConfig {
+        incoming: vec![1, 4, 5],
+        outgoing: vec![1, 2, 3],
+    });
+    new_test_raft_with_config(&config, store);
+}
+
+// test_joint_election tests that a candidate in a joint consensus needs the votes of the
+// majorities of both configurations.
+#[test]
+fn test_joint_election() {
+    let mut tests = vec![
+        // the votes granted and rejected by the other voters, the expected state
+        (vec![2, 3], vec![], StateRole::Candidate),
+        (vec![4, 5], vec![], StateRole::Candidate),
+        (vec![2, 4], vec![], StateRole::Leader),
+        (vec![4, 5], vec![2, 3], StateRole::Follower),
+        (vec![2, 3], vec![4, 5], StateRole::Follower),
+        (vec![2], vec![3, 4], StateRole::Candidate),
+    ];
+    for (i, (granted, rejected, state)) in tests.drain(..).enumerate() {
+        let mut r = new_test_raft(1, vec![1, 2, 3], 10, 1, new_storage());
+        r.begin_membership_change(&[1, 4, 5]);
+        r.step(new_message(1, 1, MessageType::MsgHup, 0)).expect("");
+        assert_eq!(r.state, StateRole::Candidate);
+        let mut to: Vec<_> = r.read_messages().iter().map(|m| m.get_to()).collect();
+        to.sort();
+        assert_eq!(to, vec![2, 3, 4, 5]);
+
+        for id in granted {
+            r.step(new_message(id, 1, MessageType::MsgRequestVoteResponse, 0)).expect("");
+        }
+        for id in rejected {
+            let mut m = new_message(id, 1, MessageType::MsgRequestVoteResponse, 0);
+            m.set_reject(true);
+            r.step(m).expect("");
+        }
+        if r.state != state {
+            panic!("#{}: state = {:?}, want {:?}", i, r.state, state);
+        }
+    }
+}
+
+// test_joint_replace_voters tests that several voters are replaced atomically.
+#[test]
+fn test_joint_replace_voters() {
+    let mut network = Network::new(vec![None, None, None, None, None]);
+    // Starts with the voters 1, 2 and 3.
+    for id in 1..6 {
+        let peer = network.peers.get_mut(&id).unwrap();
+        peer.remove_node(4);
+        peer.remove_node(5);
+    }
+    network.send(vec![new_message(1, 1, MessageType::MsgHup, 0)]);
+    assert_eq!(network.peers[&1].state, StateRole::Leader);
+
+    // Replaces 2 and 3 with 4 and 5.
+    for id in 1..6 {
+        network.peers.get_mut(&id).unwrap().begin_membership_change(&[1, 4, 5]);
+    }
+    assert_eq!(network.peers[&1].nodes(), vec![1, 2, 3, 4, 5]);
+
+    // The new voters alone can't commit the log.
+    network.isolate(2);
+    network.isolate(3);
+    let committed = network.peers[&1].raft_log.committed;
+    network.send(vec![new_message(1, 1, MessageType::MsgPropose, 1)]);
+    assert_eq!(network.peers[&1].raft_log.committed, committed);
+    assert_eq!(network.peers[&4].raft_log.last_index(),
+               network.peers[&1].raft_log.last_index());
+
+    // Neither can the old ones, though the entry replicated on the new voters before is
+    // committed now.
+    network.recover();
+    network.isolate(4);
+    network.isolate(5);
+    network.send(vec![new_message(1, 1, MessageType::MsgPropose, 1)]);
+    let last_index = network.peers[&1].raft_log.last_index();
+    assert_eq!(last_index, committed + 2);
+    assert_eq!(network.peers[&1].raft_log.committed, last_index - 1);
+
+    network.recover();
+    network.send(vec![new_message(1, 1, MessageType::MsgBeat, 0)]);
+    network.send(vec![new_message(1, 1, MessageType::MsgPropose, 1)]);
+    let last_index = network.peers[&1].raft_log.last_index();
+    assert_eq!(network.peers[&1].raft_log.committed, last_index);
+
+    for id in 1..6 {
+        network.peers.get_mut(&id).unwrap().finalize_membership_change();
+    }
+    assert_eq!(network.peers[&1].nodes(), vec![1, 4, 5]);
+    assert!(!network.peers[&2].promotable());
+    assert!(network.peers[&4].promotable());
+
+    // The new voters commit the log without the old ones.
+    network.isolate(2);
+    network.isolate(3);
+    network.send(vec![new_message(1, 1, MessageType::MsgPropose, 1)]);
+    assert_eq!(network.peers[&1].raft_log.committed, last_index + 1);
+}
+
 // test_remove_node tests that removeNode could update pendingConf, nodes and
 // and removed list correctly.
 #[test]
@@ -688,6 +688,33 @@ impl<T: Simulator> Cluster<T> {
             .unwrap();
     }
 
+    pub fn split_region(&mut self, region: &metapb::Region, split_key: &[u8], cb: Callback) {
+        let leader = self.leader_of_region(region.get_id()).unwrap();
+        let ch = self.sim.rl().get_store_sendch(leader.get_store_id()).unwrap();
+        ch.try_send(Msg::SplitRegion {
+                region_id: region.get_id(),
+                region_epoch: region.get_region_epoch().clone(),
+                split_key: split_key.to_vec(),
+                callback: Some(cb),
+            })
+            .unwrap();
+    }
+
+    pub fn batch_split_region(&mut self,
+                              region: &metapb::Region,
+                              split_keys: Vec<Vec<u8>>,
+                              cb: SplitRegionCallback) {
+        let leader = self.leader_of_region(region.get_id()).unwrap();
+        let ch = self.sim.rl().get_store_sendch(leader.get_store_id()).unwrap();
+        ch.try_send(Msg::BatchSplitRegion {
+                region_id: region.get_id(),
+                region_epoch: region.get_region_epoch().clone(),
+                split_keys: split_keys,
+                callback: cb,
+            })
+            .unwrap();
+    }
+
     pub fn must_split(&mut self, region: &metapb::Region, split_key: &[u8]) {
         let mut try_cnt = 0;
         let split_count = self.pd_client.get_split_count();
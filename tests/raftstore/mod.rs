@@ -34,4 +34,6 @@ mod test_snap;
 mod test_region_heartbeat;
 mod test_stale_peer;
 mod test_lease_read;
+mod test_follower_read;
+mod test_cdc;
 mod test_bootstrap;
@@ -30,6 +30,7 @@ use kvproto::raft_cmdpb::*;
 use kvproto::raft_serverpb::{self, RaftMessage};
 use kvproto::eraftpb::MessageType;
 use tikv::raftstore::{Result, Error};
+use tikv::raftstore::coprocessor::CoprocessorHost;
 use tikv::util::HandyRwLock;
 use tikv::util::transport::SendCh;
 use tikv::server::Config as ServerConfig;
@@ -131,11 +132,15 @@ impl Channel<RaftMessage> for ChannelTransport {
 
 type SimulateChannelTransport = SimulateTransport<RaftMessage, ChannelTransport>;
 
+pub type CoprocessorHook = Box<Fn(&Arc<DB>, &mut CoprocessorHost) + Send + Sync>;
+
 pub struct NodeCluster {
     trans: ChannelTransport,
     pd_client: Arc<TestPdClient>,
     nodes: HashMap<u64, Node<TestPdClient>>,
     simulate_trans: HashMap<u64, SimulateChannelTransport>,
+    // Called with the engine and the coprocessor host of every node before the node starts.
+    coprocessor_hook: Option<CoprocessorHook>,
 }
 
 impl NodeCluster {
@@ -145,11 +150,16 @@ impl NodeCluster {
             pd_client: pd_client,
             nodes: HashMap::new(),
             simulate_trans: HashMap::new(),
+            coprocessor_hook: None,
         }
     }
 }
 
 impl NodeCluster {
+    pub fn set_coprocessor_hook(&mut self, hook: CoprocessorHook) {
+        self.coprocessor_hook = Some(hook);
+    }
+
     #[allow(dead_code)]
     pub fn get_node_router(&self, node_id: u64) -> SimulateTransport<Msg, ServerRaftStoreRouter> {
         self.trans.rl().routers.get(&node_id).cloned().unwrap()
@@ -179,11 +189,16 @@ impl Simulator for NodeCluster {
             (snap_mgr.clone(), None)
         };
 
+        let mut coprocessor_host = CoprocessorHost::new();
+        if let Some(ref hook) = self.coprocessor_hook {
+            hook(&engine, &mut coprocessor_host);
+        }
         node.start(event_loop,
                    engine.clone(),
                    simulate_trans.clone(),
                    snap_mgr.clone(),
-                   snap_status_receiver)
+                   snap_status_receiver,
+                   coprocessor_host)
             .unwrap();
         assert!(engine.get_msg::<metapb::Region>(&keys::prepare_bootstrap_key())
             .unwrap()
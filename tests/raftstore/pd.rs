@@ -16,6 +16,8 @@ use std::collections::{HashMap, BTreeMap, HashSet};
 use std::collections::Bound::{Excluded, Unbounded};
 use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::cmp;
 
 use futures::{Future, Stream};
 use futures::future::{ok, err};
@@ -27,7 +29,9 @@ use kvproto::eraftpb;
 use tikv::pd::{PdClient, Result, Error, Key, PdFuture, RegionStat};
 use tikv::raftstore::store::keys::{self, enc_end_key, enc_start_key, data_key};
 use tikv::raftstore::store::util::check_key_in_region;
+use tikv::storage::mvcc::compose_ts;
 use tikv::util::{HandyRwLock, escape};
+use tikv::util::time::duration_to_ms;
 use super::util::*;
 
 // Rule is just for special test which we want do more accurate control
@@ -71,6 +75,9 @@ struct Cluster {
     down_peers: HashMap<u64, pdpb::PeerStats>,
     pending_peers: HashMap<u64, metapb::Peer>,
     is_bootstraped: bool,
+
+    gc_safe_point: u64,
+    tso: u64,
 }
 
 impl Cluster {
@@ -91,6 +98,8 @@ impl Cluster {
             down_peers: HashMap::new(),
             pending_peers: HashMap::new(),
             is_bootstraped: false,
+            gc_safe_point: 0,
+            tso: 0,
         }
     }
 
@@ -559,6 +568,10 @@ impl TestPdClient {
     pub fn set_bootstrap(&self, is_bootstraped: bool) {
         self.cluster.wl().set_bootstrap(is_bootstraped);
     }
+
+    pub fn set_gc_safe_point(&self, safe_point: u64) {
+        self.cluster.wl().gc_safe_point = safe_point;
+    }
 }
 
 impl PdClient for TestPdClient {
@@ -700,4 +713,22 @@ impl PdClient for TestPdClient {
         self.cluster.wl().split_count += 1;
         ok(()).boxed()
     }
+
+    fn get_gc_safe_point(&self) -> PdFuture<u64> {
+        if let Err(e) = self.check_bootstrap() {
+            return err(e).boxed();
+        }
+        ok(self.cluster.rl().gc_safe_point).boxed()
+    }
+
+    fn get_timestamp(&self) -> PdFuture<u64> {
+        if let Err(e) = self.check_bootstrap() {
+            return err(e).boxed();
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let physical = duration_to_ms(now);
+        let mut cluster = self.cluster.wl();
+        cluster.tso = cmp::max(compose_ts(physical, 0), cluster.tso + 1);
+        ok(cluster.tso).boxed()
+    }
 }
@@ -28,6 +28,7 @@ use tikv::server::resolve::{self, Task as ResolveTask};
 use tikv::server::transport::ServerRaftStoreRouter;
 use tikv::server::transport::RaftStoreRouter;
 use tikv::raftstore::{Error, Result, store};
+use tikv::raftstore::coprocessor::CoprocessorHost;
 use tikv::raftstore::store::{Msg as StoreMsg, SnapManager};
 use tikv::util::transport::SendCh;
 use tikv::util::worker::Worker;
@@ -128,7 +129,8 @@ impl Simulator for ServerCluster {
                    engine,
                    simulate_trans.clone(),
                    snap_mgr.clone(),
-                   snap_status_receiver)
+                   snap_status_receiver,
+                   CoprocessorHost::new())
             .unwrap();
         assert!(node_id == 0 || node_id == node.id());
         let node_id = node.id();
@@ -13,6 +13,7 @@
 
 use std::sync::{Arc, mpsc};
 use tikv::raftstore::store::{keys, Peekable, SnapManager, create_event_loop, bootstrap_store};
+use tikv::raftstore::coprocessor::CoprocessorHost;
 use tikv::server::Node;
 use tikv::storage::ALL_CFS;
 use tikv::util::rocksdb;
@@ -78,7 +79,8 @@ fn test_node_bootstrap_with_prepared_data() {
                engine.clone(),
                simulate_trans,
                snap_mgr,
-               snapshot_status_receiver)
+               snapshot_status_receiver,
+               CoprocessorHost::new())
         .unwrap();
     assert!(engine.clone()
         .get_msg::<metapb::Region>(&keys::prepare_bootstrap_key())
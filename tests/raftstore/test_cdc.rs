@@ -0,0 +1,282 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A module contains test cases for change data capture on the raftstore.
+
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::{Future, Stream};
+
+use tikv::cdc::{Endpoint, Event, Row, RowOp};
+use tikv::pd::PdClient;
+use tikv::storage::{make_key, MaxReadTs, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use tikv::storage::mvcc::{Lock, LockType, Write, WriteType};
+use tikv::util::HandyRwLock;
+
+use super::cluster::Cluster;
+use super::node::{new_node_cluster, NodeCluster};
+use super::pd::TestPdClient;
+use super::util::*;
+
+// The endpoints of the nodes, with the paths of their engines.
+type Endpoints = Arc<Mutex<Vec<(String, Endpoint<TestPdClient>)>>>;
+
+fn new_cdc_cluster(count: usize) -> (Cluster<NodeCluster>, Endpoints) {
+    let mut cluster = new_node_cluster(0, count);
+    let endpoints: Endpoints = Arc::new(Mutex::new(vec![]));
+    let pd_client = cluster.pd_client.clone();
+    let eps = endpoints.clone();
+    cluster.sim.wl().set_coprocessor_hook(box move |engine, host| {
+        let mut endpoint =
+            Endpoint::new(engine.clone(), pd_client.clone(), 50, MaxReadTs::default());
+        host.registry.register_observer(200, box endpoint.observer());
+        endpoint.start().unwrap();
+        eps.lock().unwrap().push((engine.path().to_owned(), endpoint));
+    });
+    cluster.run();
+    (cluster, endpoints)
+}
+
+fn stop_endpoints(endpoints: &Endpoints) {
+    for &mut (_, ref mut endpoint) in endpoints.lock().unwrap().iter_mut() {
+        if let Some(h) = endpoint.stop() {
+            h.join().unwrap();
+        }
+    }
+}
+
+fn with_endpoint<F, R>(cluster: &Cluster<NodeCluster>,
+                       endpoints: &Endpoints,
+                       store_id: u64,
+                       f: F)
+                       -> R
+    where F: FnOnce(&Endpoint<TestPdClient>) -> R
+{
+    let path = cluster.get_engine(store_id).path().to_owned();
+    let endpoints = endpoints.lock().unwrap();
+    let &(_, ref endpoint) = endpoints.iter().rev().find(|&&(ref p, _)| *p == path).unwrap();
+    f(endpoint)
+}
+
+// Returns the id of the subscriber and its events.
+fn subscribe(cluster: &Cluster<NodeCluster>,
+             endpoints: &Endpoints,
+             store_id: u64,
+             region_id: u64,
+             checkpoint_ts: u64)
+             -> (u64, mpsc::Receiver<Event>) {
+    let (id, rx) = with_endpoint(cluster, endpoints, store_id, |endpoint| {
+        endpoint.register(region_id, checkpoint_ts).unwrap()
+    });
+    let (tx, events) = mpsc::channel();
+    thread::spawn(move || for event in rx.wait() {
+        if tx.send(event.unwrap()).is_err() {
+            return;
+        }
+    });
+    (id, events)
+}
+
+fn unsubscribe(cluster: &Cluster<NodeCluster>,
+               endpoints: &Endpoints,
+               store_id: u64,
+               region_id: u64,
+               id: u64) {
+    with_endpoint(cluster, endpoints, store_id, |endpoint| {
+        endpoint.deregister(region_id, id).unwrap()
+    });
+}
+
+// Receives the rows until the resolved ts reaches `ts`.
+fn must_recv_rows(events: &mpsc::Receiver<Event>, ts: u64) -> Vec<Row> {
+    let mut res = vec![];
+    loop {
+        match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Event::Rows { rows, .. } => res.extend(rows),
+            Event::ResolvedTs { ts: resolved_ts, .. } => {
+                if resolved_ts >= ts {
+                    return res;
+                }
+            }
+            Event::Error { msg, .. } => panic!("subscription failed: {}", msg),
+        }
+    }
+}
+
+// Receives the events sent in `d`.
+fn recv_events(events: &mpsc::Receiver<Event>, d: Duration) -> Vec<Event> {
+    let mut res = vec![];
+    let timer = Instant::now();
+    while timer.elapsed() < d {
+        match events.recv_timeout(Duration::from_millis(10)) {
+            Ok(event) => res.push(event),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+    res
+}
+
+fn must_recv_error(events: &mpsc::Receiver<Event>) {
+    loop {
+        match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Event::Error { .. } => return,
+            _ => {}
+        }
+    }
+}
+
+fn get_ts(cluster: &Cluster<NodeCluster>) -> u64 {
+    cluster.pd_client.get_timestamp().wait().unwrap()
+}
+
+fn must_prewrite(cluster: &mut Cluster<NodeCluster>, key: &[u8], value: &[u8], start_ts: u64) {
+    let k = make_key(key);
+    cluster.must_put_cf(CF_DEFAULT, k.append_ts(start_ts).encoded(), value);
+    let lock = Lock::new(LockType::Put, key.to_vec(), start_ts, 0, None, 0);
+    cluster.must_put_cf(CF_LOCK, k.encoded(), &lock.to_bytes());
+}
+
+fn must_commit(cluster: &mut Cluster<NodeCluster>, key: &[u8], start_ts: u64, commit_ts: u64) {
+    let k = make_key(key);
+    let write = Write::new(WriteType::Put, start_ts, None);
+    cluster.must_put_cf(CF_WRITE, k.append_ts(commit_ts).encoded(), &write.to_bytes());
+    cluster.must_delete_cf(CF_LOCK, k.encoded());
+}
+
+fn must_rollback(cluster: &mut Cluster<NodeCluster>, key: &[u8], start_ts: u64) {
+    let k = make_key(key);
+    let write = Write::new(WriteType::Rollback, start_ts, None);
+    cluster.must_put_cf(CF_WRITE, k.append_ts(start_ts).encoded(), &write.to_bytes());
+    cluster.must_delete_cf(CF_LOCK, k.encoded());
+    cluster.must_delete_cf(CF_DEFAULT, k.append_ts(start_ts).encoded());
+}
+
+fn new_row(key: &[u8], value: &[u8], start_ts: u64, commit_ts: u64) -> Row {
+    Row {
+        key: key.to_vec(),
+        value: Some(value.to_vec()),
+        op: RowOp::Put,
+        start_ts: start_ts,
+        commit_ts: commit_ts,
+    }
+}
+
+#[test]
+fn test_node_cdc() {
+    let (mut cluster, endpoints) = new_cdc_cluster(1);
+
+    // The rows committed before the subscription are sent by the initial scan.
+    let (start_ts, commit_ts) = (get_ts(&cluster), get_ts(&cluster));
+    must_prewrite(&mut cluster, b"k1", b"v1", start_ts);
+    must_commit(&mut cluster, b"k1", start_ts, commit_ts);
+    let (_, events) = subscribe(&cluster, &endpoints, 1, 1, 0);
+    assert_eq!(must_recv_rows(&events, commit_ts),
+               vec![new_row(b"k1", b"v1", start_ts, commit_ts)]);
+
+    // A lock holds the resolved ts back until the transaction commits.
+    let start_ts = get_ts(&cluster);
+    must_prewrite(&mut cluster, b"k2", b"v2", start_ts);
+    let ts = get_ts(&cluster);
+    for event in recv_events(&events, Duration::from_millis(300)) {
+        match event {
+            Event::ResolvedTs { ts: resolved_ts, .. } => assert!(resolved_ts < ts),
+            e => panic!("unexpected event {:?}", e),
+        }
+    }
+    let commit_ts = get_ts(&cluster);
+    must_commit(&mut cluster, b"k2", start_ts, commit_ts);
+    assert_eq!(must_recv_rows(&events, commit_ts),
+               vec![new_row(b"k2", b"v2", start_ts, commit_ts)]);
+
+    // A rolled back transaction has no rows.
+    let start_ts = get_ts(&cluster);
+    must_prewrite(&mut cluster, b"k3", b"v3", start_ts);
+    must_rollback(&mut cluster, b"k3", start_ts);
+    assert_eq!(must_recv_rows(&events, get_ts(&cluster)), vec![]);
+
+    // The rows are sent in the order of their commit ts.
+    let (start_ts1, start_ts2) = (get_ts(&cluster), get_ts(&cluster));
+    must_prewrite(&mut cluster, b"k4", b"v4", start_ts1);
+    must_prewrite(&mut cluster, b"k5", b"v5", start_ts2);
+    let (commit_ts1, commit_ts2) = (get_ts(&cluster), get_ts(&cluster));
+    must_commit(&mut cluster, b"k5", start_ts2, commit_ts2);
+    must_commit(&mut cluster, b"k4", start_ts1, commit_ts1);
+    assert_eq!(must_recv_rows(&events, commit_ts2),
+               vec![new_row(b"k4", b"v4", start_ts1, commit_ts1),
+                    new_row(b"k5", b"v5", start_ts2, commit_ts2)]);
+
+    stop_endpoints(&endpoints);
+}
+
+#[test]
+fn test_node_cdc_not_leader() {
+    let (mut cluster, endpoints) = new_cdc_cluster(3);
+    cluster.must_transfer_leader(1, new_peer(1, 1));
+
+    // A follower can't be subscribed.
+    let (_, events) = subscribe(&cluster, &endpoints, 2, 1, 0);
+    must_recv_error(&events);
+
+    // The subscription fails once the leader is transferred.
+    let (_, events) = subscribe(&cluster, &endpoints, 1, 1, 0);
+    must_recv_rows(&events, get_ts(&cluster));
+    cluster.must_transfer_leader(1, new_peer(2, 2));
+    must_recv_error(&events);
+
+    stop_endpoints(&endpoints);
+}
+
+#[test]
+fn test_node_cdc_multiple_subscribers() {
+    let (mut cluster, endpoints) = new_cdc_cluster(1);
+
+    let (start_ts1, commit_ts1) = (get_ts(&cluster), get_ts(&cluster));
+    must_prewrite(&mut cluster, b"k1", b"v1", start_ts1);
+    must_commit(&mut cluster, b"k1", start_ts1, commit_ts1);
+
+    // Every subscriber starts from its own checkpoint ts.
+    let (id1, events1) = subscribe(&cluster, &endpoints, 1, 1, 0);
+    let (_, events2) = subscribe(&cluster, &endpoints, 1, 1, commit_ts1);
+    assert_eq!(must_recv_rows(&events1, commit_ts1),
+               vec![new_row(b"k1", b"v1", start_ts1, commit_ts1)]);
+    assert_eq!(must_recv_rows(&events2, commit_ts1), vec![]);
+
+    let (start_ts2, commit_ts2) = (get_ts(&cluster), get_ts(&cluster));
+    must_prewrite(&mut cluster, b"k2", b"v2", start_ts2);
+    must_commit(&mut cluster, b"k2", start_ts2, commit_ts2);
+    let row = new_row(b"k2", b"v2", start_ts2, commit_ts2);
+    assert_eq!(must_recv_rows(&events1, commit_ts2), vec![row.clone()]);
+    assert_eq!(must_recv_rows(&events2, commit_ts2), vec![row]);
+
+    // Deregistering a subscriber doesn't stop the others.
+    unsubscribe(&cluster, &endpoints, 1, 1, id1);
+    let (start_ts3, commit_ts3) = (get_ts(&cluster), get_ts(&cluster));
+    must_prewrite(&mut cluster, b"k3", b"v3", start_ts3);
+    must_commit(&mut cluster, b"k3", start_ts3, commit_ts3);
+    assert_eq!(must_recv_rows(&events2, commit_ts3),
+               vec![new_row(b"k3", b"v3", start_ts3, commit_ts3)]);
+    // The stream of the deregistered subscriber ends without the new rows.
+    loop {
+        match events1.recv_timeout(Duration::from_secs(5)) {
+            Ok(Event::ResolvedTs { .. }) => {}
+            Ok(e) => panic!("unexpected event {:?}", e),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+
+    stop_endpoints(&endpoints);
+}
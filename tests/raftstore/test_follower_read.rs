@@ -0,0 +1,179 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A module contains test cases for read index based reads on Raft followers.
+
+use std::boxed::FnBox;
+use std::time::Duration;
+
+use kvproto::eraftpb::MessageType;
+use kvproto::metapb::{Peer, Region};
+use kvproto::raft_cmdpb::{RaftCmdResponse, CmdType, Request};
+use tikv::raftstore::{Error, Result};
+use tikv::raftstore::store::Msg;
+use tikv::util::{escape, HandyRwLock};
+
+use super::cluster::{Cluster, Simulator};
+use super::node::new_node_cluster;
+use super::server::new_server_cluster;
+use super::transport_simulate::*;
+use super::util::*;
+
+// Issue a follower read request on the specified peer.
+fn follower_read_on_peer<T: Simulator>(cluster: &mut Cluster<T>,
+                                       peer: Peer,
+                                       region: Region,
+                                       cmd: Request,
+                                       timeout: Duration)
+                                       -> Result<RaftCmdResponse> {
+    let mut request = new_request(region.get_id(),
+                                  region.get_region_epoch().clone(),
+                                  vec![cmd],
+                                  false);
+    request.mut_header().set_peer(peer.clone());
+    let ch = match cluster.sim.rl().get_store_sendch(peer.get_store_id()) {
+        Some(ch) => ch,
+        None => return Err(box_err!("missing sender for store {}", peer.get_store_id())),
+    };
+    wait_op!(|cb: Box<FnBox(RaftCmdResponse) + 'static + Send>| {
+                 ch.try_send(Msg::new_follower_read_cmd(request, cb)).unwrap()
+             },
+             timeout)
+        .ok_or_else(|| Error::Timeout(format!("request timeout for {:?}", timeout)))
+}
+
+fn must_follower_read_on_peer<T: Simulator>(cluster: &mut Cluster<T>,
+                                            peer: Peer,
+                                            region: Region,
+                                            key: &[u8],
+                                            value: &[u8]) {
+    let timeout = Duration::from_secs(3);
+    let mut resp = follower_read_on_peer(cluster, peer, region, new_get_cmd(key), timeout)
+        .unwrap();
+    if resp.get_header().has_error() {
+        panic!("failed to read for key {}, err {:?}",
+               escape(key),
+               resp.get_header().get_error());
+    }
+    assert_eq!(resp.get_responses().len(), 1);
+    assert_eq!(resp.get_responses()[0].get_cmd_type(), CmdType::Get);
+    let v = resp.mut_responses()[0].mut_get().take_value();
+    if v != value {
+        panic!("read key {}, expect value {}, got {}",
+               escape(key),
+               escape(value),
+               escape(&v))
+    }
+}
+
+fn test_follower_read<T: Simulator>(cluster: &mut Cluster<T>) {
+    // Avoid triggering the log compaction in this test case.
+    cluster.cfg.raft_store.raft_log_gc_threshold = 100;
+
+    let pd_client = cluster.pd_client.clone();
+    pd_client.disable_default_rule();
+    let region_id = cluster.run_conf_change();
+    pd_client.must_add_peer(region_id, new_peer(2, 2));
+    pd_client.must_add_peer(region_id, new_peer(3, 3));
+
+    let key = b"k";
+    cluster.must_put(key, b"v1");
+    cluster.must_transfer_leader(region_id, new_peer(1, 1));
+    let follower = new_peer(2, 2);
+    must_get_equal(&cluster.get_engine(2), key, b"v1");
+
+    let region = cluster.get_region(key);
+    must_follower_read_on_peer(cluster, follower.clone(), region.clone(), key, b"v1");
+
+    // Stop replicating logs to the follower, so it can't apply up to the read index.
+    cluster.add_send_filter(CloneFilterFactory(RegionPacketFilter::new(region_id, 2)
+        .msg_type(MessageType::MsgAppend)
+        .direction(Direction::Recv)));
+    cluster.must_put(key, b"v2");
+    let res = follower_read_on_peer(cluster,
+                                    follower.clone(),
+                                    region.clone(),
+                                    new_get_cmd(key),
+                                    Duration::from_millis(500));
+    assert!(res.is_err(), "stale read on follower: {:?}", res);
+
+    cluster.clear_send_filters();
+    must_follower_read_on_peer(cluster, follower.clone(), region.clone(), key, b"v2");
+
+    // Writes are never served by followers.
+    let resp = follower_read_on_peer(cluster,
+                                     follower,
+                                     region,
+                                     new_put_cmd(key, b"v3"),
+                                     Duration::from_secs(3))
+        .unwrap();
+    assert!(resp.get_header().has_error(), "{:?}", resp);
+}
+
+fn test_follower_read_index_dropped<T: Simulator>(cluster: &mut Cluster<T>) {
+    let pd_client = cluster.pd_client.clone();
+    pd_client.disable_default_rule();
+    let region_id = cluster.run_conf_change();
+    pd_client.must_add_peer(region_id, new_peer(2, 2));
+    pd_client.must_add_peer(region_id, new_peer(3, 3));
+
+    let key = b"k";
+    cluster.must_put(key, b"v1");
+    cluster.must_transfer_leader(region_id, new_peer(1, 1));
+    let follower = new_peer(2, 2);
+    must_get_equal(&cluster.get_engine(2), key, b"v1");
+    let region = cluster.get_region(key);
+
+    // The read index request never reaches the leader, the read fails instead of hanging.
+    cluster.add_send_filter(CloneFilterFactory(RegionPacketFilter::new(region_id, 2)
+        .msg_type(MessageType::MsgReadIndex)
+        .direction(Direction::Send)));
+    let resp = follower_read_on_peer(cluster,
+                                     follower.clone(),
+                                     region.clone(),
+                                     new_get_cmd(key),
+                                     Duration::from_secs(3))
+        .unwrap();
+    assert!(resp.get_header().get_error().has_stale_command(), "{:?}", resp);
+
+    cluster.clear_send_filters();
+    must_follower_read_on_peer(cluster, follower, region, key, b"v1");
+}
+
+#[test]
+fn test_node_follower_read() {
+    let count = 3;
+    let mut cluster = new_node_cluster(0, count);
+    test_follower_read(&mut cluster);
+}
+
+#[test]
+fn test_server_follower_read() {
+    let count = 3;
+    let mut cluster = new_server_cluster(0, count);
+    test_follower_read(&mut cluster);
+}
+
+#[test]
+fn test_node_follower_read_index_dropped() {
+    let count = 3;
+    let mut cluster = new_node_cluster(0, count);
+    test_follower_read_index_dropped(&mut cluster);
+}
+
+#[test]
+fn test_server_follower_read_index_dropped() {
+    let count = 3;
+    let mut cluster = new_server_cluster(0, count);
+    test_follower_read_index_dropped(&mut cluster);
+}
@@ -11,6 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::mpsc;
 use std::time::Duration;
 use std::{thread, fs};
 use rand::{self, Rng};
@@ -81,6 +82,18 @@ fn test_base_split_region<T: Simulator>(cluster: &mut Cluster<T>, right_derive:
                 "{:?}",
                 resp);
 
+        // A batch with a key out of the region is rejected as a whole.
+        let put = util::new_request(left.get_id(),
+                                    left.get_region_epoch().clone(),
+                                    vec![util::new_put_cmd(left_key, b"vv4"),
+                                         util::new_put_cmd(right_key, b"vv4")],
+                                    false);
+        let resp = cluster.call_command_on_leader(put, Duration::from_secs(5)).unwrap();
+        assert!(resp.get_header().get_error().has_key_not_in_region(),
+                "{:?}",
+                resp);
+        assert_eq!(cluster.get(left_key).unwrap(), b"vv1".to_vec());
+        assert_eq!(cluster.get(right_key).unwrap(), b"vv3".to_vec());
     }
 }
 
@@ -587,6 +600,92 @@ fn test_node_split_stale_epoch_right_derive() {
     test_split_stale_epoch(&mut cluster, true);
 }
 
+fn test_split_region_with_callback<T: Simulator>(cluster: &mut Cluster<T>) {
+    cluster.run();
+    cluster.must_put(b"k1", b"v1");
+    cluster.must_put(b"k3", b"v3");
+    let region = cluster.get_region(b"k1");
+
+    let (tx, rx) = mpsc::channel();
+    cluster.split_region(&region, b"k2", Box::new(move |resp| tx.send(resp).unwrap()));
+    let resp = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(!resp.get_header().has_error(), "{:?}", resp);
+    let split = resp.get_admin_response().get_split();
+    assert_eq!(split.get_left().get_end_key(), b"k2");
+    assert_eq!(split.get_right().get_start_key(), b"k2");
+
+    // The region has been split, so the old epoch is stale.
+    let (tx, rx) = mpsc::channel();
+    cluster.split_region(&region, b"k3", Box::new(move |resp| tx.send(resp).unwrap()));
+    let resp = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(resp.get_header().get_error().has_stale_epoch(), "{:?}", resp);
+
+    // The split key is not in the region any more.
+    let region = if split.get_left().get_id() == region.get_id() {
+        split.get_left().clone()
+    } else {
+        split.get_right().clone()
+    };
+    let (tx, rx) = mpsc::channel();
+    cluster.split_region(&region, b"k2", Box::new(move |resp| tx.send(resp).unwrap()));
+    let resp = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(resp.get_header().has_error(), "{:?}", resp);
+}
+
+fn test_batch_split_region<T: Simulator>(cluster: &mut Cluster<T>) {
+    cluster.run();
+    for k in &[b"k1", b"k2", b"k3", b"k4", b"k5"] {
+        cluster.must_put(*k, b"v");
+    }
+    let region = cluster.get_region(b"k1");
+
+    let (tx, rx) = mpsc::channel();
+    let keys = vec![b"k4".to_vec(), b"k2".to_vec(), b"k3".to_vec(), b"k2".to_vec()];
+    cluster.batch_split_region(&region, keys, Box::new(move |res| tx.send(res).unwrap()));
+    let regions = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+    let bounds: Vec<_> = regions.iter()
+        .map(|r| (r.get_start_key().to_vec(), r.get_end_key().to_vec()))
+        .collect();
+    assert_eq!(bounds,
+               vec![(b"".to_vec(), b"k2".to_vec()),
+                    (b"k2".to_vec(), b"k3".to_vec()),
+                    (b"k3".to_vec(), b"k4".to_vec()),
+                    (b"k4".to_vec(), b"".to_vec())]);
+    for k in &[b"k1", b"k2", b"k3", b"k4", b"k5"] {
+        assert_eq!(cluster.must_get(*k), Some(b"v".to_vec()));
+    }
+
+    // The epoch is stale after the splits, so nothing is split.
+    let (tx, rx) = mpsc::channel();
+    let keys = vec![b"k41".to_vec(), b"k42".to_vec()];
+    cluster.batch_split_region(&region, keys, Box::new(move |res| tx.send(res).unwrap()));
+    let err = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap_err();
+    assert!(err.has_stale_epoch(), "{:?}", err);
+}
+
+#[test]
+fn test_node_batch_split_region() {
+    let mut cluster = new_node_cluster(0, 3);
+    test_batch_split_region(&mut cluster);
+}
+
+#[test]
+fn test_server_batch_split_region() {
+    let mut cluster = new_server_cluster(0, 3);
+    test_batch_split_region(&mut cluster);
+}
+
+#[test]
+fn test_node_split_region_with_callback() {
+    let mut cluster = new_node_cluster(0, 3);
+    test_split_region_with_callback(&mut cluster);
+}
+
+#[test]
+fn test_server_split_region_with_callback() {
+    let mut cluster = new_server_cluster(0, 3);
+    test_split_region_with_callback(&mut cluster);
+}
 
 // For the peer which is the leader of the region before split,
 // it should campaigns immediately. and then this peer may take the leadership earlier.
@@ -13,7 +13,7 @@
 
 use super::sync_storage::SyncStorage;
 use kvproto::kvrpcpb::{Context, LockInfo};
-use tikv::storage::{self, Key, Value, KvPair, Mutation, make_key};
+use tikv::storage::{self, Key, Value, KvPair, Mutation, RangeChecksum, make_key};
 use tikv::storage::mvcc::{self, MAX_TXN_WRITE_SIZE};
 use tikv::storage::txn;
 use raftstore::cluster::Cluster;
@@ -230,7 +230,32 @@ impl AssertionStorage {
                    ts: u64,
                    expect: Vec<Option<(&[u8], &[u8])>>) {
         let key_address = make_key(start_key);
-        let result = self.store.scan(self.ctx.clone(), key_address, limit, false, ts).unwrap();
+        let result = self.store
+            .scan(self.ctx.clone(), key_address, None, limit, false, ts)
+            .unwrap();
+        let result: Vec<Option<KvPair>> = result.into_iter()
+            .map(Result::ok)
+            .collect();
+        let expect: Vec<Option<KvPair>> = expect.into_iter()
+            .map(|x| x.map(|(k, v)| (k.to_vec(), v.to_vec())))
+            .collect();
+        assert_eq!(result, expect);
+    }
+
+    pub fn scan_range_ok(&self,
+                         start_key: &[u8],
+                         end_key: &[u8],
+                         limit: usize,
+                         ts: u64,
+                         expect: Vec<Option<(&[u8], &[u8])>>) {
+        let result = self.store
+            .scan(self.ctx.clone(),
+                  make_key(start_key),
+                  Some(make_key(end_key)),
+                  limit,
+                  false,
+                  ts)
+            .unwrap();
         let result: Vec<Option<KvPair>> = result.into_iter()
             .map(Result::ok)
             .collect();
@@ -246,7 +271,9 @@ impl AssertionStorage {
                             ts: u64,
                             expect: Vec<Option<&[u8]>>) {
         let key_address = make_key(start_key);
-        let result = self.store.scan(self.ctx.clone(), key_address, limit, true, ts).unwrap();
+        let result = self.store
+            .scan(self.ctx.clone(), key_address, None, limit, true, ts)
+            .unwrap();
         let result: Vec<Option<KvPair>> = result.into_iter()
             .map(Result::ok)
             .collect();
@@ -345,21 +372,129 @@ impl AssertionStorage {
         panic!("failed with 3 retry!");
     }
 
+    pub fn checksum_ok(&self,
+                       start_key: &[u8],
+                       end_key: Option<&[u8]>,
+                       ts: u64,
+                       expect: Vec<(&[u8], &[u8])>) {
+        let mut checksum = RangeChecksum::default();
+        for (k, v) in expect {
+            checksum.update(k, v);
+        }
+        let res = self.store
+            .checksum(self.ctx.clone(), make_key(start_key), end_key.map(make_key), ts)
+            .unwrap();
+        assert_eq!(res, checksum);
+    }
+
+    pub fn checksum_err(&self, start_key: &[u8], end_key: Option<&[u8]>, ts: u64) {
+        assert!(self.store
+            .checksum(self.ctx.clone(), make_key(start_key), end_key.map(make_key), ts)
+            .is_err());
+    }
+
     pub fn raw_get_ok(&self, key: Vec<u8>, value: Option<Vec<u8>>) {
-        assert_eq!(self.store.raw_get(self.ctx.clone(), key).unwrap(), value);
+        assert_eq!(self.store.raw_get(self.ctx.clone(), "", key).unwrap(), value);
     }
 
     pub fn raw_put_ok(&self, key: Vec<u8>, value: Vec<u8>) {
-        self.store.raw_put(self.ctx.clone(), key, value).unwrap();
+        self.store.raw_put(self.ctx.clone(), "", key, value, 0).unwrap();
     }
 
     pub fn raw_delete_ok(&self, key: Vec<u8>) {
-        self.store.raw_delete(self.ctx.clone(), key).unwrap()
+        self.store.raw_delete(self.ctx.clone(), "", key).unwrap()
+    }
+
+    pub fn raw_put_ttl_ok(&self, cf: &str, key: Vec<u8>, value: Vec<u8>, ttl: u64) {
+        self.store.raw_put(self.ctx.clone(), cf, key, value, ttl).unwrap();
+    }
+
+    pub fn raw_put_ttl_err(&self, cf: &str, key: Vec<u8>, value: Vec<u8>, ttl: u64) {
+        assert!(self.store.raw_put(self.ctx.clone(), cf, key, value, ttl).is_err());
+    }
+
+    pub fn raw_compare_and_swap_ok(&self,
+                                   key: &[u8],
+                                   previous_value: Option<&[u8]>,
+                                   value: &[u8],
+                                   expect: (Option<&[u8]>, bool)) {
+        let res = self.store
+            .raw_compare_and_swap(self.ctx.clone(),
+                                  "",
+                                  key.to_vec(),
+                                  previous_value.map(|v| v.to_vec()),
+                                  value.to_vec(),
+                                  0)
+            .unwrap();
+        assert_eq!(res, (expect.0.map(|v| v.to_vec()), expect.1));
+    }
+
+    pub fn raw_get_cf_ok(&self, cf: &str, key: Vec<u8>, value: Option<Vec<u8>>) {
+        assert_eq!(self.store.raw_get(self.ctx.clone(), cf, key).unwrap(), value);
+    }
+
+    pub fn raw_put_cf_ok(&self, cf: &str, key: Vec<u8>, value: Vec<u8>) {
+        self.store.raw_put(self.ctx.clone(), cf, key, value, 0).unwrap();
+    }
+
+    pub fn raw_delete_range_ok(&self, cf: &str, start_key: Vec<u8>, end_key: Vec<u8>) {
+        self.store.raw_delete_range(self.ctx.clone(), cf, start_key, end_key).unwrap();
+    }
+
+    pub fn raw_batch_get_ok(&self, keys: Vec<&[u8]>, expect: Vec<(&[u8], &[u8])>) {
+        self.raw_batch_get_cf_ok("", keys, expect)
+    }
+
+    pub fn raw_batch_get_cf_ok(&self, cf: &str, keys: Vec<&[u8]>, expect: Vec<(&[u8], &[u8])>) {
+        let keys = keys.into_iter().map(|k| k.to_vec()).collect();
+        let result: Vec<KvPair> = self.store
+            .raw_batch_get(self.ctx.clone(), cf, keys)
+            .unwrap()
+            .into_iter()
+            .map(|x| x.unwrap())
+            .collect();
+        let expect: Vec<KvPair> =
+            expect.into_iter().map(|(k, v)| (k.to_vec(), v.to_vec())).collect();
+        assert_eq!(result, expect);
+    }
+
+    pub fn raw_batch_put_ok(&self, pairs: Vec<(&[u8], &[u8])>) {
+        let pairs = pairs.into_iter().map(|(k, v)| (k.to_vec(), v.to_vec())).collect();
+        self.store.raw_batch_put(self.ctx.clone(), "", pairs, 0).unwrap();
+    }
+
+    pub fn raw_batch_delete_ok(&self, keys: Vec<&[u8]>) {
+        let keys = keys.into_iter().map(|k| k.to_vec()).collect();
+        self.store.raw_batch_delete(self.ctx.clone(), "", keys).unwrap();
     }
 
     pub fn raw_scan_ok(&self, start_key: Vec<u8>, limit: usize, expect: Vec<(&[u8], &[u8])>) {
+        self.raw_scan_cf_ok("", start_key, limit, expect)
+    }
+
+    pub fn raw_scan_cf_ok(&self,
+                          cf: &str,
+                          start_key: Vec<u8>,
+                          limit: usize,
+                          expect: Vec<(&[u8], &[u8])>) {
+        let result: Vec<KvPair> = self.store
+            .raw_scan(self.ctx.clone(), cf, start_key, None, limit)
+            .unwrap()
+            .into_iter()
+            .map(|x| x.unwrap())
+            .collect();
+        let expect: Vec<KvPair> =
+            expect.into_iter().map(|(k, v)| (k.to_vec(), v.to_vec())).collect();
+        assert_eq!(result, expect);
+    }
+
+    pub fn raw_scan_range_ok(&self,
+                             start_key: Vec<u8>,
+                             end_key: Vec<u8>,
+                             limit: usize,
+                             expect: Vec<(&[u8], &[u8])>) {
         let result: Vec<KvPair> = self.store
-            .raw_scan(self.ctx.clone(), start_key, limit)
+            .raw_scan(self.ctx.clone(), "", start_key, Some(end_key), limit)
             .unwrap()
             .into_iter()
             .map(|x| x.unwrap())
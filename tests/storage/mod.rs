@@ -16,4 +16,5 @@ pub mod sync_storage;
 pub mod assert_storage;
 mod test_storage;
 mod test_raft_storage;
+mod test_gc_worker;
 pub mod util;
@@ -14,7 +14,8 @@
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use tikv::storage::{Storage, Engine, Key, Value, KvPair, Mutation, Result, Options};
+use tikv::storage::{Storage, Engine, Key, Value, KvPair, Mutation, Result, Options,
+                    RangeChecksum};
 use tikv::storage::config::Config;
 use kvproto::kvrpcpb::{Context, LockInfo};
 
@@ -64,6 +65,7 @@ impl SyncStorage {
     pub fn scan(&self,
                 ctx: Context,
                 key: Key,
+                end_key: Option<Key>,
                 limit: usize,
                 key_only: bool,
                 start_ts: u64)
@@ -72,6 +74,7 @@ impl SyncStorage {
                 self.store
                     .async_scan(ctx,
                                 key,
+                                end_key,
                                 limit,
                                 start_ts,
                                 Options::new(0, false, key_only),
@@ -124,26 +127,99 @@ impl SyncStorage {
         wait_op!(|cb| self.store.async_gc(ctx, safe_point, cb).unwrap()).unwrap()
     }
 
-    pub fn raw_get(&self, ctx: Context, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
-        wait_op!(|cb| self.store.async_raw_get(ctx, key, cb).unwrap()).unwrap()
+    pub fn checksum(&self,
+                    ctx: Context,
+                    start_key: Key,
+                    end_key: Option<Key>,
+                    start_ts: u64)
+                    -> Result<RangeChecksum> {
+        wait_op!(|cb| {
+                self.store
+                    .async_checksum(ctx, start_key, end_key, start_ts, cb)
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    pub fn raw_get(&self, ctx: Context, cf: &str, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        wait_op!(|cb| self.store.async_raw_get(ctx, cf, key, cb).unwrap()).unwrap()
+    }
+
+    pub fn raw_put(&self,
+                   ctx: Context,
+                   cf: &str,
+                   key: Vec<u8>,
+                   value: Vec<u8>,
+                   ttl: u64)
+                   -> Result<()> {
+        wait_op!(|cb| self.store.async_raw_put(ctx, cf, key, value, ttl, cb).unwrap()).unwrap()
+    }
+
+    pub fn raw_delete(&self, ctx: Context, cf: &str, key: Vec<u8>) -> Result<()> {
+        wait_op!(|cb| self.store.async_raw_delete(ctx, cf, key, cb).unwrap()).unwrap()
+    }
+
+    pub fn raw_batch_get(&self,
+                         ctx: Context,
+                         cf: &str,
+                         keys: Vec<Vec<u8>>)
+                         -> Result<Vec<Result<KvPair>>> {
+        wait_op!(|cb| self.store.async_raw_batch_get(ctx, cf, keys, cb).unwrap()).unwrap()
     }
 
-    pub fn raw_put(&self, ctx: Context, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
-        wait_op!(|cb| self.store.async_raw_put(ctx, key, value, cb).unwrap()).unwrap()
+    pub fn raw_batch_put(&self,
+                         ctx: Context,
+                         cf: &str,
+                         pairs: Vec<KvPair>,
+                         ttl: u64)
+                         -> Result<()> {
+        wait_op!(|cb| self.store.async_raw_batch_put(ctx, cf, pairs, ttl, cb).unwrap()).unwrap()
     }
 
-    pub fn raw_delete(&self, ctx: Context, key: Vec<u8>) -> Result<()> {
-        wait_op!(|cb| self.store.async_raw_delete(ctx, key, cb).unwrap()).unwrap()
+    pub fn raw_batch_delete(&self, ctx: Context, cf: &str, keys: Vec<Vec<u8>>) -> Result<()> {
+        wait_op!(|cb| self.store.async_raw_batch_delete(ctx, cf, keys, cb).unwrap()).unwrap()
+    }
+
+    pub fn raw_compare_and_swap(&self,
+                                ctx: Context,
+                                cf: &str,
+                                key: Vec<u8>,
+                                previous_value: Option<Vec<u8>>,
+                                value: Vec<u8>,
+                                ttl: u64)
+                                -> Result<(Option<Value>, bool)> {
+        wait_op!(|cb| {
+                self.store
+                    .async_raw_compare_and_swap(ctx, cf, key, previous_value, value, ttl, cb)
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    pub fn raw_delete_range(&self,
+                            ctx: Context,
+                            cf: &str,
+                            start_key: Vec<u8>,
+                            end_key: Vec<u8>)
+                            -> Result<()> {
+        wait_op!(|cb| {
+                self.store
+                    .async_raw_delete_range(ctx, cf, start_key, end_key, cb)
+                    .unwrap()
+            })
+            .unwrap()
     }
 
     pub fn raw_scan(&self,
                     ctx: Context,
+                    cf: &str,
                     start_key: Vec<u8>,
+                    end_key: Option<Vec<u8>>,
                     limit: usize)
                     -> Result<Vec<Result<KvPair>>> {
         wait_op!(|cb| {
                 self.store
-                    .async_raw_scan(ctx, start_key, limit, cb)
+                    .async_raw_scan(ctx, cf, start_key, end_key, limit, cb)
                     .unwrap()
             })
             .unwrap()
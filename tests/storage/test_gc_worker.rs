@@ -0,0 +1,116 @@
+// Copyright 2017 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tikv::raftstore::store::keys;
+use tikv::server::gc_worker::GcWorker;
+use tikv::storage::{Mutation, make_key, CF_WRITE};
+use tikv::storage::config::Config;
+use tikv::util::HandyRwLock;
+use tikv::util::rocksdb::get_cf_handle;
+use raftstore::cluster::Cluster;
+use raftstore::pd::TestPdClient;
+use raftstore::server::ServerCluster;
+use super::sync_storage::SyncStorage;
+use super::util::new_raft_engine;
+
+fn has_version(cluster: &Cluster<ServerCluster>, store_id: u64, key: &[u8], ts: u64) -> bool {
+    let db = cluster.get_engine(store_id);
+    let handle = get_cf_handle(&db, CF_WRITE).unwrap();
+    let key = keys::data_key(make_key(key).append_ts(ts).encoded());
+    db.get_cf(handle, &key).unwrap().is_some()
+}
+
+fn must_have_version(cluster: &Cluster<ServerCluster>,
+                     store_id: u64,
+                     key: &[u8],
+                     ts: u64,
+                     exist: bool) {
+    let timer = Instant::now();
+    while has_version(cluster, store_id, key, ts) != exist {
+        if timer.elapsed() > Duration::from_secs(5) {
+            panic!("version {} of {:?} on store {} should exist: {}",
+                   ts,
+                   key,
+                   store_id,
+                   exist);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn start_gc_worker(cluster: &Cluster<ServerCluster>,
+                   store_id: u64,
+                   cfg: &Config)
+                   -> GcWorker<TestPdClient> {
+    let engine = cluster.sim.rl().storages[&store_id].clone();
+    let mut worker = GcWorker::new(store_id,
+                                   engine,
+                                   cluster.get_engine(store_id),
+                                   cluster.pd_client.clone(),
+                                   None,
+                                   cfg);
+    worker.start().unwrap();
+    worker
+}
+
+#[test]
+fn test_gc_worker_on_leader() {
+    let (cluster, engine, ctx) = new_raft_engine(3, "");
+    let storage = SyncStorage::from_engine(engine, &Config::default());
+    let key = make_key(b"k1");
+    for &(start_ts, commit_ts) in &[(10, 15), (20, 25)] {
+        storage.prewrite(ctx.clone(),
+                      vec![Mutation::Put((key.clone(), b"value".to_vec()))],
+                      b"k1".to_vec(),
+                      start_ts)
+            .unwrap();
+        storage.commit(ctx.clone(), vec![key.clone()], start_ts, commit_ts).unwrap();
+    }
+    let store_ids: Vec<u64> = cluster.engines.keys().cloned().collect();
+    for &id in &store_ids {
+        must_have_version(&cluster, id, b"k1", 15, true);
+    }
+    cluster.pd_client.set_gc_safe_point(30);
+
+    let mut cfg = Config::default();
+    cfg.gc_safe_point_poll_interval = 50;
+    // Always collect, the versions are still in the memtable without MVCC properties.
+    cfg.gc_ratio_threshold = 0.9;
+
+    // The workers of the followers skip the region.
+    let leader_store = ctx.get_peer().get_store_id();
+    let mut workers: Vec<_> = store_ids.iter()
+        .filter(|&&id| id != leader_store)
+        .map(|&id| start_gc_worker(&cluster, id, &cfg))
+        .collect();
+    thread::sleep(Duration::from_millis(500));
+    for &id in &store_ids {
+        assert!(has_version(&cluster, id, b"k1", 15));
+    }
+
+    // The worker of the leader collects it, and the deletes are replicated to the followers.
+    workers.push(start_gc_worker(&cluster, leader_store, &cfg));
+    for &id in &store_ids {
+        must_have_version(&cluster, id, b"k1", 15, false);
+        must_have_version(&cluster, id, b"k1", 25, true);
+    }
+    assert_eq!(storage.get(ctx.clone(), &key, 30).unwrap().unwrap(),
+               b"value".to_vec());
+
+    for worker in &mut workers {
+        worker.stop().unwrap().join().unwrap();
+    }
+}
@@ -50,7 +50,7 @@ fn test_raft_storage() {
     ctx.set_region_id(region_id + 1);
     assert!(storage.get(ctx.clone(), &key, 20).is_err());
     assert!(storage.batch_get(ctx.clone(), &[key.clone()], 20).is_err());
-    assert!(storage.scan(ctx.clone(), key.clone(), 1, false, 20).is_err());
+    assert!(storage.scan(ctx.clone(), key.clone(), None, 1, false, 20).is_err());
     assert!(storage.scan_lock(ctx.clone(), 20).is_err());
 }
 
@@ -104,7 +104,7 @@ fn test_raft_storage_store_not_match() {
         panic!("expect store_not_match, but got {:?}", res);
     }
     assert!(storage.batch_get(ctx.clone(), &[key.clone()], 20).is_err());
-    assert!(storage.scan(ctx.clone(), key.clone(), 1, false, 20).is_err());
+    assert!(storage.scan(ctx.clone(), key.clone(), None, 1, false, 20).is_err());
     assert!(storage.scan_lock(ctx.clone(), 20).is_err());
 }
 
@@ -19,7 +19,7 @@ use std::thread;
 use rand::random;
 use super::sync_storage::SyncStorage;
 use kvproto::kvrpcpb::{Context, LockInfo};
-use tikv::storage::{self, Mutation, Key, make_key, ALL_CFS, Storage};
+use tikv::storage::{self, Mutation, Key, make_key, ALL_CFS, CF_RAW, Storage};
 use tikv::storage::engine::{self, TEMP_DIR, Engine};
 use tikv::storage::txn::{GC_BATCH_SIZE, RESOLVE_LOCK_BATCH_SIZE};
 use tikv::storage::mvcc::MAX_TXN_WRITE_SIZE;
@@ -226,6 +226,61 @@ fn test_txn_store_scan_key_only() {
     store.scan_key_only_ok(b"AA", 2, 10, vec![Some(b"B"), Some(b"C")]);
 }
 
+#[test]
+fn test_txn_store_scan_with_end_key() {
+    let store = AssertionStorage::default();
+    store.put_ok(b"A", b"A10", 5, 10);
+    store.put_ok(b"C", b"C10", 5, 10);
+    store.put_ok(b"E", b"E10", 5, 10);
+
+    // end key is exclusive.
+    store.scan_range_ok(b"", b"C", 5, 10, vec![Some((b"A", b"A10"))]);
+    store.scan_range_ok(b"",
+                        b"C\x00",
+                        5,
+                        10,
+                        vec![Some((b"A", b"A10")), Some((b"C", b"C10"))]);
+    store.scan_range_ok(b"B", b"E", 5, 10, vec![Some((b"C", b"C10"))]);
+    store.scan_range_ok(b"C", b"C", 5, 10, vec![]);
+    store.scan_range_ok(b"F", b"G", 5, 10, vec![]);
+    // limit is still respected.
+    store.scan_range_ok(b"", b"F", 2, 10, vec![Some((b"A", b"A10")), Some((b"C", b"C10"))]);
+
+    // a lock at or beyond the end key is out of range.
+    store.prewrite_ok(vec![Mutation::Put((make_key(b"C"), b"C20".to_vec()))], b"C", 20);
+    store.scan_range_ok(b"", b"C", 5, 25, vec![Some((b"A", b"A10"))]);
+    store.scan_range_ok(b"", b"C\x00", 5, 25, vec![Some((b"A", b"A10")), None]);
+}
+
+#[test]
+fn test_txn_store_checksum() {
+    let store = AssertionStorage::default();
+    store.checksum_ok(b"", None, 10, vec![]);
+
+    store.put_ok(b"A", b"A10", 5, 10);
+    store.put_ok(b"B", b"B10", 5, 10);
+    store.put_ok(b"C", b"C10", 5, 10);
+    store.put_ok(b"B", b"B20", 15, 20);
+    store.delete_ok(b"C", 15, 20);
+
+    store.checksum_ok(b"", None, 5, vec![]);
+    store.checksum_ok(b"",
+                      None,
+                      10,
+                      vec![(b"A", b"A10"), (b"B", b"B10"), (b"C", b"C10")]);
+    store.checksum_ok(b"", None, 20, vec![(b"A", b"A10"), (b"B", b"B20")]);
+    store.checksum_ok(b"B", None, 20, vec![(b"B", b"B20")]);
+    store.checksum_ok(b"", Some(b"B"), 20, vec![(b"A", b"A10")]);
+    store.checksum_ok(b"B", Some(b"B"), 20, vec![]);
+
+    // Locks in the range fail the checksum.
+    store.prewrite_ok(vec![Mutation::Put((make_key(b"D"), b"D30".to_vec()))], b"D", 30);
+    store.checksum_err(b"", None, 35);
+    store.checksum_ok(b"", Some(b"D"), 35, vec![(b"A", b"A10"), (b"B", b"B20")]);
+    // Locks newer than the snapshot are ignored.
+    store.checksum_ok(b"", None, 25, vec![(b"A", b"A10"), (b"B", b"B20")]);
+}
+
 fn lock(key: &[u8], primary: &[u8], ts: u64) -> LockInfo {
     let mut lock = LockInfo::new();
     lock.set_key(key.to_vec());
@@ -443,6 +498,160 @@ fn test_txn_store_rawkv() {
     store.raw_scan_ok(b"k5".to_vec(), 1, vec![]);
 }
 
+#[test]
+fn test_txn_store_raw_batch() {
+    let store = AssertionStorage::default();
+    store.raw_batch_get_ok(vec![b"k1", b"k2"], vec![]);
+
+    store.raw_batch_put_ok(vec![(b"k1", b"v1"), (b"k2", b"v2"), (b"k3", b"v3")]);
+    store.raw_batch_get_ok(vec![b"k1", b"k2", b"k3"],
+                           vec![(b"k1", b"v1"), (b"k2", b"v2"), (b"k3", b"v3")]);
+    // missing keys are skipped and results follow the order of the request.
+    store.raw_batch_get_ok(vec![b"k3", b"k0", b"k1"], vec![(b"k3", b"v3"), (b"k1", b"v1")]);
+
+    store.raw_batch_put_ok(vec![(b"k2", b"v22"), (b"k4", b"v4")]);
+    store.raw_batch_get_ok(vec![b"k2", b"k4"], vec![(b"k2", b"v22"), (b"k4", b"v4")]);
+
+    store.raw_batch_delete_ok(vec![b"k1", b"k3", b"k5"]);
+    store.raw_batch_get_ok(vec![b"k1", b"k2", b"k3", b"k4"],
+                           vec![(b"k2", b"v22"), (b"k4", b"v4")]);
+    store.raw_get_ok(b"k1".to_vec(), None);
+}
+
+#[test]
+fn test_txn_store_raw_cf() {
+    let store = AssertionStorage::default();
+    store.raw_put_cf_ok("", b"k1".to_vec(), b"v1".to_vec());
+    store.raw_put_cf_ok("default", b"k2".to_vec(), b"v2".to_vec());
+    // an empty cf name means the default cf.
+    store.raw_get_ok(b"k1".to_vec(), Some(b"v1".to_vec()));
+    store.raw_get_ok(b"k2".to_vec(), Some(b"v2".to_vec()));
+    store.raw_get_cf_ok("default", b"k1".to_vec(), Some(b"v1".to_vec()));
+}
+
+#[test]
+fn test_txn_store_raw_ttl() {
+    let mut config = Config::default();
+    config.enable_ttl = true;
+    let store = AssertionStorage {
+        ctx: Context::new(),
+        store: SyncStorage::new(&config),
+    };
+    store.raw_put_ttl_ok(CF_RAW, b"k1".to_vec(), b"v1".to_vec(), 1);
+    store.raw_put_ttl_ok(CF_RAW, b"k2".to_vec(), b"v2".to_vec(), 0);
+    store.raw_put_ttl_ok(CF_RAW, b"k3".to_vec(), b"v3".to_vec(), 1000);
+    // values are returned without the expire time.
+    store.raw_get_cf_ok(CF_RAW, b"k2".to_vec(), Some(b"v2".to_vec()));
+    store.raw_get_cf_ok(CF_RAW, b"k3".to_vec(), Some(b"v3".to_vec()));
+
+    thread::sleep(Duration::from_millis(2100));
+    store.raw_get_cf_ok(CF_RAW, b"k1".to_vec(), None);
+    store.raw_get_cf_ok(CF_RAW, b"k2".to_vec(), Some(b"v2".to_vec()));
+    store.raw_batch_get_cf_ok(CF_RAW,
+                              vec![b"k1", b"k2", b"k3"],
+                              vec![(b"k2", b"v2"), (b"k3", b"v3")]);
+    store.raw_scan_cf_ok(CF_RAW, b"".to_vec(), 2, vec![(b"k2", b"v2"), (b"k3", b"v3")]);
+    // an expired key can be written again.
+    store.raw_put_cf_ok(CF_RAW, b"k1".to_vec(), b"v11".to_vec());
+    store.raw_get_cf_ok(CF_RAW, b"k1".to_vec(), Some(b"v11".to_vec()));
+
+    // the raw values in the default cf never expire.
+    store.raw_put_ttl_err("", b"k1".to_vec(), b"v1".to_vec(), 1);
+    store.raw_put_ok(b"k1".to_vec(), b"v1".to_vec());
+    store.raw_get_ok(b"k1".to_vec(), Some(b"v1".to_vec()));
+    // transactions are not affected.
+    store.put_ok(b"x", b"x1", 5, 10);
+    store.get_ok(b"x", 15, b"x1");
+}
+
+#[test]
+fn test_txn_store_raw_compare_and_swap() {
+    let store = AssertionStorage::default();
+    // the key doesn't exist.
+    store.raw_compare_and_swap_ok(b"k", Some(b"v0"), b"v1", (None, false));
+    store.raw_get_ok(b"k".to_vec(), None);
+    store.raw_compare_and_swap_ok(b"k", None, b"v1", (None, true));
+    store.raw_get_ok(b"k".to_vec(), Some(b"v1".to_vec()));
+
+    store.raw_compare_and_swap_ok(b"k", None, b"v2", (Some(b"v1"), false));
+    store.raw_compare_and_swap_ok(b"k", Some(b"v0"), b"v2", (Some(b"v1"), false));
+    store.raw_get_ok(b"k".to_vec(), Some(b"v1".to_vec()));
+    store.raw_compare_and_swap_ok(b"k", Some(b"v1"), b"v2", (Some(b"v1"), true));
+    store.raw_get_ok(b"k".to_vec(), Some(b"v2".to_vec()));
+}
+
+#[test]
+fn test_txn_store_raw_compare_and_swap_concurrently() {
+    let store = AssertionStorage::default();
+    store.raw_put_ok(b"counter".to_vec(), 0u64.to_string().into_bytes());
+    let threads = 8;
+    let incs = 10;
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let store = store.store.clone();
+            thread::spawn(move || for _ in 0..incs {
+                loop {
+                    let cur = store.raw_get(Context::new(), "", b"counter".to_vec())
+                        .unwrap()
+                        .unwrap();
+                    let n: u64 = String::from_utf8(cur.clone()).unwrap().parse().unwrap();
+                    let next = (n + 1).to_string().into_bytes();
+                    let (_, succeed) = store.raw_compare_and_swap(Context::new(),
+                                              "",
+                                              b"counter".to_vec(),
+                                              Some(cur),
+                                              next,
+                                              0)
+                        .unwrap();
+                    if succeed {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+    store.raw_get_ok(b"counter".to_vec(), Some((threads * incs).to_string().into_bytes()));
+}
+
+#[test]
+fn test_txn_store_raw_delete_range() {
+    let store = AssertionStorage::default();
+    for key in &[b"a", b"b", b"c", b"d"] {
+        store.raw_put_ok(key.to_vec(), key.to_vec());
+    }
+
+    store.raw_delete_range_ok("", b"b".to_vec(), b"d".to_vec());
+    store.raw_scan_ok(b"".to_vec(), 10, vec![(b"a", b"a"), (b"d", b"d")]);
+
+    store.raw_delete_range_ok("default", b"a".to_vec(), b"z".to_vec());
+    store.raw_get_ok(b"a".to_vec(), None);
+    store.raw_get_ok(b"d".to_vec(), None);
+}
+
+#[test]
+fn test_txn_store_raw_scan_with_end_key() {
+    let store = AssertionStorage::default();
+    store.raw_put_ok(b"k1".to_vec(), b"v1".to_vec());
+    store.raw_put_ok(b"k2".to_vec(), b"v2".to_vec());
+    store.raw_put_ok(b"k3".to_vec(), b"v3".to_vec());
+
+    store.raw_scan_range_ok(b"".to_vec(), b"k2".to_vec(), 5, vec![(b"k1", b"v1")]);
+    store.raw_scan_range_ok(b"k1".to_vec(),
+                            b"k3".to_vec(),
+                            5,
+                            vec![(b"k1", b"v1"), (b"k2", b"v2")]);
+    store.raw_scan_range_ok(b"k2".to_vec(),
+                            b"k2\x00".to_vec(),
+                            5,
+                            vec![(b"k2", b"v2")]);
+    store.raw_scan_range_ok(b"k2".to_vec(), b"k2".to_vec(), 5, vec![]);
+    store.raw_scan_range_ok(b"k4".to_vec(), b"k5".to_vec(), 5, vec![]);
+    store.raw_scan_range_ok(b"".to_vec(), b"k9".to_vec(), 1, vec![(b"k1", b"v1")]);
+}
+
 #[test]
 fn test_txn_store_lock_primary() {
     let store = AssertionStorage::default();